@@ -0,0 +1,150 @@
+use crate::paths::normalize;
+use crate::settings;
+use crate::shell::{join_args, split_args};
+use crate::CompileCommand;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where Soong writes the database of a build (`SOONG_GEN_COMPDB=1 m`), below its OUT_DIR
+const COMPDB_DIR: &str = "soong/development/ide/compdb";
+
+/// Directory of generated sources and intermediates the database's paths are relative to
+const OUT: &str = "out";
+
+/// Output directories of a checkout (`out`, and `out-*` or `out_*` kept per lunch target), or
+/// the directory itself if it is one
+fn out_dirs(root: &Path) -> Vec<PathBuf> {
+    if root.join("soong").is_dir() {
+        return vec![root.to_path_buf()];
+    }
+    let mut dirs: Vec<PathBuf> = fs::read_dir(root)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|dir| {
+            let name = dir.file_name().unwrap_or_default().to_string_lossy();
+            name.strip_prefix(OUT).is_some_and(|rest| rest.is_empty() || rest.starts_with(['-', '_']))
+                && dir.join("soong").is_dir()
+        })
+        .collect();
+    dirs.sort();
+    dirs
+}
+
+/// The product an output directory was built for, as lunch selected it
+fn product(out_dir: &Path) -> Option<String> {
+    let soong = out_dir.join("soong");
+    let variables: Option<serde_json::Value> =
+        fs::read_to_string(soong.join("soong.variables")).ok().and_then(|text| serde_json::from_str(&text).ok());
+    let named = variables.and_then(|variables| {
+        ["DeviceProduct", "DeviceName"]
+            .iter()
+            .find_map(|key| variables.get(key).and_then(|value| value.as_str()).map(str::to_string))
+    });
+    named.or_else(|| {
+        // Newer releases name the file after the product
+        fs::read_dir(&soong).ok()?.flatten().find_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            Some(name.strip_prefix("soong.")?.strip_suffix(".variables")?.to_string())
+        })
+    })
+}
+
+/// Index of the selected lunch target (`product-release-variant`, or just the product) an output
+/// directory was built for
+fn selected(out_dir: &Path, targets: &[String]) -> Option<usize> {
+    let product = product(out_dir)?;
+    targets
+        .iter()
+        .position(|target| target.split('-').next() == Some(product.as_str()))
+}
+
+/// The output directory holding a database Soong wrote
+fn out_dir(database: &Path) -> Option<&Path> {
+    let compdb = database.parent()?;
+    compdb.ends_with(COMPDB_DIR).then(|| compdb.ancestors().nth(Path::new(COMPDB_DIR).components().count()))?
+}
+
+/// Whether a database was written by Soong
+pub fn is_compdb(path: &Path) -> bool {
+    out_dir(path).is_some()
+}
+
+/// Databases of a checkout's builds for the state's --lunch-targets (all of them, where it names
+/// none), given the checkout or an output directory
+pub fn discover(root: &Path, filename: &str) -> Vec<PathBuf> {
+    let targets = &settings::current().lunch_targets;
+    let databases: Vec<PathBuf> = out_dirs(root)
+        .into_iter()
+        .filter(|dir| targets.is_empty() || selected(dir, targets).is_some())
+        .map(|dir| dir.join(COMPDB_DIR).join(filename))
+        .filter(|database| database.is_file())
+        .collect();
+    if databases.is_empty() {
+        warn!(
+            "Warning: No Soong database under {} (build with SOONG_GEN_COMPDB=1 to write {}/{})",
+            root.display(),
+            COMPDB_DIR,
+            filename
+        );
+    }
+    databases
+}
+
+/// Priority of a build's entries: the earlier its lunch target in --lunch-targets, the higher
+pub fn priority(database: &Path) -> i32 {
+    let targets = &settings::current().lunch_targets;
+    let Some(out_dir) = out_dir(database).filter(|_| !targets.is_empty()) else {
+        return 0;
+    };
+    selected(out_dir, targets).map_or(0, |index| (targets.len() - index) as i32)
+}
+
+/// Resolve the `out/` paths of a Soong database's entries (generated sources, and include
+/// directories of intermediates) against the output directory actually holding it, so they
+/// stay right when OUT_DIR was moved or renamed, e.g. to keep a build per lunch target
+pub fn normalize_entries(database: &Path, commands: Vec<CompileCommand>) -> Vec<CompileCommand> {
+    let Some(out_dir) = out_dir(database).and_then(|dir| std::path::absolute(dir).ok()) else {
+        return commands;
+    };
+    commands.into_iter().map(|command| normalize_entry(command, &out_dir)).collect()
+}
+
+fn normalize_entry(mut command: CompileCommand, out_dir: &Path) -> CompileCommand {
+    let generated = |path: &str| {
+        let rest = path.strip_prefix(OUT)?.strip_prefix(['/', '\\'])?;
+        Some(normalize(&out_dir.join(rest)).display().to_string())
+    };
+    let original = command.file.clone();
+    if let Some(file) = generated(&command.file) {
+        command.file = file;
+    }
+    let mut args = split_args(&command.command);
+    let mut changed = false;
+    for arg in args.iter_mut() {
+        if *arg == original && command.file != original {
+            *arg = command.file.clone();
+            changed = true;
+            continue;
+        }
+        // Joined (`-Iout/soong/...`) or separate arguments starting with the output directory
+        let Some(start) = arg.find(OUT).filter(|start| {
+            let option = &arg[..*start];
+            option.is_empty() || (option.starts_with('-') && !option.contains(['/', '\\', '=']))
+        }) else {
+            continue;
+        };
+        if let Some(path) = generated(&arg[start..]) {
+            *arg = format!("{}{}", &arg[..start], path);
+            changed = true;
+        }
+    }
+    if changed {
+        command.command = join_args(&args);
+    }
+    if let Some(output) = command.output.as_deref().and_then(generated) {
+        command.output = Some(output);
+    }
+    command
+}
@@ -0,0 +1,69 @@
+use crate::parse;
+use crate::CompileCommand;
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Whether a path names an archive whose databases are merged, e.g. a CI build artifact
+pub fn is_archive(path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy().to_ascii_lowercase();
+    [".tar.gz", ".tgz", ".tar", ".zip"].iter().any(|extension| name.ends_with(extension))
+}
+
+/// Read the entries of every database named `input_file` inside an archive
+pub fn read(path: &Path, input_file: &str) -> Result<Vec<CompileCommand>> {
+    let file = File::open(path)?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy().to_ascii_lowercase();
+    let databases = if name.ends_with(".zip") {
+        read_zip(file, input_file)?
+    } else if name.ends_with(".tar") {
+        read_tar(file, input_file)?
+    } else {
+        read_tar(GzDecoder::new(file), input_file)?
+    };
+
+    let mut commands = Vec::new();
+    for (member, content) in databases {
+        let parsed = parse::parse(&content).with_context(|| format!("{} in the archive", member))?;
+        info!("Found {} in {} ({} entries)", member, path.display(), parsed.len());
+        commands.extend(parsed);
+    }
+    Ok(commands)
+}
+
+/// Whether an archive member is a database, judging by its file name
+fn is_database(member: &str, input_file: &str) -> bool {
+    Path::new(member).file_name().is_some_and(|name| name == input_file)
+}
+
+fn read_tar(reader: impl Read, input_file: &str) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut archive = tar::Archive::new(reader);
+    let mut databases = Vec::new();
+    for member in archive.entries()? {
+        let mut member = member?;
+        let name = member.path()?.display().to_string();
+        if member.header().entry_type().is_file() && is_database(&name, input_file) {
+            let mut content = Vec::new();
+            member.read_to_end(&mut content)?;
+            databases.push((name, content));
+        }
+    }
+    Ok(databases)
+}
+
+fn read_zip(file: File, input_file: &str) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut archive = zip::ZipArchive::new(file)?;
+    let mut databases = Vec::new();
+    for index in 0..archive.len() {
+        let mut member = archive.by_index(index)?;
+        let name = member.name()?.to_string();
+        if member.is_file() && is_database(&name, input_file) {
+            let mut content = Vec::new();
+            member.read_to_end(&mut content)?;
+            databases.push((name, content));
+        }
+    }
+    Ok(databases)
+}
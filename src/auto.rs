@@ -0,0 +1,55 @@
+use std::path::{Path, PathBuf};
+
+/// How deep below the root build directories are looked for
+const MAX_DEPTH: usize = 6;
+
+/// Directories never holding build trees worth merging
+pub const SKIPPED_DIRS: &[&str] = &[".git", ".hg", ".svn", "node_modules", ".cache", "CMakeFiles"];
+
+/// A directory that looks like a build tree, and why
+pub struct Candidate {
+    pub dir: PathBuf,
+    pub reason: &'static str,
+}
+
+/// Find the directories below `root` that look like build trees: configured CMake trees, Ninja
+/// and Meson build directories, PlatformIO projects, AOSP out directories, and Make trees with a database. Directories inside a found build
+/// tree aren't searched further.
+pub fn find_build_dirs(root: &Path, input_file: &str) -> Vec<Candidate> {
+    let mut found = Vec::new();
+    let mut walker = walkdir::WalkDir::new(root)
+        .max_depth(MAX_DEPTH)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| entry.depth() == 0 || !SKIPPED_DIRS.iter().any(|skipped| entry.file_name() == *skipped));
+    while let Some(entry) = walker.next() {
+        let Ok(entry) = entry else {
+            continue;
+        };
+        if !entry.file_type().is_dir() {
+            continue;
+        }
+        let dir = entry.path();
+        let has = |name: &str| dir.join(name).exists();
+        let reason = if has(".pio/build") {
+            "PlatformIO project"
+        } else if has("soong/development/ide/compdb") {
+            "AOSP out directory"
+        } else if has(crate::cmake::CACHE_FILE) {
+            "CMake build tree"
+        } else if has("meson-private") {
+            "Meson build directory"
+        } else if has("build.ninja") {
+            "Ninja build directory"
+        } else if has("Makefile") && has(input_file) {
+            "Make build tree with a database"
+        } else if entry.depth() > 0 && has(input_file) {
+            "database"
+        } else {
+            continue;
+        };
+        found.push(Candidate { dir: dir.to_path_buf(), reason });
+        walker.skip_current_dir();
+    }
+    found
+}
@@ -0,0 +1,3 @@
+fn main() {
+    compile_commands_merger::wrap();
+}
@@ -0,0 +1,84 @@
+use crate::paths::normalize;
+use crate::shell::{join_args, split_args};
+use crate::CompileCommand;
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+
+/// BXL script of the Buck2 prelude that writes a compilation database for C++ targets
+const COMPILATION_DATABASE_BXL: &str = "prelude//cxx/tools/compilation_database.bxl:generate";
+
+/// Directory under the project root holding Buck2's outputs
+const BUCK_OUT: &str = "buck-out";
+
+/// Have Buck2 write the compilation database of the given targets, returning its path
+pub fn generate(targets: &[String]) -> Result<PathBuf> {
+    info!("Generating the compilation database of {} with buck2 bxl", targets.join(" "));
+    let output = Command::new("buck2")
+        .args(["bxl", COMPILATION_DATABASE_BXL, "--", "--targets"])
+        .args(targets)
+        .output()
+        .context("Failed to run buck2")?;
+    if !output.status.success() {
+        bail!("buck2 bxl failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    // The script prints where it wrote the database, relative to the project root
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let Some(printed) = stdout.lines().map(str::trim).rfind(|line| !line.is_empty()) else {
+        bail!("buck2 bxl printed no database path");
+    };
+    let path = Path::new(printed);
+    if path.is_absolute() {
+        return Ok(path.to_path_buf());
+    }
+    let root = Command::new("buck2").args(["root", "--kind", "project"]).output().context("Failed to run buck2 root")?;
+    Ok(Path::new(String::from_utf8_lossy(&root.stdout).trim()).join(path))
+}
+
+/// Whether a database was written by Buck2, living in its output directory
+pub fn is_buck_out(path: &Path) -> bool {
+    path.components().any(|component| component == Component::Normal(BUCK_OUT.as_ref()))
+}
+
+/// Make entries of a Buck2 database independent of buck-out's layout: sources that are
+/// symlinks into the project resolve to the real file, and include directories under
+/// buck-out become absolute so they work wherever the merged database is
+pub fn normalize_entries(commands: Vec<CompileCommand>) -> Vec<CompileCommand> {
+    commands.into_iter().map(normalize_entry).collect()
+}
+
+fn normalize_entry(mut command: CompileCommand) -> CompileCommand {
+    let directory = PathBuf::from(&command.directory);
+    let original = command.file.clone();
+    if is_buck_out(Path::new(&command.file)) {
+        if let Ok(real) = fs::canonicalize(directory.join(&command.file)) {
+            if !is_buck_out(&real) {
+                command.file = real.display().to_string();
+            }
+        }
+    }
+    let mut args = split_args(&command.command);
+    let mut changed = false;
+    for arg in args.iter_mut() {
+        if *arg == original && command.file != original {
+            *arg = command.file.clone(); // The source, as resolved above
+            changed = true;
+            continue;
+        }
+        // Joined (`-Ibuck-out/...`) or separate arguments starting with the output directory
+        let Some(start) = arg.find(BUCK_OUT).filter(|start| {
+            let option = &arg[..*start];
+            option.is_empty() || (option.starts_with('-') && !option.contains(['/', '\\', '=']))
+        }) else {
+            continue;
+        };
+        let absolute = normalize(&directory.join(&arg[start..])).display().to_string();
+        *arg = format!("{}{}", &arg[..start], absolute);
+        changed = true;
+    }
+    if changed {
+        command.command = join_args(&args);
+    }
+    command
+}
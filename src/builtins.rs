@@ -0,0 +1,132 @@
+use crate::compiler::{compiler_index, executable_name};
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+/// Options that change where a compiler looks for its own headers, passed on when probing it,
+/// and whether they may take their value in the next argument
+const PROBED_OPTIONS: &[(&str, bool)] = &[
+    ("--target=", false),
+    ("-target", true),
+    ("--sysroot", true),
+    ("-isysroot", true),
+    ("--gcc-toolchain=", false),
+    ("-stdlib=", false),
+    ("-m32", false),
+    ("-m64", false),
+    ("-nostdinc", false),
+];
+
+/// Options naming include directories, which the builtin ones aren't appended again after
+const INCLUDE_OPTIONS: &[&str] = &["-isystem", "-idirafter", "-iquote", "-I"];
+
+/// Appends the include directories compilers search by default as `-isystem` options, so tools
+/// that can't run the original (cross-)compiler still find its headers
+#[derive(Default)]
+pub struct BuiltinIncludes {
+    probed: Mutex<HashMap<Vec<String>, Vec<String>>>, // Directories per probe key
+}
+
+impl BuiltinIncludes {
+    /// Append the builtin include directories of a command's compiler that it doesn't already
+    /// name, returning whether the arguments changed
+    pub fn apply(&self, args: &mut Vec<String>, file: &str, directory: &str) -> bool {
+        let Some(index) = compiler_index(args) else {
+            return false;
+        };
+        let compiler = executable_name(&args[index]).to_ascii_lowercase();
+        if matches!(compiler.as_str(), "cl" | "clang-cl") {
+            return false; // MSVC-style compilers take their include paths from the environment
+        }
+        let key = probe_key(&args[index..], &compiler, file, directory);
+        let directories = {
+            let mut probed = self.probed.lock().unwrap_or_else(|e| e.into_inner());
+            probed.entry(key).or_insert_with_key(|key| probe(&key[1..], directory)).clone()
+        };
+        let named = named_includes(args);
+        let missing: Vec<String> = directories.into_iter().filter(|dir| !named.contains(&dir.as_str())).collect();
+        for dir in &missing {
+            args.push("-isystem".to_string());
+            args.push(dir.clone());
+        }
+        !missing.is_empty()
+    }
+}
+
+/// Where a relative compiler is run from, the compiler, the language and the options affecting the
+/// compiler's search path, which together decide the directories it searches
+fn probe_key(args: &[String], compiler: &str, file: &str, directory: &str) -> Vec<String> {
+    let language = args
+        .windows(2)
+        .find(|pair| pair[0] == "-x")
+        .map(|pair| pair[1].clone())
+        .unwrap_or_else(|| {
+            let cpp = compiler.contains("++")
+                || compiler.ends_with("cpp")
+                || Path::new(file)
+                    .extension()
+                    .is_some_and(|extension| ["cc", "cpp", "cxx", "c++", "mm", "C"].iter().any(|cpp| extension == *cpp));
+            if cpp { "c++" } else { "c" }.to_string()
+        });
+    let relative = Path::new(&args[0]).is_relative() && args[0].contains(['/', '\\']);
+    let place = if relative { directory } else { "" };
+    let mut key = vec![place.to_string(), args[0].clone(), language];
+    let mut options = args[1..].iter();
+    while let Some(arg) = options.next() {
+        if let Some((option, separate)) = PROBED_OPTIONS.iter().find(|(option, _)| arg.starts_with(option)) {
+            key.push(arg.clone());
+            if *separate && arg == option {
+                key.extend(options.next().cloned());
+            }
+        }
+    }
+    key
+}
+
+/// The include directories a command names, joined to their option or following it
+fn named_includes(args: &[String]) -> Vec<&str> {
+    let mut named = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if let Some(option) = INCLUDE_OPTIONS.iter().find(|option| arg.starts_with(*option)) {
+            match &arg[option.len()..] {
+                "" => named.extend(args.next().map(String::as_str)),
+                dir => named.push(dir),
+            }
+        }
+    }
+    named
+}
+
+/// Run the compiler on empty input with -v, reading the include search list it prints
+fn probe(key: &[String], directory: &str) -> Vec<String> {
+    let (compiler, language, options) = (&key[0], &key[1], &key[2..]);
+    let mut command = Command::new(compiler);
+    command.args(options).args(["-E", "-v", "-x", language, "-"]).stdin(Stdio::null()).stdout(Stdio::null());
+    if Path::new(directory).is_dir() {
+        command.current_dir(directory); // Relative compilers and sysroots
+    }
+    let output = match command.output() {
+        Ok(output) => output,
+        Err(e) => {
+            warn!("Warning: Failed to run {} for its builtin include directories: {}", compiler, e);
+            return Vec::new();
+        }
+    };
+    let printed = String::from_utf8_lossy(&output.stderr);
+    let directories: Vec<String> = printed
+        .lines()
+        .skip_while(|line| !line.starts_with("#include <...> search starts here:"))
+        .skip(1)
+        .take_while(|line| !line.starts_with("End of search list."))
+        .filter(|line| !line.ends_with("(framework directory)"))
+        .map(|line| line.trim().to_string())
+        .collect();
+    if directories.is_empty() {
+        warn!("Warning: {} printed no builtin include directories", compiler);
+    } else {
+        info!("Builtin include directories of {} ({}): {}", compiler, language, directories.join(", "));
+    }
+    directories
+}
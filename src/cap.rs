@@ -0,0 +1,70 @@
+use crate::Entry;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::cell::Cell;
+
+/// What to do when the merged entries exceed --max-entries
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CapAction {
+    /// Write them all, warning about it
+    Warn,
+    /// Write only the highest-priority, most recently ingested ones
+    Truncate,
+    /// Leave the output as it is, failing the write
+    Fail,
+}
+
+/// Keeps the output from growing past a number of entries, which editors struggle to load
+pub struct EntryCap {
+    max: usize,
+    action: CapAction,
+    reported: Cell<Option<usize>>, // Entry count last warned about, so each overflow is reported once
+}
+
+impl EntryCap {
+    pub fn new(max: usize, action: CapAction) -> Self {
+        EntryCap {
+            max,
+            action,
+            reported: Cell::new(None),
+        }
+    }
+
+    /// Apply the cap to the entries about to be written, or explain why they can't be
+    pub fn apply<'a>(&self, mut entries: Vec<&'a Entry>) -> std::io::Result<Vec<&'a Entry>> {
+        let count = entries.len();
+        if count <= self.max {
+            self.reported.set(None);
+            return Ok(entries);
+        }
+        if self.action == CapAction::Fail {
+            return Err(std::io::Error::other(format!(
+                "{} merged entries exceed --max-entries {}",
+                count, self.max
+            )));
+        }
+        let first = self.reported.replace(Some(count)) != Some(count);
+        if self.action == CapAction::Warn {
+            if first {
+                warn!("Warning: {} merged entries exceed --max-entries {}", count, self.max);
+            }
+            return Ok(entries);
+        }
+        entries.sort_by(|a, b| {
+            (b.priority, b.ingested_at)
+                .cmp(&(a.priority, a.ingested_at))
+                .then_with(|| a.command.file.cmp(&b.command.file))
+        });
+        entries.truncate(self.max);
+        if first {
+            warn!(
+                "Warning: {} merged entries exceed --max-entries {}, leaving out the {} of lowest priority",
+                count,
+                self.max,
+                count - self.max
+            );
+        }
+        Ok(entries)
+    }
+}
@@ -0,0 +1,94 @@
+use crate::compiler::source_and_output;
+use crate::settings;
+use crate::shell::{join_args, split_args};
+use crate::CompileCommand;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Whether a file is one of the state's --cargo-log logs, read as a cargo build log rather than
+/// as a database
+pub fn is_log(path: &Path) -> bool {
+    let logs = &settings::current().cargo_logs;
+    !logs.is_empty() && logs.contains(&std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()))
+}
+
+/// Reconstruct entries from the compiler invocations cc-rs printed in a `cargo build -vv` log
+pub fn read(path: &Path) -> Result<Vec<CompileCommand>> {
+    let log = fs::read_to_string(path)?;
+    let fallback = std::path::absolute(path)?.parent().unwrap_or(Path::new("")).to_path_buf();
+    let mut manifest_dirs: HashMap<String, String> = HashMap::new(); // `name version` of each package
+    let mut commands = Vec::new();
+    for line in log.lines() {
+        let line = line.trim_start();
+        // Build script output is prefixed with its package, e.g. `[ffi 0.1.0] running: "cc" ...`
+        let (package, message) = match line.strip_prefix('[').and_then(|rest| rest.split_once("] ")) {
+            Some((package, message)) => (Some(package), message),
+            None => (None, line),
+        };
+        if let Some(invocation) = message.strip_prefix("Running `").and_then(|rest| rest.strip_suffix('`')) {
+            record_manifest_dir(invocation, &mut manifest_dirs);
+        } else if let Some(invocation) = message.strip_prefix("running: ") {
+            let directory = package
+                .and_then(|package| manifest_dirs.get(package))
+                .map_or_else(|| fallback.display().to_string(), String::clone);
+            commands.extend(entry(&split_args(invocation), directory));
+        }
+    }
+    info!("Found {} cc-rs compiler invocations in {}", commands.len(), path.display());
+    Ok(commands)
+}
+
+/// Remember the package directory of a build script cargo runs, from the environment it sets
+fn record_manifest_dir(invocation: &str, manifest_dirs: &mut HashMap<String, String>) {
+    let mut variables = HashMap::new();
+    for arg in split_args(invocation) {
+        let Some((name, value)) = arg.split_once('=') else {
+            break; // The program follows the assignments
+        };
+        if !name.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_') {
+            break;
+        }
+        variables.insert(name.to_string(), value.to_string());
+    }
+    if let (Some(name), Some(version), Some(dir)) = (
+        variables.get("CARGO_PKG_NAME"),
+        variables.get("CARGO_PKG_VERSION"),
+        variables.get("CARGO_MANIFEST_DIR"),
+    ) {
+        manifest_dirs.insert(format!("{} {}", name, version), dir.clone());
+    }
+}
+
+/// The entry of one compiler invocation, if it compiles a single source
+fn entry(args: &[String], mut directory: String) -> Option<CompileCommand> {
+    let mut args = args;
+    // Newer cc-rs versions print `cd DIR && env -u VAR ... "cc" ...`
+    if let [cd, dir, and, rest @ ..] = args {
+        if cd == "cd" && and == "&&" {
+            directory = dir.clone();
+            args = rest;
+        }
+    }
+    if args.first().is_some_and(|arg| arg == "env") {
+        let mut rest = &args[1..];
+        loop {
+            match rest {
+                [unset, _, tail @ ..] if unset == "-u" => rest = tail,
+                [assignment, tail @ ..] if assignment.contains('=') && !assignment.starts_with('-') => rest = tail,
+                _ => break,
+            }
+        }
+        args = rest;
+    }
+    let (file, output) = source_and_output(args);
+    Some(CompileCommand {
+        directory,
+        command: join_args(args),
+        file: file?,
+        output,
+        extra: Default::default(),
+        original_compiler: None,
+    })
+}
@@ -0,0 +1,71 @@
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Makes clangd pick up a rewritten database without restarting the editor
+pub struct ClangdRefresh {
+    invalidate_index: bool,
+}
+
+impl ClangdRefresh {
+    pub fn new(invalidate_index: bool) -> Self {
+        ClangdRefresh { invalidate_index }
+    }
+
+    /// Bump the database's modification time and drop index shards of changed files
+    pub fn refresh(&self, output_path: &Path, changed: &[String]) {
+        if let Err(e) = touch(output_path) {
+            warn!("Warning: Failed to touch {}: {}", output_path.display(), e);
+        }
+
+        if self.invalidate_index {
+            let index_dir = output_path
+                .parent()
+                .unwrap_or(Path::new(""))
+                .join(".cache/clangd/index");
+            let removed = invalidate_shards(&index_dir, changed);
+            if removed > 0 {
+                info!("Invalidated {} clangd index shards", removed);
+            }
+        }
+    }
+}
+
+/// Set a file's modification time to now so clangd notices the new content
+fn touch(path: &Path) -> std::io::Result<()> {
+    fs::File::options()
+        .write(true)
+        .open(path)?
+        .set_modified(SystemTime::now())
+}
+
+/// Remove `<name>.<hash>.idx` shards for the given files, returning how many were removed.
+/// Shards are matched by file name since the hash is of clangd's internal path form.
+fn invalidate_shards(index_dir: &Path, files: &[String]) -> usize {
+    if files.is_empty() {
+        return 0;
+    }
+    let Ok(shards) = fs::read_dir(index_dir) else {
+        return 0;
+    };
+    let names: Vec<String> = files
+        .iter()
+        .filter_map(|file| Path::new(file.as_str()).file_name())
+        .map(|name| format!("{}.", name.to_string_lossy()))
+        .collect();
+
+    let mut removed = 0;
+    for shard in shards.flatten() {
+        let shard_name = shard.file_name().to_string_lossy().into_owned();
+        let matches = shard_name.ends_with(".idx")
+            && names.iter().any(|name| {
+                shard_name
+                    .strip_prefix(name.as_str())
+                    .is_some_and(|hash| !hash.trim_end_matches(".idx").contains('.'))
+            });
+        if matches && fs::remove_file(shard.path()).is_ok() {
+            removed += 1;
+        }
+    }
+    removed
+}
@@ -0,0 +1,61 @@
+use anyhow::{bail, Result};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// Where the merger takes the time entries are ingested at, and entries expire by
+pub trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+/// The system's wall clock
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock that only moves when told to, for reproducible tests and replays
+pub struct ManualClock {
+    now: Mutex<SystemTime>,
+}
+
+impl ManualClock {
+    pub fn new(start: SystemTime) -> Self {
+        ManualClock { now: Mutex::new(start) }
+    }
+
+    /// Move the clock forward
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner()) += by;
+    }
+
+    /// Move the clock to a time, which may be earlier than its current one
+    pub fn set(&self, now: SystemTime) {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner()) = now;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Parse an age such as `30d`, `12h`, `90m`, `45s` or `2w`; a bare number is in seconds
+pub fn parse_age(age: &str) -> Result<Duration> {
+    let (number, unit) = age.trim().split_at(age.trim().trim_end_matches(|c: char| c.is_ascii_alphabetic()).len());
+    let Ok(number) = number.parse::<u64>() else {
+        bail!("Invalid age '{}', expected a number with an optional unit (s, m, h, d, w)", age);
+    };
+    let seconds = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => bail!("Invalid unit '{}' in age '{}', expected s, m, h, d or w", unit, age),
+    };
+    Ok(Duration::from_secs(number.saturating_mul(seconds)))
+}
@@ -0,0 +1,333 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// File marking a configured CMake build tree
+pub const CACHE_FILE: &str = "CMakeCache.txt";
+
+/// Preset files read from a workspace, the user's presets implicitly including the project's
+const PRESET_FILES: &[&str] = &["CMakePresets.json", "CMakeUserPresets.json"];
+
+/// How deep below a watched directory build trees are looked for
+const MAX_DEPTH: usize = 5;
+
+/// Generators that never write compile_commands.json
+const NON_EXPORTING_GENERATORS: &[&str] = &["Visual Studio", "Xcode", "Green Hills MULTI"];
+
+/// Settings of a build tree that decide what its database contains
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct CacheSettings {
+    generator: Option<String>,
+    source_dir: Option<String>,
+    export_compile_commands: Option<bool>,
+}
+
+impl CacheSettings {
+    fn read(path: &Path) -> Option<Self> {
+        let content = fs::read_to_string(path).ok()?;
+        let mut settings = CacheSettings::default();
+        for line in content.lines() {
+            // Entries look like `NAME:TYPE=VALUE`
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let name = key.split(':').next().unwrap_or(key);
+            match name {
+                "CMAKE_GENERATOR" => settings.generator = Some(value.to_string()),
+                "CMAKE_HOME_DIRECTORY" => settings.source_dir = Some(value.to_string()),
+                "CMAKE_EXPORT_COMPILE_COMMANDS" => {
+                    settings.export_compile_commands =
+                        Some(matches!(value.to_ascii_uppercase().as_str(), "ON" | "1" | "TRUE" | "YES" | "Y"))
+                }
+                _ => {}
+            }
+        }
+        Some(settings)
+    }
+
+    /// Why a build tree with these settings won't have a database, if it won't
+    fn missing_database_reason(&self) -> Option<String> {
+        if let Some(generator) = &self.generator {
+            if NON_EXPORTING_GENERATORS.iter().any(|prefix| generator.starts_with(prefix)) {
+                return Some(format!("the {} generator does not write compile_commands.json", generator));
+            }
+        }
+        (self.export_compile_commands == Some(false)).then(|| "CMAKE_EXPORT_COMPILE_COMMANDS is OFF".to_string())
+    }
+}
+
+/// What a change of a build tree's cache means for the merged entries
+pub enum Reconfiguration {
+    /// Nothing that affects its database changed
+    Unchanged,
+    /// Entries of the tree are `stale` if they no longer apply; its database is given if it
+    /// was regenerated already, otherwise its regeneration is picked up as a change later
+    Reconfigured { tree: PathBuf, database: Option<PathBuf>, stale: bool },
+}
+
+/// Configured CMake build trees under the watched directories, keyed by their directory
+#[derive(Default)]
+pub struct BuildTrees {
+    trees: RefCell<HashMap<PathBuf, CacheSettings>>,
+}
+
+impl BuildTrees {
+    /// Databases of the build trees under a directory, or `None` if it holds no build tree
+    pub fn discover(&self, root: &Path, filename: &str) -> Option<Vec<PathBuf>> {
+        let mut found = Vec::new();
+        let mut walker = walkdir::WalkDir::new(root).max_depth(MAX_DEPTH).into_iter();
+        while let Some(entry) = walker.next() {
+            let Ok(entry) = entry else {
+                continue;
+            };
+            if !entry.file_type().is_dir() || !entry.path().join(CACHE_FILE).is_file() {
+                continue;
+            }
+            walker.skip_current_dir(); // Nested trees (e.g. ExternalProject builds) belong to this one
+            found.push(std::path::absolute(entry.path()).unwrap_or_else(|_| entry.path().to_path_buf()));
+        }
+        if found.is_empty() {
+            return None;
+        }
+
+        let mut trees = self.trees.borrow_mut();
+        let mut databases = Vec::new();
+        for tree in found {
+            let settings = CacheSettings::read(&tree.join(CACHE_FILE)).unwrap_or_default();
+            let database = tree.join(filename);
+            if database.is_file() {
+                databases.push(database);
+            } else if let Some(reason) = settings.missing_database_reason() {
+                warn!("Warning: Build tree {} has no {}: {}", tree.display(), filename, reason);
+            }
+            trees.insert(tree, settings);
+        }
+        Some(databases)
+    }
+
+    /// Handle a change of a build tree's cache
+    pub fn reconfigured(&self, cache: &Path, filename: &str) -> Reconfiguration {
+        let cache = std::path::absolute(cache).unwrap_or_else(|_| cache.to_path_buf());
+        let Some(tree) = cache.parent() else {
+            return Reconfiguration::Unchanged;
+        };
+        let Some(settings) = CacheSettings::read(&cache) else {
+            return Reconfiguration::Unchanged; // Deleted, or being rewritten
+        };
+        let previous = self.trees.borrow_mut().insert(tree.to_path_buf(), settings.clone());
+        if previous.as_ref() == Some(&settings) {
+            return Reconfiguration::Unchanged;
+        }
+        if let Some(reason) = settings.missing_database_reason() {
+            warn!("Warning: Build tree {} will have no {}: {}", tree.display(), filename, reason);
+        }
+        let stale = match &previous {
+            Some(previous) => {
+                if previous.generator != settings.generator {
+                    info!(
+                        "Build tree {} switched generator to {}",
+                        tree.display(),
+                        settings.generator.as_deref().unwrap_or("(none)")
+                    );
+                }
+                if previous.source_dir != settings.source_dir {
+                    info!(
+                        "Build tree {} now builds {}",
+                        tree.display(),
+                        settings.source_dir.as_deref().unwrap_or("(none)")
+                    );
+                }
+                previous.source_dir != settings.source_dir || previous.generator != settings.generator
+            }
+            None => {
+                info!("New build tree: {}", tree.display());
+                false
+            }
+        };
+        let database = tree.join(filename);
+        let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+        let regenerated = modified(&database).is_some_and(|database| modified(&cache).is_some_and(|cache| database >= cache));
+        Reconfiguration::Reconfigured {
+            tree: tree.to_path_buf(),
+            database: regenerated.then_some(database),
+            stale,
+        }
+    }
+}
+
+/// A configure preset as written, its fields falling back to those of the presets it inherits
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ConfigurePreset {
+    name: String,
+    #[serde(default)]
+    hidden: bool,
+    #[serde(default)]
+    inherits: Inherits,
+    binary_dir: Option<String>,
+    generator: Option<String>,
+    #[serde(default)]
+    environment: HashMap<String, Option<String>>,
+    #[serde(skip)]
+    file_dir: PathBuf, // Directory of the file defining the preset, for ${fileDir}
+}
+
+#[derive(Deserialize, Default)]
+#[serde(untagged)]
+enum Inherits {
+    #[default]
+    None,
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Inherits {
+    fn names(&self) -> &[String] {
+        match self {
+            Inherits::None => &[],
+            Inherits::One(name) => std::slice::from_ref(name),
+            Inherits::Many(names) => names,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PresetFile {
+    #[serde(default)]
+    include: Vec<String>,
+    #[serde(default)]
+    configure_presets: Vec<ConfigurePreset>,
+}
+
+/// The build directories of a workspace's visible configure presets, by preset name, as read from
+/// its CMakePresets.json and CMakeUserPresets.json and the files they include
+pub fn preset_build_dirs(workspace: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let workspace = std::path::absolute(workspace)?;
+    let mut presets = Vec::new();
+    let mut read = Vec::new();
+    for name in PRESET_FILES {
+        let path = workspace.join(name);
+        if path.is_file() {
+            read_presets(&path, &mut presets, &mut read)?;
+        }
+    }
+    if read.is_empty() {
+        anyhow::bail!("No {} in {}", PRESET_FILES.join(" or "), workspace.display());
+    }
+    let by_name: HashMap<&str, &ConfigurePreset> = presets.iter().map(|preset| (preset.name.as_str(), preset)).collect();
+    let mut dirs = Vec::new();
+    for preset in presets.iter().filter(|preset| !preset.hidden) {
+        let Some(binary_dir) = inherited(preset, &by_name, &|preset| preset.binary_dir.clone()) else {
+            info!("Preset {} sets no binaryDir, skipping it", preset.name);
+            continue;
+        };
+        let dir = expand(&binary_dir, preset, &by_name, &workspace);
+        dirs.push((preset.name.clone(), crate::paths::normalize(&workspace.join(dir))));
+    }
+    Ok(dirs)
+}
+
+/// Read a preset file and those it includes, each once
+fn read_presets(path: &Path, presets: &mut Vec<ConfigurePreset>, read: &mut Vec<PathBuf>) -> Result<()> {
+    let path = crate::paths::normalize(path);
+    if read.contains(&path) {
+        return Ok(());
+    }
+    read.push(path.clone());
+    let text = fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let file: PresetFile = serde_json::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))?;
+    let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+    for include in &file.include {
+        read_presets(&dir.join(include), presets, read)?;
+    }
+    presets.extend(file.configure_presets.into_iter().map(|preset| ConfigurePreset { file_dir: dir.clone(), ..preset }));
+    Ok(())
+}
+
+/// A field of a preset, or of the first preset it inherits (depth first) that sets it
+fn inherited<T>(
+    preset: &ConfigurePreset,
+    by_name: &HashMap<&str, &ConfigurePreset>,
+    field: &dyn Fn(&ConfigurePreset) -> Option<T>,
+) -> Option<T> {
+    fn find<T>(
+        preset: &ConfigurePreset,
+        by_name: &HashMap<&str, &ConfigurePreset>,
+        field: &dyn Fn(&ConfigurePreset) -> Option<T>,
+        depth: usize,
+    ) -> Option<T> {
+        if depth > by_name.len() {
+            return None; // Inheritance cycle
+        }
+        field(preset).or_else(|| {
+            preset
+                .inherits
+                .names()
+                .iter()
+                .filter_map(|name| by_name.get(name.as_str()))
+                .find_map(|parent| find(parent, by_name, field, depth + 1))
+        })
+    }
+    find(preset, by_name, field, 0)
+}
+
+/// Expand the macros CMake allows in a binaryDir; unknown ones (e.g. `$vendor{...}`) are left as written
+fn expand(value: &str, preset: &ConfigurePreset, by_name: &HashMap<&str, &ConfigurePreset>, workspace: &Path) -> String {
+    let mut expanded = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let candidate = &rest[start..];
+        let macro_end = candidate.find('}');
+        let replacement = macro_end.and_then(|end| {
+            let (namespace, name) = candidate[1..end].split_once('{')?;
+            let value = match namespace {
+                "" => match name {
+                    "sourceDir" => Some(workspace.display().to_string()),
+                    "sourceParentDir" => Some(workspace.parent().unwrap_or(workspace).display().to_string()),
+                    "sourceDirName" => Some(workspace.file_name().unwrap_or_default().to_string_lossy().into_owned()),
+                    "presetName" => Some(preset.name.clone()),
+                    "generator" => inherited(preset, by_name, &|preset| preset.generator.clone()),
+                    "hostSystemName" => Some(host_system_name().to_string()),
+                    "fileDir" => Some(preset.file_dir.display().to_string()),
+                    "dollar" => Some("$".to_string()),
+                    "pathListSep" => Some(if cfg!(windows) { ";" } else { ":" }.to_string()),
+                    _ => None,
+                },
+                "env" => inherited(preset, by_name, &|preset| preset.environment.get(name).cloned().flatten())
+                    .or_else(|| std::env::var(name).ok())
+                    .or_else(|| Some(String::new())),
+                "penv" => Some(std::env::var(name).unwrap_or_default()),
+                _ => None,
+            };
+            value.map(|value| (value, end + 1))
+        });
+        match replacement {
+            Some((value, length)) => {
+                expanded.push_str(&value);
+                rest = &candidate[length..];
+            }
+            None => {
+                expanded.push('$');
+                rest = &candidate[1..];
+            }
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// The operating system as CMake names it in ${hostSystemName}
+fn host_system_name() -> &'static str {
+    match std::env::consts::OS {
+        "linux" => "Linux",
+        "windows" => "Windows",
+        "macos" => "Darwin",
+        "freebsd" => "FreeBSD",
+        other => other,
+    }
+}
@@ -0,0 +1,193 @@
+use crate::shell::{join_args, split_args};
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+/// Launchers that run the actual compiler given as their first argument
+const LAUNCHERS: &[&str] = &["ccache", "sccache", "distcc", "icecc"];
+
+/// Index of the compiler executable among a command's arguments, skipping launchers like ccache
+pub fn compiler_index(args: &[String]) -> Option<usize> {
+    args.iter().position(|arg| !LAUNCHERS.contains(&executable_name(arg)))
+}
+
+/// File name of an executable, without its directory or `.exe` extension
+pub fn executable_name(path: &str) -> &str {
+    let name = Path::new(path).file_name().and_then(|name| name.to_str()).unwrap_or(path);
+    let name = name.rsplit('\\').next().unwrap_or(name); // Windows paths on other hosts
+    name.strip_suffix(".exe").unwrap_or(name)
+}
+
+/// Locations compilers resolve to, keyed by their spelling (and directory, when relative to it)
+static RESOLVED: OnceLock<Mutex<HashMap<String, Option<String>>>> = OnceLock::new();
+
+/// Where a compiler actually is, looked up in PATH and following symlinks (e.g. `cc` to
+/// `/usr/bin/gcc-13`), resolving each spelling only once
+pub fn resolve(compiler: &str, directory: &str) -> Option<String> {
+    let relative = Path::new(compiler).is_relative() && compiler.contains(['/', '\\']);
+    if relative && directory.is_empty() {
+        return None;
+    }
+    let key = match relative {
+        true => format!("{}\n{}", directory, compiler),
+        false => compiler.to_string(),
+    };
+    let cache = RESOLVED.get_or_init(Default::default);
+    if let Some(resolved) = cache.lock().unwrap_or_else(|e| e.into_inner()).get(&key) {
+        return resolved.clone();
+    }
+    let resolved = locate(compiler, directory).and_then(|path| std::fs::canonicalize(path).ok()).map(|path| {
+        let path = path.display().to_string();
+        path.strip_prefix(r"\\?\").map(str::to_string).unwrap_or(path) // Verbatim prefix of Windows paths
+    });
+    cache.lock().unwrap_or_else(|e| e.into_inner()).insert(key, resolved.clone());
+    resolved
+}
+
+/// Path of a compiler named by a command, before following symlinks
+fn locate(compiler: &str, directory: &str) -> Option<PathBuf> {
+    let path = Path::new(compiler);
+    if path.is_absolute() {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+    if compiler.contains(['/', '\\']) {
+        let path = Path::new(directory).join(path);
+        return path.is_file().then_some(path);
+    }
+    let names = [compiler.to_string(), format!("{}.exe", compiler)];
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .flat_map(|dir| names.iter().map(move |name| dir.join(name)))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Whether a compiler matches a glob over its name or path, either as spelled or as resolved
+pub fn matches_compiler(pattern: &str, compiler: &str) -> bool {
+    if glob_match(pattern, executable_name(compiler)) || glob_match(pattern, compiler) {
+        return true;
+    }
+    resolve(compiler, "").is_some_and(|resolved| glob_match(pattern, executable_name(&resolved)) || glob_match(pattern, &resolved))
+}
+
+/// Replace the compiler in a command's arguments with where it resolves to, returning the
+/// original if it changed
+pub fn resolve_in(args: &mut [String], directory: &str) -> Option<String> {
+    let index = compiler_index(args)?;
+    let resolved = resolve(&args[index], directory).filter(|resolved| *resolved != args[index])?;
+    Some(std::mem::replace(&mut args[index], resolved))
+}
+
+/// A command with its compiler spelled as it resolves, so that equivalent spellings compare equal
+pub fn normalized_command(command: &str, directory: &str) -> String {
+    let mut args = split_args(command);
+    match resolve_in(&mut args, directory) {
+        Some(_) => join_args(&args),
+        None => command.to_string(),
+    }
+}
+
+/// Names of the C and C++ compilers recognized in build output and intercepted processes
+const COMPILERS: &[&str] = &[
+    "cc", "c++", "*gcc", "*g++", "gcc-*", "g++-*", "*clang", "*clang++", "clang-*", "clang++-*", "icc", "icpc", "icx", "icpx",
+];
+
+/// Whether an executable name is that of a compiler
+pub fn is_compiler(name: &str) -> bool {
+    COMPILERS.iter().any(|pattern| glob_match(pattern, name))
+}
+
+/// Extensions of the sources compilers are invoked on
+const SOURCE_EXTENSIONS: &[&str] = &["c", "cc", "cpp", "cxx", "c++", "m", "mm", "s", "S", "asm"];
+
+/// Whether a path has the extension of a source compilers are invoked on
+pub fn is_source(path: &str) -> bool {
+    Path::new(path).extension().is_some_and(|extension| SOURCE_EXTENSIONS.iter().any(|source| extension == *source))
+}
+
+/// The source a compiler invocation compiles and the object it writes, as found in its arguments
+pub fn source_and_output(args: &[String]) -> (Option<String>, Option<String>) {
+    let mut file = None;
+    let mut output = None;
+    let mut previous = "";
+    for arg in args.iter().skip(1) {
+        if previous == "-o" {
+            output = Some(arg.clone());
+        } else if let Some(object) = arg.strip_prefix("-Fo").or_else(|| arg.strip_prefix("/Fo")) {
+            output = Some(object.to_string());
+        } else if !arg.starts_with('-') && is_source(arg) {
+            file = Some(arg.clone());
+        }
+        previous = arg;
+    }
+    (file, output)
+}
+
+/// Match text against a pattern where `*` matches any run of characters and `?` any single one
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None; // Position after the last `*`, and where it resumed
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, t));
+                p += 1;
+            }
+            Some('?') => {
+                p += 1;
+                t += 1;
+            }
+            Some(&c) if c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, resumed)) => {
+                    p = star;
+                    t = resumed + 1;
+                    backtrack = Some((star, resumed + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Compiler substitutions, e.g. running analysis with clang on a GCC cross toolchain's database
+#[derive(Debug, Default)]
+pub struct CompilerMap {
+    rules: Vec<(String, String)>, // Glob over the compiler's name or path, and its replacement
+}
+
+impl CompilerMap {
+    /// Parse `PATTERN=COMPILER` rules, the first matching one applying
+    pub fn new(specs: &[String]) -> Result<Self> {
+        let rules = specs
+            .iter()
+            .map(|spec| match spec.split_once('=') {
+                Some((pattern, compiler)) if !pattern.is_empty() && !compiler.is_empty() => {
+                    Ok((pattern.to_string(), compiler.to_string()))
+                }
+                _ => bail!("Invalid compiler mapping '{}', expected PATTERN=COMPILER", spec),
+            })
+            .collect::<Result<_>>()?;
+        Ok(CompilerMap { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Replace the compiler in a command's arguments, returning the original if it was replaced
+    pub fn apply(&self, args: &mut [String]) -> Option<String> {
+        let index = compiler_index(args)?;
+        let compiler = &args[index];
+        let (_, replacement) = self
+            .rules
+            .iter()
+            .find(|(pattern, _)| matches_compiler(pattern, compiler))?;
+        Some(std::mem::replace(&mut args[index], replacement.clone()))
+    }
+}
@@ -0,0 +1,39 @@
+use crate::compiler::glob_match;
+use anyhow::{anyhow, Result};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Extensions of compressed databases, e.g. `compile_commands.json.gz` as CI artifacts often are
+const EXTENSIONS: &[&str] = &["gz", "zst"];
+
+/// Whether a file is the database named `input_file`, or whose name matches it as a glob,
+/// compressed or not
+pub fn is_database(path: &Path, input_file: &str) -> bool {
+    if path.ends_with(input_file) {
+        return true;
+    }
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    glob_match(input_file, &name)
+        || EXTENSIONS.iter().any(|extension| {
+            name.strip_suffix(extension)
+                .and_then(|name| name.strip_suffix('.'))
+                .is_some_and(|name| glob_match(input_file, name))
+        })
+}
+
+/// Whether a database is compressed, going by its extension
+pub fn is_compressed(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| EXTENSIONS.iter().any(|compressed| extension == *compressed))
+}
+
+/// A reader of the decompressed content of a compressed database
+pub fn decompress(path: &Path, file: File) -> Result<Box<dyn Read + Send>> {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("gz") => Ok(Box::new(flate2::read::MultiGzDecoder::new(file))),
+        Some("zst") => ruzstd::decoding::StreamingDecoder::new(file)
+            .map(|decoder| Box::new(decoder) as Box<dyn Read + Send>)
+            .map_err(|e| anyhow!("Not a zstd file: {}", e)),
+        _ => Err(anyhow!("Unknown compression of {}", path.display())),
+    }
+}
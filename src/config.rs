@@ -0,0 +1,69 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::fs;
+use std::path::Path;
+use toml::{Table, Value};
+
+/// Config file read from the working directory when --config isn't given
+pub const CONFIG_FILE: &str = "ccm.toml";
+
+/// Keys that only make sense on the command line, choosing the config file itself
+const COMMAND_LINE_ONLY: &[&str] = &["config", "profile"];
+
+/// The options a config file sets, as command-line arguments: its top-level keys, replaced by
+/// those of `[profile.NAME]` when a profile is selected. Keys are long option names, with
+/// `true` for flags, arrays for repeatable options, and tables for those taking JSON objects
+/// (`[[root]]` sections), e.g.:
+/// ```toml
+/// directories = ["build"]
+/// sort = true
+///
+/// [[root]]
+/// path = "build-arm"
+/// priority = 10
+///
+/// [profile.embedded]
+/// directories = ["build-arm"]
+/// output = "compile_commands.arm.json"
+/// ```
+pub fn arguments(path: &Path, profile: Option<&str>) -> Result<Vec<String>> {
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let mut options: Table = toml::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))?;
+    let profiles = match options.remove("profile") {
+        Some(Value::Table(profiles)) => profiles,
+        Some(_) => bail!("`profile` in {} must be a table of [profile.NAME] sections", path.display()),
+        None => Table::new(),
+    };
+    if let Some(name) = profile {
+        let selected = match profiles.get(name) {
+            Some(Value::Table(selected)) => selected,
+            Some(_) => bail!("[profile.{}] in {} must be a table", name, path.display()),
+            None => {
+                let known: Vec<&str> = profiles.keys().map(String::as_str).collect();
+                bail!("No profile '{}' in {} (profiles: {})", name, path.display(), known.join(", "))
+            }
+        };
+        options.extend(selected.clone());
+    }
+    let mut arguments = Vec::new();
+    for (key, value) in options {
+        if COMMAND_LINE_ONLY.contains(&key.as_str()) {
+            bail!("`{}` can only be given on the command line", key);
+        }
+        let values = match value {
+            Value::Array(values) => values,
+            value => vec![value],
+        };
+        for value in values {
+            match value {
+                Value::Boolean(true) => arguments.push(format!("--{}", key)),
+                Value::Boolean(false) => {}
+                Value::String(value) => arguments.push(format!("--{}={}", key, value)),
+                Value::Integer(value) => arguments.push(format!("--{}={}", key, value)),
+                Value::Float(value) => arguments.push(format!("--{}={}", key, value)),
+                Value::Table(table) => arguments.push(format!("--{}={}", key, serde_json::to_string(&table)?)),
+                _ => return Err(anyhow!("Unsupported value of `{}` in {}", key, path.display())),
+            }
+        }
+    }
+    Ok(arguments)
+}
@@ -0,0 +1,34 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Minimum time before the same failure is notified again
+const REPEAT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Surfaces merge failures as OS desktop notifications
+#[derive(Default)]
+pub struct DesktopNotifier {
+    last_sent: HashMap<String, Instant>, // Keyed by notification body
+}
+
+impl DesktopNotifier {
+    /// Show a failure notification, unless the same one was shown recently
+    pub fn failure(&mut self, body: &str) {
+        if self
+            .last_sent
+            .get(body)
+            .is_some_and(|sent| sent.elapsed() < REPEAT_INTERVAL)
+        {
+            return;
+        }
+        self.last_sent.insert(body.to_string(), Instant::now());
+
+        let result = notify_rust::Notification::new()
+            .summary("Compile commands merge failed")
+            .body(body)
+            .appname("compile_commands_merger")
+            .show();
+        if let Err(e) = result {
+            warn!("Warning: Failed to show desktop notification: {}", e);
+        }
+    }
+}
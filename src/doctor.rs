@@ -0,0 +1,145 @@
+use crate::limits;
+use notify::{Config, RecommendedWatcher, RecursiveMode, Watcher};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// How long a test file's event may take to arrive
+const EVENT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Filesystems on which native change events are unreliable or missing
+const REMOTE_FILESYSTEMS: &[&str] = &[
+    "nfs", "nfs4", "cifs", "smb3", "smbfs", "9p", "virtiofs", "vboxsf", "fuse.sshfs", "fuse.gcsfuse", "afs",
+];
+
+/// Check that changes under each watched directory would reach the watcher, returning the number
+/// of problems found
+pub fn run(directories: &[String], input_file: &str) -> usize {
+    let mut problems = 0;
+    if let Some(limit) = limits::max_user_watches() {
+        let needed: usize = directories.iter().map(|dir| limits::count_directories(Path::new(dir))).sum();
+        if needed as u64 > limit {
+            warn!(
+                "Warning: Watching needs {} inotify watches but fs.inotify.max_user_watches is {}. \
+                 Raise it (e.g. `sysctl fs.inotify.max_user_watches={}`) or watch fewer directories.",
+                needed,
+                limit,
+                needed.next_power_of_two()
+            );
+            problems += 1;
+        } else {
+            info!("OK: {} inotify watches needed, limit {}", needed, limit);
+        }
+    }
+    for dir in directories {
+        problems += check_root(Path::new(dir), input_file);
+    }
+    match problems {
+        0 => info!("No problems found."),
+        1 => warn!("Warning: 1 problem found."),
+        n => warn!("Warning: {} problems found.", n),
+    }
+    problems
+}
+
+/// Check one watched directory, returning the number of problems found
+fn check_root(root: &Path, input_file: &str) -> usize {
+    info!("Checking {}", root.display());
+    if !root.is_dir() {
+        warn!("Warning: {} is not a directory. Check the path given to --directories.", root.display());
+        return 1;
+    }
+    let mut problems = 0;
+
+    let databases = walkdir::WalkDir::new(root)
+        .into_iter()
+        .flatten()
+        .filter(|entry| entry.file_type().is_file() && entry.file_name() == input_file)
+        .count();
+    if databases == 0 {
+        warn!(
+            "Warning: No {} below {}. Check that the build exports one (e.g. CMAKE_EXPORT_COMPILE_COMMANDS=ON), or pass its name with --input.",
+            input_file,
+            root.display()
+        );
+        problems += 1;
+    } else {
+        info!("OK: {} databases found", databases);
+    }
+
+    if let Some(filesystem) = filesystem_type(root) {
+        if REMOTE_FILESYSTEMS.contains(&filesystem.as_str()) {
+            warn!(
+                "Warning: {} is on a {} filesystem, where changes made on other machines or outside the container raise no events. \
+                 Use --rescan-interval to catch them.",
+                root.display(),
+                filesystem
+            );
+            problems += 1;
+        } else {
+            info!("OK: filesystem type {}", filesystem);
+        }
+    }
+
+    match event_round_trip(root) {
+        Ok(elapsed) => info!("OK: change event arrived after {}ms", elapsed.as_millis()),
+        Err(e) => {
+            warn!(
+                "Warning: {}. The merge will not update on changes here; use --rescan-interval, or check container and sandbox settings.",
+                e
+            );
+            problems += 1;
+        }
+    }
+    problems
+}
+
+/// Create a file under a directory and time how long its change event takes to arrive
+fn event_round_trip(root: &Path) -> Result<Duration, String> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = RecommendedWatcher::new(tx, Config::default()).map_err(|e| format!("Cannot create a watcher: {}", e))?;
+    watcher
+        .watch(root, RecursiveMode::Recursive)
+        .map_err(|e| format!("Cannot watch {}: {}", root.display(), e))?;
+
+    let probe: PathBuf = root.join(format!(".compile_commands_merger_doctor_{}", std::process::id()));
+    let started = Instant::now();
+    fs::write(&probe, "probe").map_err(|e| format!("Cannot create a test file in {}: {}", root.display(), e))?;
+    let probe_name = probe.file_name().unwrap_or_default().to_os_string();
+    let result = loop {
+        let remaining = EVENT_TIMEOUT.saturating_sub(started.elapsed());
+        match rx.recv_timeout(remaining) {
+            Ok(Ok(event)) if event.paths.iter().any(|path| path.file_name() == Some(probe_name.as_os_str())) => {
+                break Ok(started.elapsed());
+            }
+            Ok(_) => continue,
+            Err(_) => break Err(format!("No change event within {}s for a test file in {}", EVENT_TIMEOUT.as_secs(), root.display())),
+        }
+    };
+    let _ = fs::remove_file(&probe);
+    result
+}
+
+/// Type of the filesystem holding a path, from the mount table
+#[cfg(target_os = "linux")]
+fn filesystem_type(path: &Path) -> Option<String> {
+    let path = fs::canonicalize(path).ok()?;
+    let mounts = fs::read_to_string("/proc/self/mounts").ok()?;
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let _device = fields.next()?;
+            let mount_point = fields.next()?.replace("\\040", " ");
+            let filesystem = fields.next()?;
+            path.starts_with(&mount_point).then(|| (mount_point.len(), filesystem.to_string()))
+        })
+        .max_by_key(|(length, _)| *length)
+        .map(|(_, filesystem)| filesystem)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn filesystem_type(_path: &Path) -> Option<String> {
+    None
+}
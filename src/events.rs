@@ -0,0 +1,92 @@
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Number of unpaired rename cookies remembered before they are discarded
+const MAX_PENDING: usize = 1024;
+
+/// What happened at a path, as far as merged databases are concerned
+#[derive(Debug)]
+pub enum Change {
+    /// Content at the path may have changed
+    Modified(PathBuf),
+    /// A file or directory was renamed or moved to the path
+    MovedIn(PathBuf),
+    /// A file or directory was renamed or moved away from the path
+    MovedOut(PathBuf),
+    /// A file or directory at the path was deleted, unless something replaced it since
+    Deleted(PathBuf),
+}
+
+impl Change {
+    /// The path the change is of
+    pub fn path(&self) -> &Path {
+        match self {
+            Change::Modified(path) | Change::MovedIn(path) | Change::MovedOut(path) | Change::Deleted(path) => path,
+        }
+    }
+
+    /// Whether the change is of the given file, written in place or replaced
+    pub fn touches(&self, file: &Path) -> bool {
+        match self {
+            Change::Modified(path) | Change::MovedIn(path) => crate::is_same_file(path, file),
+            Change::MovedOut(_) | Change::Deleted(_) => false,
+        }
+    }
+}
+
+/// Resolves filesystem events to changes, pairing the halves of renames so that generators
+/// writing a temp file and renaming it over the database are seen as an update of the final
+/// destination, and moved build directories as moves rather than unrelated events.
+#[derive(Default)]
+pub struct RenameTracker {
+    resolved: HashSet<usize>, // Rename cookies whose halves were already reported
+}
+
+impl RenameTracker {
+    pub fn changes(&mut self, event: Event) -> Vec<Change> {
+        let tracker = event.attrs.tracker();
+        match event.kind {
+            EventKind::Create(_) => event.paths.into_iter().map(Change::Modified).collect(),
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+                event.paths.into_iter().map(Change::MovedOut).collect()
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+                // Renames into the tree from outside never get a paired half
+                if self.resolved.len() > MAX_PENDING {
+                    self.resolved.clear();
+                }
+                self.resolved.extend(tracker);
+                event.paths.into_iter().map(Change::MovedIn).collect()
+            }
+            // Paired rename: report both halves unless the `From`/`To` events already did
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+                if tracker.is_some_and(|cookie| self.resolved.remove(&cookie)) {
+                    return Vec::new();
+                }
+                let mut paths = event.paths.into_iter();
+                match (paths.next(), paths.next()) {
+                    (Some(from), Some(to)) => vec![Change::MovedOut(from), Change::MovedIn(to)],
+                    (Some(to), None) => vec![Change::MovedIn(to)],
+                    _ => Vec::new(),
+                }
+            }
+            // Backends that can't tell rename halves apart: a path either still exists or not
+            EventKind::Modify(ModifyKind::Name(_)) => event
+                .paths
+                .into_iter()
+                .map(|path| {
+                    if path.exists() {
+                        Change::MovedIn(path)
+                    } else {
+                        Change::MovedOut(path)
+                    }
+                })
+                .collect(),
+            EventKind::Modify(_) => event.paths.into_iter().map(Change::Modified).collect(),
+            EventKind::Remove(_) => event.paths.into_iter().map(Change::Deleted).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
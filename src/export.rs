@@ -0,0 +1,80 @@
+use crate::compiler::{compiler_index, executable_name, source_and_output};
+use crate::includes::settings;
+use crate::shell::split_args;
+use crate::{read_compile_commands, CompileCommand};
+use anyhow::Result;
+use clap::ValueEnum;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Columns of the export, one row per entry
+const COLUMNS: &[&str] = &[
+    "file", "directory", "compiler", "std", "optimization", "debug", "defines", "includes", "warnings", "arguments", "output",
+];
+
+/// Flat table formats entries are exported in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum TableFormat {
+    Csv,
+    Tsv,
+}
+
+/// Write a row per entry of a database, with the flags that matter when comparing builds
+/// broken out into columns
+pub fn export(database: &Path, format: TableFormat, out: &mut impl Write) -> Result<()> {
+    let commands = read_compile_commands(database)?;
+    write_row(out, format, COLUMNS.iter().map(|column| column.to_string()))?;
+    for command in &commands {
+        write_row(out, format, row(command))?;
+    }
+    Ok(())
+}
+
+/// The columns of an entry, derived from its tokenized command
+fn row(command: &CompileCommand) -> impl Iterator<Item = String> {
+    let args = split_args(&command.command);
+    let flags = settings(&args, Path::new(&command.directory));
+    let start = compiler_index(&args);
+    let compiler = start.map_or("", |index| executable_name(&args[index]));
+    let options = &args[start.map_or(args.len(), |index| index + 1)..];
+    // Slash options only mean something to MSVC-style compilers, elsewhere they are paths
+    let msvc = matches!(compiler.to_ascii_lowercase().as_str(), "cl" | "clang-cl");
+    let option = |arg: &str, name: &str| {
+        arg.strip_prefix('-').or_else(|| arg.strip_prefix('/').filter(|_| msvc))?.strip_prefix(name).map(str::to_string)
+    };
+    // The last of repeated options wins, as with the compilers themselves
+    let std = options.iter().rev().find_map(|arg| option(arg, "std=").or_else(|| option(arg, "std:")));
+    let optimization = options.iter().rev().find_map(|arg| option(arg, "O").map(|level| format!("O{}", level)));
+    let debug = options.iter().any(|arg| arg.starts_with("-g") || option(arg, "Zi").is_some() || option(arg, "Z7").is_some());
+    let warnings = options.iter().filter(|arg| arg.starts_with("-W") && !arg.starts_with("-Wl,")).count();
+    [
+        command.file.clone(),
+        command.directory.clone(),
+        compiler.to_string(),
+        std.unwrap_or_default(),
+        optimization.unwrap_or_default(),
+        debug.to_string(),
+        flags.defines.len().to_string(),
+        flags.includes.len().to_string(),
+        warnings.to_string(),
+        options.len().to_string(),
+        command.output.clone().or_else(|| source_and_output(&args[start.unwrap_or(0)..]).1).unwrap_or_default(),
+    ]
+    .into_iter()
+}
+
+fn write_row(out: &mut impl Write, format: TableFormat, fields: impl Iterator<Item = String>) -> io::Result<()> {
+    let (separator, fields): (&str, Vec<String>) = match format {
+        TableFormat::Csv => (",", fields.map(|field| csv_field(&field)).collect()),
+        TableFormat::Tsv => ("\t", fields.map(|field| field.replace(['\t', '\n', '\r'], " ")).collect()),
+    };
+    writeln!(out, "{}", fields.join(separator))
+}
+
+/// Quote a CSV field if it needs it, doubling the quotes inside
+fn csv_field(field: &str) -> String {
+    match field.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_string(),
+    }
+}
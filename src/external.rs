@@ -0,0 +1,111 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::Hasher;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+/// What to do when something other than the merger modified the output
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExternalChangePolicy {
+    /// Overwrite it with the merged entries
+    Reassert,
+    /// Keep the entries added or edited in it, overriding those of the inputs
+    Ingest,
+}
+
+/// Recognizes the output as last written by the merger
+#[derive(Default)]
+pub struct OutputGuard {
+    written: Option<Written>,
+}
+
+struct Written {
+    modified: Option<SystemTime>,
+    fingerprint: Fingerprint,
+}
+
+/// Length and hash identifying a file's content
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Fingerprint {
+    len: u64,
+    hash: u64,
+    crc: u32, // Unlike the hash, the same across builds of the tool, for files that outlive it
+}
+
+impl Fingerprint {
+    pub fn of(content: &[u8]) -> Self {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(content);
+        Fingerprint { len: content.len() as u64, hash: hasher.finish(), crc: checksum(content) }
+    }
+
+    /// The content's checksum as recorded in other files, e.g. `crc32:1a2b3c4d`
+    pub fn checksum(&self) -> String {
+        format!("crc32:{:08x}", self.crc)
+    }
+}
+
+/// CRC-32 of some content
+pub fn checksum(content: &[u8]) -> u32 {
+    let mut crc = flate2::Crc::new();
+    crc.update(content);
+    crc.sum()
+}
+
+/// Writer fingerprinting the content passing through it, so it needn't be kept in memory
+pub struct Fingerprinting<W> {
+    inner: W,
+    hasher: DefaultHasher,
+    crc: flate2::Crc,
+    len: u64,
+}
+
+impl<W: Write> Fingerprinting<W> {
+    pub fn new(inner: W) -> Self {
+        Fingerprinting { inner, hasher: DefaultHasher::new(), crc: flate2::Crc::new(), len: 0 }
+    }
+
+    pub fn finish(self) -> Fingerprint {
+        Fingerprint { len: self.len, hash: self.hasher.finish(), crc: self.crc.sum() }
+    }
+}
+
+impl<W: Write> Write for Fingerprinting<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.write(&buf[..written]);
+        self.crc.update(&buf[..written]);
+        self.len += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl OutputGuard {
+    /// Remember the fingerprint of the content just written to the output
+    pub fn record(&mut self, path: &Path, fingerprint: Fingerprint) {
+        let metadata = fs::metadata(path).ok();
+        self.written = Some(Written {
+            modified: metadata.as_ref().and_then(|metadata| metadata.modified().ok()),
+            fingerprint,
+        });
+    }
+
+    /// The output's content, if it exists and something changed it since the last write
+    pub fn foreign_content(&self, path: &Path) -> Option<String> {
+        let written = self.written.as_ref()?;
+        let metadata = fs::metadata(path).ok()?;
+        if metadata.len() == written.fingerprint.len && metadata.modified().ok() == written.modified {
+            return None; // Untouched, no need to read it
+        }
+        let content = fs::read_to_string(path).ok()?;
+        (Fingerprint::of(content.as_bytes()) != written.fingerprint).then_some(content)
+    }
+}
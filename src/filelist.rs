@@ -0,0 +1,33 @@
+use crate::paths::normalize;
+use crate::{write_atomically, Entry};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+/// Plain list of the merged source files, one absolute path per line, for tools like ctags and
+/// cscope (`ctags -L`, `cscope -i`)
+pub struct FileList {
+    path: PathBuf,
+}
+
+impl FileList {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileList { path: path.into() }
+    }
+
+    /// Write the list for the given merged entries, sorted and without duplicates
+    pub fn write(&self, entries: &[&Entry]) -> std::io::Result<()> {
+        let files: BTreeSet<String> = entries
+            .iter()
+            .map(|entry| {
+                let command = &entry.command;
+                normalize(&Path::new(&command.directory).join(&command.file)).display().to_string()
+            })
+            .collect();
+        let mut content = String::new();
+        for file in files {
+            content.push_str(&file);
+            content.push('\n');
+        }
+        write_atomically(&self.path, &content)
+    }
+}
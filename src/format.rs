@@ -0,0 +1,76 @@
+use crate::shell::split_args;
+use crate::CompileCommand;
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// Layout of the written output
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// The standard compile_commands.json array
+    #[default]
+    Json,
+    /// One entry object per line (JSON Lines), for jq and data pipelines
+    Ndjson,
+    /// The standard array without indentation, with `arguments` lists instead of command strings
+    Arguments,
+}
+
+/// An entry in the `arguments` form
+#[derive(Serialize)]
+struct ArgumentsCommand<'a> {
+    directory: &'a str,
+    arguments: Vec<String>,
+    file: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output: Option<&'a str>,
+    #[serde(flatten)]
+    extra: &'a serde_json::Map<String, serde_json::Value>,
+}
+
+impl OutputFormat {
+    /// Serialize the entries to a writer in this format
+    pub fn write(self, writer: &mut impl Write, commands: &[&CompileCommand]) -> io::Result<()> {
+        match self {
+            OutputFormat::Json => serde_json::to_writer_pretty(writer, commands)?,
+            OutputFormat::Ndjson => {
+                for command in commands {
+                    serde_json::to_writer(&mut *writer, command)?;
+                    writer.write_all(b"\n")?;
+                }
+            }
+            OutputFormat::Arguments => {
+                let commands: Vec<ArgumentsCommand> = commands
+                    .iter()
+                    .map(|command| ArgumentsCommand {
+                        directory: &command.directory,
+                        arguments: split_args(&command.command),
+                        file: &command.file,
+                        output: command.output.as_deref(),
+                        extra: &command.extra,
+                    })
+                    .collect();
+                serde_json::to_writer(writer, &commands)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An output written along with the main one, from `FORMAT=PATH`
+pub fn parse_also(spec: &str) -> Result<(OutputFormat, PathBuf)> {
+    let (format, path) = spec
+        .split_once('=')
+        .ok_or_else(|| anyhow!("Invalid output '{}', expected FORMAT=PATH", spec))?;
+    let format = OutputFormat::from_str(format, true).map_err(|_| {
+        let formats: Vec<String> = OutputFormat::value_variants()
+            .iter()
+            .filter_map(|format| format.to_possible_value().map(|value| value.get_name().to_string()))
+            .collect();
+        anyhow!("Unknown format '{}' in '{}' (formats: {})", format, spec, formats.join(", "))
+    })?;
+    Ok((format, PathBuf::from(path)))
+}
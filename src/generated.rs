@@ -0,0 +1,198 @@
+use crate::compiler::{glob_match, is_source};
+use crate::paths::normalize;
+use crate::shell::{join_args, split_args};
+use crate::CompileCommand;
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Sources that are generated wherever they are: Qt moc/rcc outputs, AUTOMOC and try_compile artifacts
+const ALWAYS_GENERATED: &[&str] = &[
+    "*/moc_*.cpp",
+    "*/qrc_*.cpp",
+    "*/mocs_compilation*.cpp",
+    "*_autogen/*",
+    "*/CMakeFiles/CMakeTmp/*",
+    "*/CMakeFiles/CMakeScratch/*",
+];
+
+/// Sources that are generated when they are found in a build tree: protobuf, bison and flex outputs
+const GENERATED_IN_BUILD_TREE: &[&str] = &["*.pb.cc", "*.pb.c", "*.pb-c.c", "*.tab.c", "*.tab.cc", "*/lex.yy.c"];
+
+/// Files marking the top of a build tree
+const BUILD_TREE_MARKERS: &[&str] = &["CMakeCache.txt", "build.ninja", "meson-private"];
+
+/// Prefixes generators put before the name of the file they generate from
+const GENERATOR_PREFIXES: &[&str] = &["moc_", "qrc_"];
+
+/// Drops the entries of generated sources, or gives them to the checked-in template they are
+/// made from where one is configured
+pub struct GeneratedSources {
+    heuristics: bool, // Recognize the usual generators' outputs, besides the configured patterns
+    patterns: Vec<String>,
+    templates: Vec<(String, String)>, // Glob of generated files, and their template
+    build_trees: Mutex<HashMap<PathBuf, bool>>, // Whether a directory is in a build tree
+}
+
+impl GeneratedSources {
+    /// Parse the `PATTERN=TEMPLATE` mappings; None when nothing is to be filtered
+    pub fn new(heuristics: bool, patterns: &[String], templates: &[String]) -> Result<Option<Self>> {
+        if !heuristics && patterns.is_empty() && templates.is_empty() {
+            return Ok(None);
+        }
+        let templates = templates
+            .iter()
+            .map(|spec| match spec.split_once('=') {
+                Some((pattern, template)) if !pattern.is_empty() && !template.is_empty() => {
+                    Ok((pattern.to_string(), template.to_string()))
+                }
+                _ => bail!("Invalid generated source template '{}', expected PATTERN=TEMPLATE", spec),
+            })
+            .collect::<Result<_>>()?;
+        Ok(Some(GeneratedSources {
+            heuristics,
+            patterns: patterns.to_vec(),
+            templates,
+            build_trees: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    /// The entry to keep for a source: itself, the one of its template, or none for a generated source
+    pub fn apply(&self, mut command: CompileCommand) -> Option<CompileCommand> {
+        let path = normalize(&Path::new(&command.directory).join(&command.file));
+        let path_text = path.display().to_string().replace('\\', "/");
+        if !self.is_generated(&path, &path_text) {
+            return Some(command);
+        }
+        let template = self
+            .templates
+            .iter()
+            .find(|(pattern, _)| glob_match(pattern, &path_text) || glob_match(pattern, &command.file))
+            .map(|(_, template)| expand_template(template, &path))?;
+        if !Path::new(&command.directory).join(&template).is_file() {
+            return None;
+        }
+        give_to(&mut command, template);
+        Some(command)
+    }
+
+    fn is_generated(&self, path: &Path, path_text: &str) -> bool {
+        if self.patterns.iter().any(|pattern| glob_match(pattern, path_text)) {
+            return true;
+        }
+        if !self.heuristics {
+            return false;
+        }
+        if ALWAYS_GENERATED.iter().any(|pattern| glob_match(pattern, path_text)) {
+            return true;
+        }
+        GENERATED_IN_BUILD_TREE.iter().any(|pattern| glob_match(pattern, path_text))
+            && path.parent().is_some_and(|dir| self.in_build_tree(dir))
+    }
+
+    /// Whether a directory or one above it holds a build tree marker, remembered per directory
+    fn in_build_tree(&self, dir: &Path) -> bool {
+        let mut build_trees = self.build_trees.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(&known) = build_trees.get(dir) {
+            return known;
+        }
+        let found = dir
+            .ancestors()
+            .any(|ancestor| BUILD_TREE_MARKERS.iter().any(|marker| ancestor.join(marker).exists()));
+        build_trees.insert(dir.to_path_buf(), found);
+        found
+    }
+}
+
+/// Gives the entries of files a build placed in its tree back to the source-tree file they were
+/// copied, configured or generated from
+#[derive(Default)]
+pub struct SourceMap {
+    rules: Vec<(PathBuf, PathBuf)>, // Build directory, and the source directory it mirrors
+}
+
+impl SourceMap {
+    /// Parse `BUILD=SOURCE` directory mappings, the first matching one applying to each entry
+    pub fn new(specs: &[String]) -> Result<Self> {
+        let rules = specs
+            .iter()
+            .map(|spec| match spec.split_once('=') {
+                Some((build, source)) if !build.is_empty() && !source.is_empty() => {
+                    Ok((std::path::absolute(build)?, std::path::absolute(source)?))
+                }
+                _ => bail!("Invalid source mapping '{}', expected BUILD=SOURCE", spec),
+            })
+            .collect::<Result<_>>()?;
+        Ok(SourceMap { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Rewrite the entry of a file in a mapped build directory to the source-tree file it comes
+    /// from, if one exists: the same path, a configure_file template (`.in`, `.cmake`), the
+    /// .proto of protobuf outputs or the header of moc outputs
+    pub fn apply(&self, command: &mut CompileCommand) {
+        let path = normalize(&Path::new(&command.directory).join(&command.file));
+        let Some((relative, source)) = self
+            .rules
+            .iter()
+            .find_map(|(build, source)| Some((path.strip_prefix(build).ok()?, source)))
+        else {
+            return;
+        };
+        let Some(name) = relative.file_name().map(|name| name.to_string_lossy().into_owned()) else {
+            return;
+        };
+        let dir = source.join(relative.parent().unwrap_or(Path::new("")));
+        if let Some(original) = origins(&name).into_iter().map(|origin| dir.join(origin)).find(|origin| origin.is_file()) {
+            give_to(command, original.display().to_string());
+        }
+    }
+}
+
+/// Names of the source-tree files a file of a build tree may come from, most likely first
+fn origins(name: &str) -> Vec<String> {
+    let mut origins = vec![name.to_string(), format!("{}.in", name), format!("{}.cmake", name)];
+    let stem = name.split('.').next().unwrap_or(name);
+    if [".pb.cc", ".pb.c", ".pb-c.c", ".grpc.pb.cc"].iter().any(|suffix| name.ends_with(suffix)) {
+        origins.push(format!("{}.proto", stem));
+    }
+    if let Some(header) = stem.strip_prefix("moc_") {
+        origins.extend(["h", "hpp", "hxx"].iter().map(|extension| format!("{}.{}", header, extension)));
+    }
+    origins
+}
+
+/// Headers, which the source argument may be replaced with like sources
+const HEADER_EXTENSIONS: &[&str] = &["h", "hh", "hpp", "hxx"];
+
+/// Make an entry the one of another file; the source argument is replaced too, so tools taking it
+/// from the command agree, unless the other file isn't C-family code (e.g. a .proto or .in)
+fn give_to(command: &mut CompileCommand, file: String) {
+    let header = Path::new(&file).extension().is_some_and(|extension| HEADER_EXTENSIONS.iter().any(|header| extension == *header));
+    if !is_source(&file) && !header {
+        command.file = file;
+        return;
+    }
+    let args: Vec<String> = split_args(&command.command)
+        .into_iter()
+        .map(|arg| if arg == command.file { file.clone() } else { arg })
+        .collect();
+    command.command = join_args(&args);
+    command.file = file;
+}
+
+/// Fill in a template's `{name}` with the generated file's name, and `{base}` with that name up
+/// to its first dot and without a generator's prefix
+fn expand_template(template: &str, generated: &Path) -> String {
+    let name = generated.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let stem = name.split('.').next().unwrap_or(&name);
+    let base = GENERATOR_PREFIXES
+        .iter()
+        .find_map(|prefix| stem.strip_prefix(prefix))
+        .unwrap_or(stem);
+    template.replace("{name}", &name).replace("{base}", base)
+}
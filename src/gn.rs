@@ -0,0 +1,63 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// File marking a GN build directory
+const ARGS_FILE: &str = "args.gn";
+
+/// Build directory preferred when several define the same file, by Chromium convention
+const PRIMARY_OUT_DIR: &str = "Default";
+
+/// GN build directories of a checkout (`out/*` with an args.gn), or the directory itself if it is one
+fn out_dirs(root: &Path) -> Vec<PathBuf> {
+    if root.join(ARGS_FILE).is_file() {
+        return vec![root.to_path_buf()];
+    }
+    let out = if root.join("out").is_dir() { root.join("out") } else { root.to_path_buf() };
+    let mut dirs: Vec<PathBuf> = std::fs::read_dir(out)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|dir| dir.join(ARGS_FILE).is_file())
+        .collect();
+    dirs.sort();
+    dirs
+}
+
+/// Databases of a checkout's build directories, having GN export those that are missing
+pub fn discover(root: &Path, filename: &str) -> Vec<PathBuf> {
+    out_dirs(root)
+        .into_iter()
+        .filter_map(|dir| {
+            let database = dir.join(filename);
+            if !database.is_file() && filename == "compile_commands.json" {
+                export(&dir);
+            }
+            database.is_file().then_some(database)
+        })
+        .collect()
+}
+
+/// Priority of a build directory's entries: the primary one wins over the others
+pub fn priority(database: &Path) -> i32 {
+    let out_dir = database.parent().and_then(Path::file_name);
+    i32::from(out_dir.is_some_and(|name| name == PRIMARY_OUT_DIR))
+}
+
+/// Run `gn gen --export-compile-commands` in a build directory, which GN resolves to its checkout
+fn export(out_dir: &Path) {
+    info!("Exporting compile commands of {} with gn gen", out_dir.display());
+    let output = Command::new("gn")
+        .args(["gen", ".", "--export-compile-commands"])
+        .current_dir(out_dir)
+        .output();
+    match output {
+        Ok(output) if output.status.success() => {}
+        Ok(output) => warn!(
+            "Warning: gn gen failed for {}: {}",
+            out_dir.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+        Err(e) => warn!("Warning: Failed to run gn for {}: {}", out_dir.display(), e),
+    }
+}
@@ -0,0 +1,241 @@
+use crate::compiler::glob_match;
+use crate::watch::{Control, SharedState};
+use crate::merger::MergeEvent;
+use crate::source_path;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::task::{Context, Poll};
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::body::BoxBody;
+use tonic::codec::ProstCodec;
+use tonic::codegen::{http, Body, BoxFuture, Service, StdError};
+use tonic::server::{Grpc, NamedService, ServerStreamingService, UnaryService};
+use tonic::{Request, Response, Status};
+
+/// Messages of proto/ccm.proto, written out rather than generated so no protoc is needed
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RootRequest {
+    #[prost(string, tag = "1")]
+    pub directory: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RootReply {
+    #[prost(uint64, tag = "1")]
+    pub entries: u64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct QueryRequest {
+    #[prost(string, tag = "1")]
+    pub file: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Entry {
+    #[prost(string, tag = "1")]
+    pub directory: String,
+    #[prost(string, tag = "2")]
+    pub command: String,
+    #[prost(string, tag = "3")]
+    pub file: String,
+    #[prost(string, optional, tag = "4")]
+    pub output: Option<String>,
+    #[prost(string, tag = "5")]
+    pub source: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct QueryReply {
+    #[prost(message, repeated, tag = "1")]
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SubscribeRequest {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ChangeEvent {
+    #[prost(enumeration = "Kind", tag = "1")]
+    pub kind: i32,
+    #[prost(string, tag = "2")]
+    pub file: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum Kind {
+    Added = 0,
+    Modified = 1,
+    Removed = 2,
+}
+
+type ChangeStream = std::pin::Pin<Box<dyn Stream<Item = Result<ChangeEvent, Status>> + Send>>;
+
+/// Serve the Merger service on an address until the watcher exits
+pub async fn serve(
+    addr: SocketAddr,
+    state: SharedState,
+    control: mpsc::UnboundedSender<Control>,
+    changes: broadcast::Sender<MergeEvent>,
+) {
+    info!("Serving gRPC on {}", addr);
+    let service = MergerService { state, control, changes };
+    if let Err(e) = tonic::transport::Server::builder().add_service(service).serve(addr).await {
+        error!("Error: gRPC server failed: {}", e);
+    }
+}
+
+/// The Merger service of proto/ccm.proto
+#[derive(Clone)]
+struct MergerService {
+    state: SharedState,
+    control: mpsc::UnboundedSender<Control>,
+    changes: broadcast::Sender<MergeEvent>,
+}
+
+impl MergerService {
+    /// Ask the event loop, which owns the watches, to add or remove a root
+    async fn root(&self, request: RootRequest, control: fn(PathBuf, oneshot::Sender<Result<usize, String>>) -> Control) -> Result<Response<RootReply>, Status> {
+        let directory = std::path::absolute(&request.directory).map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let (reply, replied) = oneshot::channel();
+        self.control
+            .send(control(directory, reply))
+            .map_err(|_| Status::unavailable("The watcher is shutting down"))?;
+        let entries = replied
+            .await
+            .map_err(|_| Status::unavailable("The watcher is shutting down"))?
+            .map_err(Status::failed_precondition)?;
+        Ok(Response::new(RootReply { entries: entries as u64 }))
+    }
+
+    fn query(&self, request: QueryRequest) -> QueryReply {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let entries = state
+            .merged_entries()
+            .filter(|entry| {
+                request.file.is_empty()
+                    || glob_match(&request.file, &source_path(&entry.command).display().to_string())
+                    || glob_match(&request.file, &entry.command.file)
+            })
+            .map(|entry| Entry {
+                directory: entry.command.directory.clone(),
+                command: entry.command.command.clone(),
+                file: entry.command.file.clone(),
+                output: entry.command.output.clone(),
+                source: entry.source.clone(),
+            })
+            .collect();
+        QueryReply { entries }
+    }
+
+    fn subscribe(&self) -> ChangeStream {
+        let changes = BroadcastStream::new(self.changes.subscribe()).filter_map(|change| {
+            let change = match change {
+                Ok(change) => change,
+                // Ends the stream, telling the subscriber to query the entries again
+                Err(BroadcastStreamRecvError::Lagged(missed)) => {
+                    return Some(Err(Status::data_loss(format!("Fell behind and missed {} changes", missed))));
+                }
+            };
+            let (kind, file) = match change {
+                MergeEvent::Added { file } => (Kind::Added, file),
+                MergeEvent::Updated { file } => (Kind::Modified, file),
+                MergeEvent::Removed { file } => (Kind::Removed, file),
+                MergeEvent::Merged { .. } | MergeEvent::Failed { .. } => return None,
+            };
+            Some(Ok(ChangeEvent { kind: kind as i32, file }))
+        });
+        Box::pin(changes)
+    }
+}
+
+impl NamedService for MergerService {
+    const NAME: &'static str = "ccm.v1.Merger";
+}
+
+impl<B> Service<http::Request<B>> for MergerService
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let service = self.clone();
+        match request.uri().path() {
+            "/ccm.v1.Merger/AddRoot" => {
+                Box::pin(async move { Ok(Grpc::new(ProstCodec::default()).unary(AddRoot(service), request).await) })
+            }
+            "/ccm.v1.Merger/RemoveRoot" => {
+                Box::pin(async move { Ok(Grpc::new(ProstCodec::default()).unary(RemoveRoot(service), request).await) })
+            }
+            "/ccm.v1.Merger/Query" => {
+                Box::pin(async move { Ok(Grpc::new(ProstCodec::default()).unary(Query(service), request).await) })
+            }
+            "/ccm.v1.Merger/Subscribe" => Box::pin(async move {
+                Ok(Grpc::new(ProstCodec::default()).server_streaming(Subscribe(service), request).await)
+            }),
+            _ => Box::pin(async move { Ok(Status::unimplemented("Unknown method").into_http()) }),
+        }
+    }
+}
+
+struct AddRoot(MergerService);
+
+impl UnaryService<RootRequest> for AddRoot {
+    type Response = RootReply;
+    type Future = BoxFuture<Response<RootReply>, Status>;
+
+    fn call(&mut self, request: Request<RootRequest>) -> Self::Future {
+        let service = self.0.clone();
+        Box::pin(async move { service.root(request.into_inner(), Control::AddRoot).await })
+    }
+}
+
+struct RemoveRoot(MergerService);
+
+impl UnaryService<RootRequest> for RemoveRoot {
+    type Response = RootReply;
+    type Future = BoxFuture<Response<RootReply>, Status>;
+
+    fn call(&mut self, request: Request<RootRequest>) -> Self::Future {
+        let service = self.0.clone();
+        Box::pin(async move { service.root(request.into_inner(), Control::RemoveRoot).await })
+    }
+}
+
+struct Query(MergerService);
+
+impl UnaryService<QueryRequest> for Query {
+    type Response = QueryReply;
+    type Future = BoxFuture<Response<QueryReply>, Status>;
+
+    fn call(&mut self, request: Request<QueryRequest>) -> Self::Future {
+        let reply = self.0.query(request.into_inner());
+        Box::pin(async move { Ok(Response::new(reply)) })
+    }
+}
+
+struct Subscribe(MergerService);
+
+impl ServerStreamingService<SubscribeRequest> for Subscribe {
+    type Response = ChangeEvent;
+    type ResponseStream = ChangeStream;
+    type Future = BoxFuture<Response<ChangeStream>, Status>;
+
+    fn call(&mut self, _request: Request<SubscribeRequest>) -> Self::Future {
+        let changes = self.0.subscribe();
+        Box::pin(async move { Ok(Response::new(changes)) })
+    }
+}
@@ -0,0 +1,83 @@
+use crate::compiler::{compiler_index, executable_name};
+use crate::paths::normalize;
+use crate::shell::split_args;
+use crate::{write_atomically, Entry};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Options taking an include directory, either joined (`-Iinc`) or as the next argument
+const INCLUDE_OPTIONS: &[&str] = &["-isystem", "-iquote", "-idirafter", "-I"];
+
+/// Options defining or undefining a macro, likewise
+const DEFINE_OPTIONS: &[&str] = &["-D"];
+const UNDEFINE_OPTIONS: &[&str] = &["-U"];
+
+/// The same options as spelled for MSVC-style compilers, which also accept the above
+const MSVC_INCLUDE_OPTIONS: &[&str] = &["-isystem", "-iquote", "-idirafter", "-I", "/I"];
+const MSVC_DEFINE_OPTIONS: &[&str] = &["-D", "/D"];
+const MSVC_UNDEFINE_OPTIONS: &[&str] = &["-U", "/U"];
+
+/// Compact mapping of each source file to its include directories and defines, written next to
+/// the output for tools that don't parse compile commands
+pub struct IncludeMap {
+    path: PathBuf,
+}
+
+#[derive(Serialize, Default)]
+pub struct FileSettings {
+    pub includes: Vec<String>, // Absolute, in search order
+    pub defines: Vec<String>,  // `NAME` or `NAME=VALUE`, as given
+}
+
+impl IncludeMap {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        IncludeMap { path: path.into() }
+    }
+
+    /// Write the map for the given merged entries
+    pub fn write(&self, entries: &[&Entry]) -> std::io::Result<()> {
+        let files: BTreeMap<String, FileSettings> = entries
+            .iter()
+            .map(|entry| {
+                let command = &entry.command;
+                let directory = Path::new(&command.directory);
+                let file = normalize(&directory.join(&command.file)).display().to_string();
+                (file, settings(&split_args(&command.command), directory))
+            })
+            .collect();
+        write_atomically(&self.path, &serde_json::to_string_pretty(&files)?)
+    }
+}
+
+/// Include directories and defines of a command's arguments
+pub fn settings(args: &[String], directory: &Path) -> FileSettings {
+    let msvc = compiler_index(args)
+        .is_some_and(|index| matches!(executable_name(&args[index]).to_ascii_lowercase().as_str(), "cl" | "clang-cl"));
+    let (include_options, define_options, undefine_options) = match msvc {
+        true => (MSVC_INCLUDE_OPTIONS, MSVC_DEFINE_OPTIONS, MSVC_UNDEFINE_OPTIONS),
+        false => (INCLUDE_OPTIONS, DEFINE_OPTIONS, UNDEFINE_OPTIONS),
+    };
+    let mut settings = FileSettings::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |options: &[&str]| {
+            let option = options.iter().find(|option| arg.starts_with(*option))?;
+            match arg.len() == option.len() {
+                true => args.next().cloned(),
+                false => Some(arg[option.len()..].to_string()),
+            }
+        };
+        if let Some(include) = value(include_options) {
+            let include = normalize(&directory.join(include)).display().to_string();
+            if !settings.includes.contains(&include) {
+                settings.includes.push(include);
+            }
+        } else if let Some(define) = value(define_options) {
+            settings.defines.push(define);
+        } else if let Some(name) = value(undefine_options) {
+            settings.defines.retain(|define| define.split('=').next() != Some(name.as_str()));
+        }
+    }
+    settings
+}
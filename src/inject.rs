@@ -0,0 +1,92 @@
+use crate::compiler::{compiler_index, glob_match, matches_compiler};
+use anyhow::{bail, Result};
+
+/// An option appended to entries whose compiler matches a pattern, unless already present
+#[derive(Debug)]
+pub struct Injection {
+    pattern: Option<String>, // Glob over the compiler's executable name; all entries if unset
+    option: &'static str,
+    value: String,
+}
+
+impl Injection {
+    /// `--target=TRIPLE` from `[PATTERN=]TRIPLE`
+    pub fn target(spec: &str) -> Result<Self> {
+        Self::parse(spec, "--target")
+    }
+
+    /// `--sysroot=PATH` from `[PATTERN=]PATH`
+    pub fn sysroot(spec: &str) -> Result<Self> {
+        Self::parse(spec, "--sysroot")
+    }
+
+    fn parse(spec: &str, option: &'static str) -> Result<Self> {
+        let (pattern, value) = match spec.split_once('=') {
+            Some((pattern, value)) => (Some(pattern.to_string()), value),
+            None => (None, spec),
+        };
+        if value.is_empty() {
+            bail!("Missing value in '{}', expected [PATTERN=]VALUE", spec);
+        }
+        Ok(Injection {
+            pattern,
+            option,
+            value: value.to_string(),
+        })
+    }
+
+    /// Append the option to a command's arguments, returning whether they changed
+    pub fn apply(&self, args: &mut Vec<String>) -> bool {
+        let Some(compiler) = compiler_index(args) else {
+            return false;
+        };
+        if let Some(pattern) = &self.pattern {
+            if !matches_compiler(pattern, &args[compiler]) {
+                return false;
+            }
+        }
+        // Clang also accepts the single-dash spelling of --target
+        let short = &self.option[1..];
+        let present = args.iter().any(|arg| {
+            [self.option, short]
+                .iter()
+                .any(|option| arg == option || arg.strip_prefix(option).is_some_and(|rest| rest.starts_with('=')))
+        });
+        if present {
+            return false;
+        }
+        args.push(format!("{}={}", self.option, self.value));
+        true
+    }
+}
+
+/// Global argument tweaks in the style of clang-tidy's `--extra-arg` options
+#[derive(Debug, Default)]
+pub struct ArgEdits {
+    pub before: Vec<String>, // Inserted right after the compiler
+    pub after: Vec<String>,  // Appended to the command
+    pub remove: Vec<String>, // Globs matched against each whole argument
+}
+
+impl ArgEdits {
+    pub fn is_empty(&self) -> bool {
+        self.before.is_empty() && self.after.is_empty() && self.remove.is_empty()
+    }
+
+    /// Remove matching arguments, then add the extra ones, returning whether anything changed
+    pub fn apply(&self, args: &mut Vec<String>) -> bool {
+        let Some(compiler) = compiler_index(args) else {
+            return false;
+        };
+        let before = args.len();
+        let mut index = 0;
+        args.retain(|arg| {
+            index += 1;
+            index <= compiler + 1 || !self.remove.iter().any(|pattern| glob_match(pattern, arg))
+        });
+        let removed = args.len() != before;
+        args.splice(compiler + 1..compiler + 1, self.before.iter().cloned());
+        args.extend(self.after.iter().cloned());
+        removed || !self.before.is_empty() || !self.after.is_empty()
+    }
+}
@@ -0,0 +1,120 @@
+use crate::compiler::{executable_name, is_compiler};
+use crate::wrap;
+use std::collections::HashSet;
+use std::ffi::CString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Run a build command under ptrace, recording every compiler it (or any process below it)
+/// executes in the spool directory; returns the build's exit code
+pub fn run(spool: Option<&Path>, command: &[String]) -> i32 {
+    let Some(spool) = spool.map(Path::to_path_buf).or_else(|| std::env::var_os(wrap::SPOOL_VAR).map(PathBuf::from)) else {
+        error!("Error: No spool directory, set --spool or {}", wrap::SPOOL_VAR);
+        return 2;
+    };
+    if let Err(e) = wrap::prepare(&spool) {
+        error!("Error: {}", e);
+        return 2;
+    }
+    let Ok(argv) = command.iter().map(|arg| CString::new(arg.as_bytes())).collect::<Result<Vec<_>, _>>() else {
+        error!("Error: The build command contains a NUL byte");
+        return 2;
+    };
+    let Some(program) = argv.first() else {
+        error!("Error: No build command to run");
+        return 2;
+    };
+    let mut pointers: Vec<*const libc::c_char> = argv.iter().map(|arg| arg.as_ptr()).collect();
+    pointers.push(std::ptr::null());
+
+    // SAFETY: the child only calls async-signal-safe functions between fork and exec
+    let child = unsafe { libc::fork() };
+    if child < 0 {
+        error!("Error: Failed to start the build: {}", std::io::Error::last_os_error());
+        return 2;
+    }
+    if child == 0 {
+        unsafe {
+            libc::ptrace(libc::PTRACE_TRACEME, 0, 0, 0);
+            libc::raise(libc::SIGSTOP); // Wait for the tracer to set its options
+            libc::execvp(program.as_ptr(), pointers.as_ptr());
+            libc::_exit(127);
+        }
+    }
+    info!("Intercepting compilers run by: {}", command.join(" "));
+    let (exit_code, recorded) = trace(child, &spool);
+    info!("Recorded {} compiler invocations in {}", recorded, spool.display());
+    exit_code
+}
+
+/// Follow the build and its descendants until all have exited, returning the build's exit code
+/// and the number of compilations recorded
+fn trace(build: libc::pid_t, spool: &Path) -> (i32, usize) {
+    let options = libc::PTRACE_O_TRACEEXEC
+        | libc::PTRACE_O_TRACEFORK
+        | libc::PTRACE_O_TRACEVFORK
+        | libc::PTRACE_O_TRACECLONE
+        | libc::PTRACE_O_EXITKILL;
+    let mut started: HashSet<libc::pid_t> = HashSet::new(); // Processes past their initial stop
+    let mut exit_code = 1;
+    let mut recorded = 0;
+    loop {
+        let mut status = 0;
+        let pid = unsafe { libc::waitpid(-1, &mut status, libc::__WALL) };
+        if pid < 0 {
+            break; // No traced processes left
+        }
+        if libc::WIFEXITED(status) || libc::WIFSIGNALED(status) {
+            if pid == build {
+                exit_code = match libc::WIFEXITED(status) {
+                    true => libc::WEXITSTATUS(status),
+                    false => 128 + libc::WTERMSIG(status),
+                };
+            }
+            started.remove(&pid);
+            continue;
+        }
+        if !libc::WIFSTOPPED(status) {
+            continue;
+        }
+        let signal = libc::WSTOPSIG(status);
+        let event = status >> 16;
+        let mut deliver = 0;
+        match event {
+            libc::PTRACE_EVENT_EXEC => recorded += usize::from(record_exec(pid, spool)),
+            0 if signal == libc::SIGSTOP && started.insert(pid) => {
+                // Descendants inherit the options, setting them again is harmless
+                unsafe { libc::ptrace(libc::PTRACE_SETOPTIONS, pid, 0, options) };
+            }
+            0 if signal != libc::SIGTRAP => deliver = signal, // A signal meant for the process
+            _ => {} // Forks and clones: the new process is traced and reports its own initial stop
+        }
+        unsafe { libc::ptrace(libc::PTRACE_CONT, pid, 0, deliver) };
+    }
+    (exit_code, recorded)
+}
+
+/// Record the program a process just executed if it is a compiler, reading its arguments and
+/// working directory from /proc; returns whether a compilation was recorded
+fn record_exec(pid: libc::pid_t, spool: &Path) -> bool {
+    let Ok(cmdline) = fs::read(format!("/proc/{}/cmdline", pid)) else {
+        return false;
+    };
+    let args: Vec<String> = cmdline
+        .strip_suffix(&[0])
+        .unwrap_or(&cmdline)
+        .split(|byte| *byte == 0)
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect();
+    // Clang's driver runs itself again with -cc1 for the actual compilation
+    if !args.first().is_some_and(|program| is_compiler(executable_name(program))) || args.iter().any(|arg| arg == "-cc1") {
+        return false;
+    }
+    let Ok(directory) = fs::read_link(format!("/proc/{}/cwd", pid)) else {
+        return false;
+    };
+    wrap::record(spool, &args, &directory).unwrap_or_else(|e| {
+        warn!("Warning: Failed to record the invocation in {}: {}", spool.display(), e);
+        false
+    })
+}
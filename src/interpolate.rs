@@ -0,0 +1,160 @@
+use crate::orphans::SOURCE_EXTENSIONS;
+use crate::paths::normalize;
+use crate::shell::{join_args, split_args};
+use crate::{parse, source_path, CompileCommand, Entry};
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Options whose value is a file named after the source, such as its object or dependency file
+const OUTPUT_OPTIONS: &[&str] = &["-o", "-MF", "-MT", "-MQ"];
+
+/// Best-guess entries for sources that have none but sit next to sources that do, borrowing the
+/// command of the most similar sibling as clangd interpolates, until a build gives them their own
+#[derive(Default)]
+pub struct Interpolation {
+    reported: RefCell<HashSet<String>>, // Files already logged as interpolated
+    listings: RefCell<HashMap<PathBuf, (SystemTime, Vec<String>)>>, // Sources of each directory, as of its modification time
+}
+
+impl Interpolation {
+    /// Entries for the sources without one in the directories of the entries about to be written
+    pub fn entries(&self, entries: &[&Entry]) -> Vec<Entry> {
+        let mut directories: BTreeMap<PathBuf, Vec<(String, &Entry)>> = BTreeMap::new();
+        for entry in entries {
+            let path = normalize(&source_path(&entry.command));
+            if let (Some(dir), Some(name)) = (path.parent(), path.file_name()) {
+                directories.entry(dir.to_path_buf()).or_default().push((name.to_string_lossy().into_owned(), entry));
+            }
+        }
+        let mut interpolated = Vec::new();
+        for (dir, siblings) in &mut directories {
+            siblings.sort_by(|a, b| a.0.cmp(&b.0));
+            let Some(mut names) = self.sources(dir) else {
+                continue;
+            };
+            names.retain(|name| !siblings.iter().any(|(sibling, _)| sibling == name));
+            for name in names {
+                let Some((_, sibling)) = siblings.iter().max_by_key(|(sibling, _)| {
+                    (language(sibling) == language(&name), common_prefix(stem(sibling), stem(&name)), Reverse(sibling.as_str()))
+                }) else {
+                    continue;
+                };
+                let entry = borrow(sibling, &name);
+                let path = dir.join(&name).display().to_string();
+                if parse::is_excluded(&path) || parse::is_excluded(&entry.command.file) {
+                    continue;
+                }
+                if self.reported.borrow_mut().insert(path.clone()) {
+                    info!("Interpolated an entry for {} from {}", path, sibling.command.file);
+                }
+                interpolated.push(entry);
+            }
+        }
+        interpolated
+    }
+
+    /// Read the sources of a directory again on the next write, as after a watch event for it or
+    /// a file in it
+    pub fn forget(&self, path: &Path) {
+        let mut listings = self.listings.borrow_mut();
+        listings.remove(path);
+        if let Some(dir) = path.parent() {
+            listings.remove(dir);
+        }
+    }
+
+    /// Names of the source files in a directory, sorted, listed again only once it changed
+    fn sources(&self, dir: &Path) -> Option<Vec<String>> {
+        let modified = fs::metadata(dir).and_then(|metadata| metadata.modified()).ok()?;
+        let mut listings = self.listings.borrow_mut();
+        if let Some((_, names)) = listings.get(dir).filter(|(listed, _)| *listed == modified) {
+            return Some(names.clone());
+        }
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .ok()?
+            .flatten()
+            .filter(|file| file.file_type().is_ok_and(|file_type| file_type.is_file()))
+            .map(|file| file.file_name().to_string_lossy().into_owned())
+            .filter(|name| is_source(name))
+            .collect();
+        names.sort();
+        listings.insert(dir.to_path_buf(), (modified, names.clone()));
+        Some(names)
+    }
+}
+
+/// The sibling's entry with its file, and what is named after it, renamed to the given source
+fn borrow(sibling: &Entry, name: &str) -> Entry {
+    let file = Path::new(&sibling.command.file);
+    let from = file.file_name().unwrap_or_default().to_string_lossy();
+    let (from_stem, to_stem) = (stem(&from), stem(name));
+    let rename_output = |output: &str| {
+        let path = Path::new(output);
+        match path.file_name().map(|file_name| file_name.to_string_lossy()) {
+            Some(file_name) if file_name.starts_with(&format!("{}.", from)) => {
+                path.with_file_name(format!("{}{}", name, &file_name[from.len()..])).display().to_string()
+            }
+            Some(file_name) if file_name.starts_with(&format!("{}.", from_stem)) => {
+                path.with_file_name(format!("{}{}", to_stem, &file_name[from_stem.len()..])).display().to_string()
+            }
+            _ => output.to_string(),
+        }
+    };
+    let args = split_args(&sibling.command.command);
+    let args: Vec<String> = args
+        .iter()
+        .enumerate()
+        .map(|(index, arg)| {
+            let path = Path::new(arg);
+            if path.file_name().is_some_and(|file_name| *file_name == *from) {
+                path.with_file_name(name).display().to_string()
+            } else if index > 0 && OUTPUT_OPTIONS.contains(&args[index - 1].as_str()) {
+                rename_output(arg)
+            } else {
+                arg.clone()
+            }
+        })
+        .collect();
+    Entry {
+        command: CompileCommand {
+            command: join_args(&args),
+            file: file.with_file_name(name).display().to_string(),
+            output: sibling.command.output.as_deref().map(rename_output),
+            ..sibling.command.clone()
+        },
+        priority: sibling.priority,
+        source: sibling.source.clone(),
+        ingested_at: sibling.ingested_at,
+        derived_from: Some(sibling.command.file.clone()),
+        unity_members: None,
+        interpolated: true,
+    }
+}
+
+fn is_source(name: &str) -> bool {
+    Path::new(name).extension().is_some_and(|extension| SOURCE_EXTENSIONS.iter().any(|source| extension == *source))
+}
+
+/// The language a source is compiled as, going by its extension
+fn language(name: &str) -> &'static str {
+    match Path::new(name).extension().and_then(|extension| extension.to_str()) {
+        Some("c") => "c",
+        Some("m") => "objective-c",
+        Some("mm") => "objective-c++",
+        Some("cu") => "cuda",
+        _ => "c++",
+    }
+}
+
+fn stem(name: &str) -> &str {
+    name.rsplit_once('.').map_or(name, |(stem, _)| stem)
+}
+
+/// Length of the common start of two names, which similar sources tend to share (`foo.c`, `foo_test.c`)
+fn common_prefix(a: &str, b: &str) -> usize {
+    a.chars().zip(b.chars()).take_while(|(a, b)| a == b).count()
+}
@@ -0,0 +1,2265 @@
+#[macro_use]
+mod log;
+mod aosp;
+mod archive;
+mod auto;
+mod buck2;
+mod builtins;
+mod cap;
+mod cargo;
+mod clangd;
+mod clock;
+mod cmake;
+mod compiler;
+mod compressed;
+mod config;
+mod desktop;
+mod doctor;
+mod events;
+mod export;
+mod external;
+mod filelist;
+mod format;
+mod generated;
+mod gn;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod includes;
+mod inject;
+#[cfg(target_os = "linux")]
+mod intercept;
+mod interpolate;
+mod limits;
+mod lint;
+mod lock;
+mod make;
+pub mod merger;
+mod meta;
+mod mmap;
+mod nix;
+mod orphans;
+mod outputs;
+mod overrides;
+mod parse;
+mod paths;
+mod pch;
+mod pipe;
+mod platformio;
+mod plugin;
+mod policy;
+mod porcelain;
+mod prefix_map;
+mod presets;
+mod remote;
+mod replay;
+mod roots;
+mod script;
+#[cfg(windows)]
+mod service;
+mod settings;
+mod shell;
+mod spill;
+mod sse;
+mod stale;
+mod sysroot;
+mod systemd;
+mod tidy;
+mod timing;
+mod toolchain;
+mod tui;
+mod transform;
+mod unity;
+mod unordered;
+mod verify;
+mod vscode;
+mod watch;
+mod wrap;
+
+use builtins::BuiltinIncludes;
+use cap::{CapAction, EntryCap};
+use clangd::ClangdRefresh;
+use clock::{Clock, ManualClock, SystemClock};
+use cmake::{BuildTrees, Reconfiguration};
+use compiler::CompilerMap;
+use desktop::DesktopNotifier;
+use external::{ExternalChangePolicy, Fingerprint, Fingerprinting, OutputGuard};
+use filelist::FileList;
+use format::OutputFormat;
+use generated::{GeneratedSources, SourceMap};
+use includes::IncludeMap;
+use inject::{ArgEdits, Injection};
+use interpolate::Interpolation;
+use lint::{FlagLint, MissingCompilers};
+use lock::FileLock;
+use log::ColorChoice;
+use merger::MergeEvent;
+use meta::Sidecar;
+use outputs::{OutputDedup, OutputDedupMode};
+use overrides::Overrides;
+use nix::NixProfiles;
+use paths::{PathMap, PathStyle};
+use pch::PchMode;
+use pipe::PipeThrough;
+use plugin::WasmPlugin;
+use prefix_map::PrefixMapMode;
+use policy::{Candidate, MergePolicy, PreferPriority, Resolution};
+use presets::WorkspacePreset;
+use remote::{HttpSource, RemoteSource, SshSource, Update};
+use replay::Replay;
+use roots::WatchRoot;
+use script::Script;
+use settings::Settings;
+use shell::{split_args, CommandSyntax};
+use spill::MemoryBudget;
+use stale::Staleness;
+use sysroot::{SysrootPreset, SysrootRemap};
+use systemd::Systemd;
+use tidy::TidyRunner;
+use timing::{Phase, Timings};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant, SystemTime};
+use anyhow::Result;
+use clap::{CommandFactory, Parser, Subcommand};
+use transform::Transforms;
+use unordered::UnorderedFlags;
+use verify::Drift;
+use watch::WatchOptions;
+
+/// Command-line arguments
+#[derive(Parser, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[command(args_override_self = true)] // Options of the command line override those of the config file
+#[command(name = "Compile Commands Merger", version = env!("CARGO_PKG_VERSION"), author = "Ligo George", about = "Merges compile commands into a single file and monitors for updates.")]
+struct Args {
+    /// Directories to scan, or .tar.gz/.zip archives of build trees (e.g. CI artifacts); `-` reads them from stdin, one per line
+    #[arg(short, long, value_delimiter = ',')]
+    directories: Vec<String>,
+
+    /// Also scan a directory with settings of its own, given as a JSON object of the keys of a `[[root]]` section of the config file, e.g. '{"path": "build-arm", "priority": 10, "poll": 5}' (repeatable)
+    #[arg(long, value_name = "JSON", value_parser = roots::parse)]
+    root: Vec<WatchRoot>,
+
+    /// Only merge databases of configured CMake build trees (with a CMakeCache.txt) where a directory has any, following their reconfigurations
+    #[arg(long)]
+    cmake_trees: bool,
+
+    /// Find the build directories under ROOT (CMake, Meson and Ninja build trees, Make trees with a database) and merge them, listing what was found
+    #[arg(long, value_name = "ROOT")]
+    auto: Option<String>,
+
+    /// Merge the build directories of the configure presets in DIR's CMakePresets.json and CMakeUserPresets.json [default DIR: .], naming them for --configs and --prefer-presets
+    #[arg(long, value_name = "DIR", num_args = 0..=1, default_missing_value = ".")]
+    cmake_presets: Option<String>,
+
+    /// Presets whose entries win over those of other databases for the same file, the first one most
+    #[arg(long, value_delimiter = ',', value_name = "NAMES", requires = "cmake_presets")]
+    prefer_presets: Vec<String>,
+
+    /// VS Code .code-workspace file (or a folder's .vscode/settings.json) whose folders, and the build directories and databases their settings point at, are merged; changes to it add and remove roots
+    #[arg(long, value_name = "FILE")]
+    code_workspace: Option<PathBuf>,
+
+    /// Databases or archives to watch and merge directly, whatever their names, without scanning
+    #[arg(long, value_delimiter = ',')]
+    files: Vec<String>,
+
+    /// Buck2 targets whose compilation database is generated with buck2 bxl and merged (e.g. //app/...)
+    #[arg(long, value_name = "TARGET", value_delimiter = ',')]
+    buck2: Vec<String>,
+
+    /// Logs of `cargo build -vv`, from which the C/C++ compiler invocations of cc-rs build scripts are merged
+    #[arg(long, value_name = "LOG")]
+    cargo_log: Vec<PathBuf>,
+
+    /// Make-based build directories whose compile commands are taken from `make -nB` and merged
+    #[arg(long, value_name = "DIR")]
+    make: Vec<PathBuf>,
+
+    /// Target given to make by --make, instead of the default goal
+    #[arg(long, value_name = "TARGET")]
+    make_target: Option<String>,
+
+    /// Spool directory where `wrap` and ccm-wrap record compiler invocations, watched and merged
+    #[arg(long, value_name = "DIR")]
+    spool: Option<PathBuf>,
+
+    /// Output file
+    #[arg(short, long, default_value = "compile_commands.json")]
+    output: String,
+
+    /// Format of the output: the standard array, one entry per line, or the array with `arguments` lists
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = OutputFormat::Json)]
+    output_format: OutputFormat,
+
+    /// Also write the merged entries in FORMAT to PATH on every write, e.g. ndjson=compile_commands.ndjson (repeatable)
+    #[arg(long = "also-output", value_name = "FORMAT=PATH")]
+    #[serde(rename = "also-output")]
+    also_outputs: Vec<String>,
+
+    /// Input file, also read gzip or zstd compressed as INPUT.gz or INPUT.zst
+    #[arg(short, long, default_value = "compile_commands.json")]
+    input: String,
+
+    /// Also merge link command databases found under the directories into this file, watched alike
+    #[arg(long, value_name = "FILE")]
+    link_output: Option<String>,
+
+    /// Link command database file
+    #[arg(long, default_value = "link_commands.json")]
+    link_input: String,
+
+    /// Drop entries of source files matching the glob PATTERN while parsing (repeatable, e.g. "*/third_party/*")
+    #[arg(long, value_name = "PATTERN")]
+    exclude: Vec<String>,
+
+    /// Drop entries of generated sources: Qt moc/rcc outputs, CMake try_compile artifacts, and protobuf, bison and flex outputs in a build tree
+    #[arg(long)]
+    drop_generated: bool,
+
+    /// Also drop entries of sources matching the glob PATTERN as generated (repeatable, e.g. "*/build/gen/*")
+    #[arg(long, value_name = "PATTERN")]
+    generated: Vec<String>,
+
+    /// Give the entry of a generated source matching the glob PATTERN to its checked-in template instead of dropping it, if it exists relative to the entry's directory ({name} and {base} stand for the generated file's name and its name up to the first dot, e.g. "*/moc_*.cpp=../src/{base}.h")
+    #[arg(long, value_name = "PATTERN=TEMPLATE")]
+    generated_template: Vec<String>,
+
+    /// Give entries of files a build copied, configured or generated into BUILD to the file under SOURCE they come from, where one exists (BUILD=SOURCE, repeatable)
+    #[arg(long, value_name = "BUILD=SOURCE")]
+    map_generated: Vec<String>,
+
+    /// Remote compile_commands.json URLs to fetch and poll
+    #[arg(short, long, value_delimiter = ',')]
+    urls: Vec<String>,
+
+    /// Remote directory trees to scan over SSH (user@host:/path)
+    #[arg(short, long, value_delimiter = ',')]
+    remote: Vec<String>,
+
+    /// Seconds between polls of remote sources
+    #[arg(long, default_value_t = 30)]
+    poll_interval: u64,
+
+    /// Translate paths between WSL (/mnt/c/...) and Windows (C:\...) forms
+    #[arg(long, value_enum)]
+    translate_paths: Option<PathStyle>,
+
+    /// Quoting of the command strings of entries, when splitting them into arguments and joining them back: POSIX shell quoting, or the CommandLineToArgvW rules used on Windows
+    #[arg(long, value_enum, value_name = "SYNTAX", default_value_t = CommandSyntax::Auto)]
+    command_syntax: CommandSyntax,
+
+    /// Rewrite paths starting with FROM to start with TO, e.g. an archived CI build tree to the local checkout (repeatable)
+    #[arg(long, value_name = "FROM=TO")]
+    map_path: Vec<String>,
+
+    /// Rewrite /nix/store paths to where a profile, result or GC-root symlink exposes them, keeping entries stable across rebuilds (repeatable)
+    #[arg(long, value_name = "PATH")]
+    nix_profile: Vec<String>,
+
+    /// Rewrite paths through the -ffile-prefix-map/-fdebug-prefix-map options of entries to the side that exists, or strip the options
+    #[arg(long, value_enum, value_name = "MODE")]
+    prefix_maps: Option<PrefixMapMode>,
+
+    /// Rewrite Yocto/Buildroot build-tree sysroots to a stable SDK location
+    #[arg(long, value_enum, requires = "sdk_root")]
+    sysroot_preset: Option<SysrootPreset>,
+
+    /// Stable SDK location used by --sysroot-preset
+    #[arg(long)]
+    sdk_root: Option<String>,
+
+    /// Sysroot mapping overrides (LAYOUT=TEMPLATE, with {sdk} and {arch} placeholders)
+    #[arg(long, value_delimiter = ',')]
+    sysroot_map: Vec<String>,
+
+    /// Discover, sanitize and prioritize the databases of an embedded SDK, GN, colcon, PlatformIO or AOSP workspace
+    #[arg(long, value_enum)]
+    workspace_preset: Option<WorkspacePreset>,
+
+    /// Merge the package databases of a ROS colcon or catkin workspace (build/<package>/), following packages as they are built or removed
+    #[arg(long, value_name = "WS", conflicts_with = "workspace_preset")]
+    colcon_workspace: Option<String>,
+
+    /// Only merge the databases of these PlatformIO environments (.pio/build/<env>/)
+    #[arg(long, value_delimiter = ',', value_name = "NAMES")]
+    pio_envs: Vec<String>,
+
+    /// Only merge the AOSP builds of these lunch targets (PRODUCT[-RELEASE-VARIANT]), the first winning where they share sources
+    #[arg(long, value_delimiter = ',', value_name = "TARGETS")]
+    lunch_targets: Vec<String>,
+
+    /// Append --target=TRIPLE to entries, or only to those whose compiler matches the glob PATTERN
+    #[arg(long, value_name = "[PATTERN=]TRIPLE")]
+    add_target: Vec<String>,
+
+    /// Append --sysroot=PATH to entries, or only to those whose compiler matches the glob PATTERN
+    #[arg(long, value_name = "[PATTERN=]PATH")]
+    add_sysroot: Vec<String>,
+
+    /// Translate commands of armcc, armclang, IAR iccarm and TI cl430/cl2000 into clang equivalents
+    #[arg(long)]
+    translate_toolchains: bool,
+
+    /// Rewrite compilers to the absolute path they resolve to through PATH and symlinks (e.g. cc to /usr/bin/gcc-13)
+    #[arg(long)]
+    resolve_compilers: bool,
+
+    /// Replace compilers whose name or path matches the glob PATTERN (PATTERN=COMPILER, repeatable)
+    #[arg(long, value_name = "PATTERN=COMPILER")]
+    compiler_map: Vec<String>,
+
+    /// Argument appended to every command (repeatable)
+    #[arg(long, allow_hyphen_values = true, value_name = "ARG")]
+    extra_arg: Vec<String>,
+
+    /// Argument inserted right after the compiler in every command (repeatable)
+    #[arg(long, allow_hyphen_values = true, value_name = "ARG")]
+    extra_arg_before: Vec<String>,
+
+    /// Remove arguments matching the glob PATTERN from every command (repeatable)
+    #[arg(long, allow_hyphen_values = true, value_name = "PATTERN")]
+    remove_arg: Vec<String>,
+
+    /// Resolve relative -I/-isystem/-iquote/-idirafter directories against each entry's directory
+    #[arg(long)]
+    absolute_includes: bool,
+
+    /// Run each distinct compiler once with -E -v and append the include directories it searches by default as -isystem, for machines without the original toolchain
+    #[arg(long)]
+    resolve_builtin_includes: bool,
+
+    /// Set every entry's directory to ROOT, adjusting the relative paths in its command, file and output to it
+    #[arg(long, value_name = "ROOT")]
+    rebase_directory: Option<PathBuf>,
+
+    /// Remove precompiled-header flags (-include-pch, -fpch-*, /Yu, /Yc, /Fp)
+    #[arg(long)]
+    strip_pch: bool,
+
+    /// Like --strip-pch, but include the source header of a precompiled header where it can be found
+    #[arg(long, conflicts_with = "strip_pch")]
+    rewrite_pch: bool,
+
+    /// Report or collapse entries producing the same output object file
+    #[arg(long, value_enum, value_name = "MODE")]
+    dedup_outputs: Option<OutputDedupMode>,
+
+    /// Besides macros defined once, also ignore the order of these flags when telling whether an entry changed, for generators emitting them in any order
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "FLAGS")]
+    unordered_flags: Vec<UnorderedFlags>,
+
+    /// Number of entries above which the output is capped, as --on-max-entries says
+    #[arg(long, value_name = "N")]
+    max_entries: Option<usize>,
+
+    /// What to do when the merged entries exceed --max-entries
+    #[arg(long, value_enum, value_name = "ACTION", default_value_t = CapAction::Warn, requires = "max_entries")]
+    on_max_entries: CapAction,
+
+    /// Warn about entries with contradictory flags: several -std= or optimization levels, or a macro defined with different values
+    #[arg(long)]
+    lint_flags: bool,
+
+    /// Warn about compilers of entries that are not found on this machine, through PATH or as written
+    #[arg(long)]
+    check_compilers: bool,
+
+    /// Generate entries for the sources included by unity (jumbo) build files; the sidecar marks the unity file's entry with its number of sources
+    #[arg(long)]
+    expand_unity: bool,
+
+    /// Build configurations kept as separate merged states (NAME=DIR, e.g. debug=build-debug, or a preset NAME with --cmake-presets)
+    #[arg(long, value_delimiter = ',')]
+    configs: Vec<String>,
+
+    /// Write entry provenance to a sidecar file next to the output (e.g. compile_commands.meta.json)
+    #[arg(long)]
+    meta: bool,
+
+    /// After each write, also write a JSON map of each source file to its include directories and defines
+    #[arg(long, value_name = "FILE")]
+    include_map: Option<PathBuf>,
+
+    /// After each write, also write the merged source files to FILE, one per line (e.g. for ctags -L or cscope -i)
+    #[arg(long, value_name = "FILE")]
+    emit_filelist: Option<PathBuf>,
+
+    /// After each write, make clangd pick up the new database
+    #[arg(long)]
+    notify_clangd: bool,
+
+    /// With --notify-clangd, also drop clangd index shards of files whose commands changed
+    #[arg(long, requires = "notify_clangd")]
+    invalidate_clangd_index: bool,
+
+    /// After each merge, run clang-tidy on entries that were added or changed
+    #[arg(long)]
+    tidy_on_change: bool,
+
+    /// Extra arguments passed to clang-tidy by --tidy-on-change (e.g. "-checks=-*,bugprone-*")
+    #[arg(long, allow_hyphen_values = true, requires = "tidy_on_change", default_value = "")]
+    tidy_args: String,
+
+    /// Show a live status dashboard instead of log output while watching
+    #[arg(long)]
+    tui: bool,
+
+    /// Show desktop notifications when inputs fail to parse or the output fails to write
+    #[arg(long)]
+    desktop_notifications: bool,
+
+    /// Milliseconds to wait for related filesystem events before merging them in one write
+    #[arg(long, default_value_t = 100)]
+    debounce_ms: u64,
+
+    /// Seconds between full rescans of the watched directories, catching missed events (0 disables)
+    #[arg(long, default_value_t = 0)]
+    rescan_interval: u64,
+
+    /// Check the merged entries against a merge from scratch every SECS seconds while watching, healing any drift; without SECS, verify the existing output once and exit (status 1 on drift)
+    #[arg(long, value_name = "SECS", num_args = 0..=1, default_missing_value = "0")]
+    verify: Option<u64>,
+
+    /// Seconds that must pass between rewrites of the output, changes in between being written together (0 disables)
+    #[arg(long, value_name = "SECS", default_value_t = 0)]
+    min_write_interval: u64,
+
+    /// Seconds between checks that entries' source files still exist, dropping those of deleted files (0 disables)
+    #[arg(long, value_name = "SECS", default_value_t = 0)]
+    prune_missing: u64,
+
+    /// Also watch the source files of entries, marking those changed after their database as stale in the sidecar and dashboard
+    #[arg(long)]
+    watch_sources: bool,
+
+    /// Watch directories individually down to N levels below each directory, instead of recursively without limit
+    #[arg(long, value_name = "N")]
+    watch_depth: Option<usize>,
+
+    /// Apply the filesystem events recorded in FILE instead of watching, on a clock starting at the Unix epoch, and exit after the last
+    #[arg(long, value_name = "FILE")]
+    replay: Option<String>,
+
+    /// Serve the control and query API of proto/ccm.proto over gRPC on this address (e.g. 127.0.0.1:50051)
+    #[cfg(feature = "grpc")]
+    #[arg(long, value_name = "ADDR")]
+    grpc: Option<std::net::SocketAddr>,
+
+    /// Stream the entries each write adds, updates and removes as server-sent events over HTTP on this address (e.g. 127.0.0.1:8790)
+    #[arg(long, value_name = "ADDR")]
+    events: Option<std::net::SocketAddr>,
+
+    /// WASM modules rewriting or dropping each entry (exporting memory, alloc and transform)
+    #[arg(long = "plugin", value_name = "WASM")]
+    #[serde(rename = "plugin")]
+    plugins: Vec<String>,
+
+    /// Rhai script whose transform(entry) function edits or drops each entry
+    #[arg(long, value_name = "SCRIPT")]
+    script: Option<String>,
+
+    /// Program each database's entries are streamed through as JSON lines, printing the transformed entries back
+    #[arg(long, value_name = "CMD")]
+    pipe_through: Option<String>,
+
+    /// What to do when another program modified the output: overwrite it, or keep its added and edited entries
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = ExternalChangePolicy::Reassert)]
+    on_external_change: ExternalChangePolicy,
+
+    /// Parse databases of 16 MiB or more straight from a memory mapping rather than reading them (unsafe with generators truncating them in place)
+    #[arg(long)]
+    mmap: bool,
+
+    /// MiB of memory above which entries of configurations other than the active one are moved to disk until needed (0 disables; requires --configs)
+    #[arg(long, value_name = "MIB", default_value_t = 0, requires = "configs")]
+    max_memory: u64,
+
+    /// Drop entries of databases not refreshed within this age (e.g. 30d, 12h), for long-running watchers
+    #[arg(long, value_name = "AGE")]
+    entry_ttl: Option<String>,
+
+    /// Add best-guess entries for sources without one next to sources with one, borrowing the command of the most similar sibling until a build gives them their own; the sidecar marks them as interpolated
+    #[arg(long)]
+    interpolate: bool,
+
+    /// Write entries sorted by file and directory, so the output only changes where entries do
+    #[arg(long)]
+    sort: bool,
+
+    /// Hold an exclusive lock on <OUTPUT>.lock while writing, for tools that also write or read the output
+    #[arg(long)]
+    lock: bool,
+
+    /// Log the time taken by each phase of every merge (discovery, parsing, merging, serializing, writing) and the peak memory use
+    #[arg(long)]
+    timing: bool,
+
+    /// Only print errors, for running under editors and CI
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// Print a JSON object per event (merge-started, merged, error, pruned) on stdout, one per line, moving messages to stderr
+    #[arg(long, conflicts_with = "tui")]
+    porcelain: bool,
+
+    /// When to color warnings and errors
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// Print the effective configuration as JSON, after defaults and stdin directories are resolved, and exit
+    #[arg(long)]
+    print_config: bool,
+
+    /// Config file setting options by their long names, before those of the command line [default: ccm.toml if present]
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Apply the options of the config file's [profile.NAME] section over its top-level ones
+    #[arg(long, value_name = "NAME")]
+    profile: Option<String>,
+
+    #[command(subcommand)]
+    #[serde(skip)]
+    command: Option<Command>,
+}
+
+/// Subcommands controlling a running watcher
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Switch which configuration the output file reflects
+    UseConfig {
+        /// Configuration name, as given to --configs
+        name: String,
+    },
+    /// Repeat the initial merge of --directories and report the time taken by each phase
+    Bench {
+        /// Number of merges to time
+        #[arg(long, default_value_t = 5)]
+        iterations: usize,
+    },
+    /// Check that changes under --directories reach the watcher, diagnosing watch limits and filesystems
+    Doctor,
+    /// Run a compiler, recording the invocation in a spool directory the watcher follows; set as
+    /// `CC="compile_commands_merger wrap gcc"` or use the ccm-wrap binary as a compiler launcher
+    Wrap {
+        /// Spool directory to record in, instead of $CCM_SPOOL
+        #[arg(long, value_name = "DIR")]
+        spool: Option<PathBuf>,
+        /// Compiler and its arguments
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true)]
+        compiler: Vec<String>,
+    },
+    /// Run a build under ptrace, recording every compiler it executes in the spool directory
+    #[cfg(target_os = "linux")]
+    Intercept {
+        /// Spool directory to record in, instead of $CCM_SPOOL
+        #[arg(long, value_name = "DIR")]
+        spool: Option<PathBuf>,
+        /// Build command and its arguments
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true)]
+        command: Vec<String>,
+    },
+    /// Write a CSV or TSV table of a database's entries with their compiler, standard,
+    /// optimization level and flag counts broken out, for spreadsheets and BI tools
+    Export {
+        /// Database to export, instead of the output file
+        database: Option<PathBuf>,
+        /// Table format
+        #[arg(long, value_enum, default_value_t = export::TableFormat::Csv)]
+        format: export::TableFormat,
+    },
+    /// List the C/C++ sources under a source root that have no entry in the output, honoring
+    /// --exclude, to find subprojects not built or watched; exits with status 1 if there are any
+    Orphans {
+        /// Source tree to look for sources in
+        root: PathBuf,
+        /// Database to check, instead of the output file
+        #[arg(long)]
+        database: Option<PathBuf>,
+    },
+    /// Print a shell completion script
+    Completions {
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+    /// Install, control or run the watcher as a Windows service
+    #[cfg(windows)]
+    Service {
+        #[command(subcommand)]
+        action: service::ServiceAction,
+    },
+}
+
+/// Struct for compile_commands.json entry
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CompileCommand {
+    pub directory: String,
+    pub command: String,
+    pub file: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>, // Fields of other generators (e.g. `language`), kept as they are
+    #[serde(skip)]
+    pub original_compiler: Option<String>, // Compiler replaced by --compiler-map, recorded in the sidecar
+}
+
+/// A merged entry along with where and when it was ingested
+#[derive(Clone)]
+struct Entry {
+    command: CompileCommand,
+    priority: i32,
+    source: String, // Database the entry was read from
+    ingested_at: SystemTime,
+    derived_from: Option<String>, // Unity file the entry was generated from, or sibling it was interpolated from
+    unity_members: Option<usize>, // Number of sources the entry's unity file was expanded into
+    interpolated: bool, // Guessed from the sibling in `derived_from`, with --interpolate
+}
+
+impl Entry {
+    fn candidate(&self) -> Candidate<'_> {
+        Candidate {
+            command: &self.command,
+            source: &self.source,
+            priority: self.priority,
+            derived: self.derived_from.is_some(),
+        }
+    }
+}
+
+/// Global state for combined data
+struct CombinedState {
+    input_file: String,
+    output_file: String,
+    data: HashMap<String, Entry>, // Deduplicated entries keyed by `entry_key`, shared by all configurations
+    configs: Vec<(String, PathBuf)>, // Configuration names and their build directories
+    directory_priorities: Vec<(PathBuf, i32)>, // Priority of databases below directories, from --prefer-presets
+    config_data: HashMap<String, HashMap<String, Entry>>, // Entries of each configuration keyed by `entry_key`
+    active_config: Option<String>,
+    preset: Option<WorkspacePreset>,
+    transforms: Transforms,
+    sidecar: Option<Sidecar>,
+    include_map: Option<IncludeMap>, // Include directories and defines of each file, written alongside the output
+    file_list: Option<FileList>, // Source files, one per line, written alongside the output
+    clangd: Option<ClangdRefresh>,
+    tidy: Option<TidyRunner>,
+    last_written: Option<HashMap<String, u64>>, // `entry_hash` of each written entry, by file
+    database_hashes: HashMap<String, u64>, // Hash of the entries each database had when last merged
+    conflicts: VecDeque<String>, // Recent entries defined differently by several databases
+    notifier: Option<DesktopNotifier>,
+    dedup: bool, // Keep a single entry per file, rather than per distinct command
+    policy: Arc<dyn MergePolicy>, // Settles entries for the same file
+    output_dedup: Option<OutputDedup>,
+    entry_cap: Option<EntryCap>, // Limit on the number of entries written, with --max-entries
+    flag_lint: Option<FlagLint>, // Warns about contradictory flags in written entries
+    missing_compilers: Option<MissingCompilers>, // Warns about compilers of written entries not found here
+    staleness: Option<Staleness>, // Sources changed after their database, with --watch-sources
+    interpolation: Option<Interpolation>, // Entries guessed for sources without one, with --interpolate
+    overrides: Overrides, // .ccm.toml files found under the directories, applied before the transforms
+    roots: Vec<WatchRoot>, // Directories with settings of their own, with resolved paths
+    systemd: Option<Systemd>,
+    lock_output: bool, // Hold the output's lock file while writing it
+    sort_output: bool, // Write entries ordered by file rather than in hash order
+    output_format: OutputFormat,
+    also_outputs: Vec<(OutputFormat, PathBuf)>, // Written from the same entries as the output
+    output_guard: OutputGuard,
+    external_policy: ExternalChangePolicy,
+    files: Vec<PathBuf>, // Databases and archives given by path rather than found by scanning
+    cmake: Option<BuildTrees>, // Configured CMake build trees, when discovery prefers them
+    timings: Option<Timings>, // Time spent per phase, with --timing
+    memory_budget: Option<MemoryBudget>,
+    entry_ttl: Option<Duration>, // Age after which entries of databases not read again are dropped
+    changes: Option<tokio::sync::broadcast::Sender<MergeEvent>>, // Entries changed by each write, for subscribers
+    clock: Arc<dyn Clock>, // Time of ingestion and expiry, manual when replaying
+    settings: Arc<Settings>, // How its databases are read and entries compared, entered while working for it
+}
+
+/// Number of recent conflicts kept for display
+const MAX_RECENT_CONFLICTS: usize = 100;
+
+impl CombinedState {
+    /// Initialize an empty combined state; `scan` loads the databases
+    fn new(
+        input_file: &str,
+        output_file: &str,
+        configs: Vec<(String, PathBuf)>,
+        preset: Option<WorkspacePreset>,
+        transforms: Transforms,
+    ) -> Self {
+        CombinedState {
+            input_file: input_file.to_string(),
+            output_file: output_file.to_string(),
+            data: HashMap::new(),
+            active_config: configs.first().map(|(name, _)| name.clone()),
+            configs,
+            directory_priorities: Vec::new(),
+            config_data: HashMap::new(),
+            preset,
+            transforms,
+            sidecar: None,
+            include_map: None,
+            file_list: None,
+            clangd: None,
+            tidy: None,
+            last_written: None,
+            database_hashes: HashMap::new(),
+            conflicts: VecDeque::new(),
+            notifier: None,
+            dedup: true,
+            policy: Arc::new(PreferPriority),
+            output_dedup: None,
+            entry_cap: None,
+            flag_lint: None,
+            missing_compilers: None,
+            staleness: None,
+            interpolation: None,
+            overrides: Overrides::default(),
+            roots: Vec::new(),
+            systemd: None,
+            lock_output: false,
+            sort_output: false,
+            output_format: OutputFormat::Json,
+            also_outputs: Vec::new(),
+            output_guard: OutputGuard::default(),
+            external_policy: ExternalChangePolicy::Reassert,
+            files: Vec::new(),
+            cmake: None,
+            timings: None,
+            memory_budget: None,
+            entry_ttl: None,
+            changes: None,
+            clock: Arc::new(SystemClock),
+            settings: Arc::default(),
+        }
+    }
+
+    /// Merge every database found under the directories, and the archives among them
+    fn scan(&mut self, directories: &[String]) {
+        porcelain::event("merge-started", serde_json::json!({ "directories": directories }));
+        for dir in directories {
+            if archive::is_archive(Path::new(dir)) {
+                self.add_file(Path::new(dir));
+                continue;
+            }
+            let started = Instant::now();
+            self.overrides.discover(Path::new(dir));
+            let databases = self.discover(Path::new(dir));
+            self.record(Phase::Discovery, started);
+            for path in databases {
+                match self.read_timed(&path) {
+                    Ok(commands) => {
+                        info!(
+                            "Adding entries from: {} ({} entries)",
+                            path.display(),
+                            commands.len()
+                        );
+                        self.record_database_hash(&path, &commands);
+                        let priority = self.priority(&path);
+                        let config = self.config_for(&path);
+                        let origin = path.display().to_string();
+                        self.insert(&origin, commands, priority, config.as_deref());
+                    }
+                    Err(e) => error!("Error: Failed to read {}: {}", path.display(), e),
+                }
+            }
+        }
+    }
+
+    /// Read a changed override file again, returning the databases to merge again for it to
+    /// apply: all of them, as any may have entries of sources below it
+    fn reload_overrides(&mut self, path: &Path, directories: &[String]) -> Vec<PathBuf> {
+        info!("Overrides changed: {}", path.display());
+        self.overrides.reload(path);
+        self.database_hashes.clear(); // Their entries are the same, but not what they merge into
+        directories
+            .iter()
+            .flat_map(|dir| self.discover(Path::new(dir)))
+            .chain(self.files.iter().cloned())
+            .collect()
+    }
+
+    /// Find the databases under a watched directory
+    fn discover(&self, dir: &Path) -> Vec<PathBuf> {
+        let input_file = self.database_name(dir);
+        match self.preset {
+            Some(preset) => preset.discover(dir, input_file),
+            None => self
+                .cmake
+                .as_ref()
+                .and_then(|cmake| cmake.discover(dir, input_file))
+                .unwrap_or_else(|| find_compile_commands(dir, input_file)),
+        }
+    }
+
+    /// Name of the databases at a path, a glob where its root gives one
+    fn database_name(&self, path: &Path) -> &str {
+        match roots::containing(&self.roots, path) {
+            Some(root) => root.database_name(&self.input_file),
+            None => &self.input_file,
+        }
+    }
+
+    /// Compare the merged entries with a merge from scratch of the given parsed databases, keeping
+    /// the fresh merge if the output's entries differ; entries from elsewhere (remote sources,
+    /// edits of the output) and of unreadable databases carry over. Returns whether they drifted
+    fn verify(&mut self, parsed: Vec<(PathBuf, Result<Vec<CompileCommand>>)>, directories: &[String]) -> bool {
+        let incremental = verify::snapshot(self.merged_entries().map(|entry| &entry.command));
+        let saved_data = std::mem::take(&mut self.data);
+        let saved_configs = std::mem::take(&mut self.config_data);
+        let saved_hashes = self.database_hashes.clone();
+        let conflicts = self.conflicts.clone();
+        let mut rescanned = HashSet::new();
+        for (path, result) in parsed {
+            let Ok(commands) = result else {
+                continue; // Perhaps being written; its entries stay as they are
+            };
+            self.record_database_hash(&path, &commands);
+            let priority = self.priority(&path);
+            let config = self.config_for(&path);
+            self.insert(&path.display().to_string(), commands, priority, config.as_deref());
+            rescanned.insert(stale::key(&path));
+        }
+        let watched: Vec<PathBuf> = directories
+            .iter()
+            .map(|dir| stale::key(Path::new(dir)))
+            .chain(self.files.iter().map(|file| stale::key(file)))
+            .collect();
+        let replaced = |source: &str| {
+            let source = stale::key(Path::new(source));
+            // Read again, or a database that is gone without the watcher noticing
+            rescanned.contains(&source) || (!source.exists() && watched.iter().any(|dir| source.starts_with(dir)))
+        };
+        for (key, entry) in saved_data.iter().filter(|(_, entry)| !replaced(&entry.source)) {
+            Self::insert_entry(&*self.policy, &mut self.data, key.clone(), entry.clone());
+        }
+        for (name, entries) in &saved_configs {
+            let data = self.config_data.entry(name.clone()).or_default();
+            for (key, entry) in entries.iter().filter(|(_, entry)| !replaced(&entry.source)) {
+                Self::insert_entry(&*self.policy, data, key.clone(), entry.clone());
+            }
+        }
+        self.conflicts = conflicts;
+        let fresh = verify::snapshot(self.merged_entries().map(|entry| &entry.command));
+        let drift = Drift::between(&incremental, &fresh);
+        drift.report(fresh.len());
+        if !drift.is_empty() {
+            return true;
+        }
+        // Keeping the entries as they were ingested, along with those restored from disk meanwhile
+        let restored: Vec<(String, HashMap<String, Entry>)> =
+            self.config_data.drain().filter(|(name, _)| !saved_configs.contains_key(name)).collect();
+        self.data = saved_data;
+        self.config_data = saved_configs;
+        self.config_data.extend(restored);
+        self.database_hashes = saved_hashes;
+        false
+    }
+
+    /// Write the output from a merge from scratch, reporting how the output it replaces had
+    /// drifted from it; returns whether it had
+    fn verify_output(&mut self) -> Result<bool> {
+        let snapshot = |path: &Path| -> Result<verify::Snapshot> {
+            let commands = match fs::File::open(path) {
+                Ok(file) => parse::parse_stream(BufReader::new(file))?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+                Err(e) => return Err(e.into()),
+            };
+            Ok(verify::snapshot(&commands))
+        };
+        let output = PathBuf::from(&self.output_file);
+        let previous = snapshot(&output)?;
+        self.write_to_file()?;
+        let fresh = snapshot(&output)?;
+        let drift = Drift::between(&previous, &fresh);
+        drift.report(fresh.len());
+        Ok(!drift.is_empty())
+    }
+
+    /// Remove entries read from databases at or below a path that moved away, returning how many were removed
+    fn remove_entries_under(&mut self, path: &Path) -> usize {
+        let Ok(path) = std::path::absolute(path) else {
+            return 0;
+        };
+        let is_stale = |entry: &Entry| {
+            std::path::absolute(&entry.source).is_ok_and(|source| source.starts_with(&path))
+        };
+        let mut removed = 0;
+        for data in std::iter::once(&mut self.data).chain(self.config_data.values_mut()) {
+            let before = data.len();
+            data.retain(|_, entry| !is_stale(entry));
+            removed += before - data.len();
+        }
+        self.database_hashes
+            .retain(|source, _| !std::path::absolute(source).is_ok_and(|source| source.starts_with(&path)));
+        removed
+    }
+
+    /// Source files of all entries resolved against their directories, along with the directories
+    fn sources(&self) -> Vec<(PathBuf, PathBuf)> {
+        let mut sources: Vec<(PathBuf, PathBuf)> = std::iter::once(&self.data)
+            .chain(self.config_data.values())
+            .flat_map(|data| data.values())
+            .map(|entry| (source_path(&entry.command), PathBuf::from(&entry.command.directory)))
+            .collect();
+        sources.sort();
+        sources.dedup();
+        sources
+    }
+
+    /// Mark the given sources (as `stale::key`s) stale where they changed after the database of
+    /// their entries, with --watch-sources, returning whether any became stale
+    fn sources_changed(&self, sources: &HashSet<PathBuf>) -> bool {
+        match &self.staleness {
+            Some(staleness) if !sources.is_empty() => {
+                let entries: Vec<&Entry> = self.merged_entries().collect();
+                staleness.check(&entries, sources)
+            }
+            _ => false,
+        }
+    }
+
+    /// Remove the entries of the given source files, returning how many were removed
+    fn remove_sources(&mut self, sources: &HashSet<PathBuf>) -> usize {
+        let mut removed = 0;
+        for data in std::iter::once(&mut self.data).chain(self.config_data.values_mut()) {
+            let before = data.len();
+            data.retain(|_, entry| !sources.contains(&source_path(&entry.command)));
+            removed += before - data.len();
+        }
+        if removed > 0 {
+            self.database_hashes.clear(); // Re-reading a database must bring the entries back
+        }
+        removed
+    }
+
+    /// Priority of entries read from a local database
+    fn priority(&self, path: &Path) -> i32 {
+        if let Some(priority) = roots::containing(&self.roots, path).and_then(|root| root.priority) {
+            return priority;
+        }
+        let preset = self.preset.map_or(0, |preset| preset.priority(path));
+        if self.directory_priorities.is_empty() {
+            return preset;
+        }
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        self.directory_priorities
+            .iter()
+            .filter(|(dir, _)| path.starts_with(dir))
+            .map(|(_, priority)| *priority)
+            .fold(preset, i32::max)
+    }
+
+    /// Configuration whose build directory contains a local database
+    fn config_for(&self, path: &Path) -> Option<String> {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        self.configs
+            .iter()
+            .find(|(_, dir)| path.starts_with(dir))
+            .map(|(name, _)| name.clone())
+    }
+
+    /// Select the configuration reflected in the output, returning whether it changed
+    fn set_active_config(&mut self, name: &str) -> bool {
+        if self.active_config.as_deref() == Some(name) {
+            return false;
+        }
+        if !self.configs.iter().any(|(config, _)| config == name) {
+            warn!("Warning: Unknown configuration '{}'. Keeping the current one.", name);
+            return false;
+        }
+        info!("Active configuration: {}", name);
+        self.active_config = Some(name.to_string());
+        self.restore_config(name);
+        true
+    }
+
+    /// Read a configuration's entries back if they were spilled to disk
+    fn restore_config(&mut self, name: &str) {
+        let Some(restored) = self.memory_budget.as_mut().and_then(|budget| budget.restore(name)) else {
+            return;
+        };
+        match restored {
+            Ok(entries) => {
+                info!("Restored {} entries of configuration '{}'", entries.len(), name);
+                self.config_data.insert(name.to_string(), entries);
+            }
+            Err(e) => {
+                warn!("Warning: Failed to restore the entries of configuration '{}': {}. Reading its databases again.", name, e);
+                self.rebuild_config(name);
+            }
+        }
+    }
+
+    /// Merge the databases of a configuration again, for entries of it that were lost
+    fn rebuild_config(&mut self, name: &str) {
+        let dirs: Vec<PathBuf> = self.configs.iter().filter(|(config, _)| config == name).map(|(_, dir)| dir.clone()).collect();
+        for dir in dirs {
+            for path in self.discover(&dir) {
+                match self.read_timed(&path) {
+                    Ok(commands) => {
+                        self.record_database_hash(&path, &commands);
+                        let priority = self.priority(&path);
+                        self.insert(&path.display().to_string(), commands, priority, Some(name));
+                    }
+                    Err(e) => error!("Error: Failed to read {}: {}", path.display(), e),
+                }
+            }
+        }
+    }
+
+    /// Spill the entries of inactive configurations to disk while memory exceeds --max-memory
+    fn enforce_memory_budget(&mut self) {
+        let Some(budget) = &mut self.memory_budget else {
+            return;
+        };
+        let Some(used) = budget.exceeded() else {
+            return;
+        };
+        let inactive: Vec<String> = self
+            .config_data
+            .keys()
+            .filter(|name| self.active_config.as_ref() != Some(*name))
+            .cloned()
+            .collect();
+        if inactive.is_empty() {
+            budget.warn_exceeded(used);
+        }
+        for name in inactive {
+            match budget.spill(&name, &self.config_data[&name]) {
+                Ok(()) => {
+                    self.config_data.remove(&name);
+                }
+                Err(e) => warn!("Warning: Failed to spill configuration '{}' to disk: {}", name, e),
+            }
+        }
+    }
+
+    /// Merge the entries read from a database, returning whether they differ from those it had
+    /// when last merged; a database rewritten with the same entries in another order is skipped
+    fn add_entries_from_file(&mut self, path: &Path, parsed: Result<Vec<CompileCommand>>) -> bool {
+        match parsed {
+            Ok(commands) => {
+                let origin = path.display().to_string();
+                if !self.record_database_hash(path, &commands) {
+                    info!("Unchanged entries in: {}", origin);
+                    self.refresh_entries(&origin);
+                    // Written again all the same, so its sources are current
+                    let entries: Vec<&Entry> = self.merged_entries().collect();
+                    return self.staleness.as_ref().is_some_and(|staleness| staleness.refresh(&entries));
+                }
+                let priority = self.priority(path);
+                let config = self.config_for(path);
+                self.add_entries(&origin, commands, priority, config.as_deref());
+                true
+            }
+            Err(e) => {
+                self.report_failure(format!("Error: Failed to read {}: {}", path.display(), e));
+                false
+            }
+        }
+    }
+
+    /// Mark the entries of a database as current again, as when they are re-read
+    fn refresh_entries(&mut self, origin: &str) {
+        let now = self.clock.now();
+        for data in std::iter::once(&mut self.data).chain(self.config_data.values_mut()) {
+            data.values_mut()
+                .filter(|entry| entry.source == origin)
+                .for_each(|entry| entry.ingested_at = now);
+        }
+    }
+
+    /// Drop the entries read from a database, returning how many were dropped
+    fn remove_entries_of(&mut self, origin: &str) -> usize {
+        let mut removed = 0;
+        for data in std::iter::once(&mut self.data).chain(self.config_data.values_mut()) {
+            let before = data.len();
+            data.retain(|_, entry| entry.source != origin);
+            removed += before - data.len();
+        }
+        removed
+    }
+
+    /// Drop entries whose database was last read longer ago than the TTL, returning how many
+    /// entries of how many databases were dropped
+    fn expire_entries(&mut self, ttl: Duration) -> (usize, usize) {
+        let Some(cutoff) = self.clock.now().checked_sub(ttl) else {
+            return (0, 0);
+        };
+        let mut expired = HashSet::new();
+        let mut removed = 0;
+        for data in std::iter::once(&mut self.data).chain(self.config_data.values_mut()) {
+            let before = data.len();
+            data.retain(|_, entry| {
+                let keep = entry.ingested_at >= cutoff;
+                if !keep {
+                    expired.insert(entry.source.clone());
+                }
+                keep
+            });
+            removed += before - data.len();
+        }
+        self.database_hashes.retain(|database, _| {
+            !expired.iter().any(|source| std::path::absolute(source).is_ok_and(|source| source == Path::new(database)))
+        });
+        (removed, expired.len())
+    }
+
+    /// Remember the entries a database was merged with, independently of their order, returning
+    /// whether they differ from those it had the previous time
+    fn record_database_hash(&mut self, path: &Path, commands: &[CompileCommand]) -> bool {
+        let mut hashes: Vec<u64> = commands.iter().map(entry_hash).collect();
+        hashes.sort_unstable();
+        let mut hasher = DefaultHasher::new();
+        hashes.hash(&mut hasher);
+        let hash = hasher.finish();
+        let database = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        self.database_hashes.insert(database.display().to_string(), hash) != Some(hash)
+    }
+
+    /// Handle a change of a CMake build tree's cache, returning its database if it needs re-reading
+    fn cmake_reconfigured(&mut self, cache: &Path) -> (bool, Option<PathBuf>) {
+        let Some(cmake) = &self.cmake else {
+            return (false, None);
+        };
+        match cmake.reconfigured(cache, &self.input_file) {
+            Reconfiguration::Unchanged => (false, None),
+            Reconfiguration::Reconfigured { tree, database, stale } => {
+                let removed = if stale { self.remove_entries_under(&tree) } else { 0 };
+                if removed > 0 {
+                    info!("Removed {} entries of the previous configuration of {}", removed, tree.display());
+                }
+                (removed > 0, database)
+            }
+        }
+    }
+
+    /// Merge a database or archive given by path, re-reading it whenever it changes
+    fn add_file(&mut self, path: &Path) {
+        let parsed = self.read_timed(path);
+        self.add_entries_from_file(path, parsed);
+        self.files.push(path.to_path_buf());
+    }
+
+    /// Read a database or archive, timing it for --timing
+    fn read_timed(&self, path: &Path) -> Result<Vec<CompileCommand>> {
+        let started = Instant::now();
+        let parsed = read_database(path, &self.input_file);
+        self.record_parse(path, started.elapsed(), &parsed);
+        parsed
+    }
+
+    /// Add the time spent in a phase since `started`, if timing
+    fn record(&self, phase: Phase, started: Instant) {
+        if let Some(timings) = &self.timings {
+            timings.record(phase, started);
+        }
+    }
+
+    /// Add the time spent parsing a database, if timing
+    fn record_parse(&self, path: &Path, elapsed: Duration, parsed: &Result<Vec<CompileCommand>>) {
+        if let Some(timings) = &self.timings {
+            let entries = parsed.as_ref().map_or(0, Vec::len);
+            timings.record_parse(&path.display().to_string(), elapsed, entries);
+        }
+    }
+
+    /// Log the times measured since the last report, if timing
+    fn log_timings(&self) {
+        if let Some(timings) = &self.timings {
+            timings.take().log();
+        }
+    }
+
+    /// Log a failure, also raising a desktop notification if enabled
+    fn report_failure(&mut self, message: String) {
+        if let Some(notifier) = &mut self.notifier {
+            notifier.failure(&message);
+        }
+        error!("{}", message);
+    }
+
+    /// Add or update entries read from the given origin
+    fn add_entries(&mut self, origin: &str, commands: Vec<CompileCommand>, priority: i32, config: Option<&str>) {
+        info!(
+            "Adding/Updating entries from: {} ({} entries)",
+            origin,
+            commands.len()
+        );
+        self.insert(origin, commands, priority, config);
+    }
+
+    /// Transform and insert entries, expanding unity builds into their member sources
+    fn insert(&mut self, origin: &str, commands: Vec<CompileCommand>, priority: i32, config: Option<&str>) {
+        let started = Instant::now();
+        let commands = match roots::containing(&self.roots, Path::new(origin)) {
+            Some(root) if Path::new(origin).is_file() => root.apply_all(commands), // Not edits or remote sources
+            _ => commands,
+        };
+        let commands = self.transforms.apply_all(self.overrides.apply_all(commands));
+        let ingested_at = self.clock.now();
+        let dedup = self.dedup;
+        if let Some(name) = config {
+            self.restore_config(name);
+        }
+        let data = match config {
+            Some(name) => self.config_data.entry(name.to_string()).or_default(),
+            None => &mut self.data,
+        };
+        for command in commands {
+            let expanded = self.transforms.expand(&command);
+            let unity_members = expanded.as_ref().map(Vec::len);
+            if let Some(members) = expanded {
+                info!("Expanded unity file {} into {} entries", command.file, members.len());
+                for member in members {
+                    let key = entry_key(&member, dedup);
+                    let conflict = Self::insert_entry(&*self.policy, data, key, Entry {
+                        command: member,
+                        priority,
+                        source: origin.to_string(),
+                        ingested_at,
+                        derived_from: Some(command.file.clone()),
+                        unity_members: None,
+                        interpolated: false,
+                    });
+                    self.conflicts.extend(conflict);
+                }
+            }
+            let key = entry_key(&command, dedup);
+            let conflict = Self::insert_entry(&*self.policy, data, key, Entry {
+                command,
+                priority,
+                source: origin.to_string(),
+                ingested_at,
+                derived_from: None,
+                unity_members,
+                interpolated: false,
+            });
+            self.conflicts.extend(conflict);
+        }
+        while self.conflicts.len() > MAX_RECENT_CONFLICTS {
+            self.conflicts.pop_front();
+        }
+        self.record(Phase::Merge, started);
+    }
+
+    /// Insert an entry, or settle it with the one already merged for the same file as the merge
+    /// policy says, by default keeping that one if it came from a higher-priority database or is
+    /// a real entry shadowing a derived one.
+    /// Returns a description of the conflict if another database had a different command.
+    fn insert_entry(policy: &dyn MergePolicy, data: &mut HashMap<String, Entry>, key: String, entry: Entry) -> Option<String> {
+        let Some(existing) = data.get(&key) else {
+            data.insert(key, entry); // Add entry
+            return None;
+        };
+        let differs = existing.source != entry.source && !policy::equivalent(&existing.command, &entry.command);
+        match policy.resolve(&existing.candidate(), &entry.candidate()) {
+            Resolution::KeepExisting => differs.then(|| format!("{}: {} wins over {}", entry.command.file, existing.source, entry.source)),
+            Resolution::TakeIncoming => {
+                let conflict = differs.then(|| format!("{}: {} wins over {}", entry.command.file, entry.source, existing.source));
+                data.insert(key, entry); // Update entry
+                conflict
+            }
+            Resolution::Combine(command) => {
+                let conflict = differs.then(|| format!("{}: {} and {} combined", entry.command.file, existing.source, entry.source));
+                let priority = existing.priority.max(entry.priority);
+                data.insert(key, Entry { command, priority, derived_from: None, ..entry });
+                conflict
+            }
+            Resolution::KeepBoth => {
+                // Under a key of its own, as without deduplication, unless it has that already
+                let distinct = entry_key(&entry.command, false);
+                if distinct == key {
+                    data.insert(key, entry);
+                    return None;
+                }
+                Self::insert_entry(policy, data, distinct, entry)
+            }
+        }
+    }
+
+    /// Fetch remote sources that changed since the last poll, returning whether any did
+    fn poll_remotes(&mut self, remotes: &mut [RemoteSource]) -> bool {
+        self.add_remote_updates(remote::poll_all(remotes))
+    }
+
+    /// Add the results of polling remote sources, returning whether any source changed
+    fn add_remote_updates(&mut self, polled: Vec<(String, Result<Vec<Update>>)>) -> bool {
+        let mut changed = false;
+        for (name, result) in polled {
+            match result {
+                Ok(updates) => {
+                    for update in updates {
+                        match update {
+                            Update::Changed(origin, commands) => self.add_entries(&origin, commands, 0, None),
+                            Update::Removed(origin) => {
+                                let removed = self.remove_entries_of(&origin);
+                                info!("Deleted: {} ({} entries removed)", origin, removed);
+                                porcelain::event("pruned", serde_json::json!({ "reason": "deleted", "path": origin, "entries": removed }));
+                            }
+                        }
+                        changed = true;
+                    }
+                }
+                Err(e) => self.report_failure(format!("Error fetching {}: {}", name, e)),
+            }
+        }
+        changed
+    }
+
+    /// Entries reflected in the output, with the active configuration's entries taking
+    /// precedence over shared ones
+    fn merged_entries(&self) -> impl Iterator<Item = &Entry> {
+        let active = self
+            .active_config
+            .as_ref()
+            .and_then(|name| self.config_data.get(name));
+        self.data
+            .iter()
+            .filter(move |(file, _)| !active.is_some_and(|config| config.contains_key(*file)))
+            .map(|(_, entry)| entry)
+            .chain(active.into_iter().flat_map(|config| config.values()))
+    }
+
+    /// Whether something other than the merger changed the output since it was last written
+    fn output_modified_externally(&self) -> bool {
+        self.output_guard.foreign_content(Path::new(&self.output_file)).is_some()
+    }
+
+    /// Handle changes made to the output by something else, according to the policy
+    fn reconcile_output(&mut self) {
+        let Some(content) = self.output_guard.foreign_content(Path::new(&self.output_file)) else {
+            return;
+        };
+        warn!("Warning: {} was modified by another program", self.output_file);
+        if self.external_policy == ExternalChangePolicy::Reassert {
+            return;
+        }
+        let commands: Vec<CompileCommand> = match parse::parse(content.as_bytes()) {
+            Ok(commands) => commands,
+            Err(e) => {
+                warn!("Warning: Cannot ingest the modified {}, overwriting it: {}", self.output_file, e);
+                return;
+            }
+        };
+
+        // Only entries added or edited since the last write are taken, so the inputs keep
+        // owning the rest; they go in untransformed, as they are already in output form
+        let previous = self.last_written.as_ref();
+        let edited: Vec<CompileCommand> = commands
+            .into_iter()
+            .filter(|command| {
+                let written = previous.and_then(|previous| previous.get(&command.file));
+                written != Some(&entry_hash(command))
+            })
+            .collect();
+        info!("Ingesting {} entries edited in {}", edited.len(), self.output_file);
+        let origin = format!("{} (edited)", self.output_file);
+        let ingested_at = self.clock.now();
+        let dedup = self.dedup;
+        let data = match &self.active_config {
+            Some(name) => self.config_data.entry(name.clone()).or_default(),
+            None => &mut self.data,
+        };
+        for command in edited {
+            let key = entry_key(&command, dedup);
+            let conflict = Self::insert_entry(&*self.policy, data, key, Entry {
+                command,
+                priority: i32::MAX,
+                source: origin.clone(),
+                ingested_at,
+                derived_from: None,
+                unity_members: None,
+                interpolated: false,
+            });
+            self.conflicts.extend(conflict);
+        }
+    }
+
+    /// Write combined state to the output file, reporting rather than propagating failures
+    fn update_output(&mut self) {
+        if let Err(e) = self.write_to_file() {
+            let message = format!("Error: Failed to update {}: {}", self.output_file, e);
+            if let Some(systemd) = &self.systemd {
+                systemd.status(&message);
+            }
+            self.report_failure(message);
+        }
+        self.log_timings();
+        self.enforce_memory_budget();
+    }
+
+    /// Write the entries in the formats of --also-output, copying files already written in the
+    /// same format rather than serializing the entries again
+    fn write_also_outputs(&self, commands: &[&CompileCommand]) -> std::io::Result<()> {
+        let mut written: Vec<(OutputFormat, &Path)> = vec![(self.output_format, Path::new(&self.output_file))];
+        for (format, path) in &self.also_outputs {
+            match written.iter().find(|(written, _)| written == format) {
+                Some((_, copied)) => write_atomically_with(path, |file| {
+                    std::io::copy(&mut fs::File::open(copied)?, file)?;
+                    Ok(())
+                })?,
+                None => write_atomically_with(path, |file| format.write(file, commands))?,
+            }
+            written.push((*format, path));
+        }
+        Ok(())
+    }
+
+    /// Write combined state to the output file, and provenance to the sidecar if enabled
+    fn write_to_file(&mut self) -> std::io::Result<()> {
+        self.reconcile_output();
+        let started = Instant::now();
+        let output_path = self.output_file.as_str();
+        let mut entries: Vec<&Entry> = self.merged_entries().collect();
+        let interpolated = match &self.interpolation {
+            Some(interpolation) => interpolation.entries(&entries),
+            None => Vec::new(),
+        };
+        entries.extend(&interpolated);
+        if let Some(output_dedup) = &self.output_dedup {
+            entries = output_dedup.apply(entries);
+        }
+        if let Some(entry_cap) = &self.entry_cap {
+            entries = entry_cap.apply(entries)?;
+        }
+        if let Some(flag_lint) = &self.flag_lint {
+            flag_lint.check(&entries);
+        }
+        if let Some(missing_compilers) = &self.missing_compilers {
+            missing_compilers.check(&entries);
+        }
+        if self.sort_output {
+            entries.sort_by(|a, b| {
+                (&a.command.file, &a.command.directory).cmp(&(&b.command.file, &b.command.directory))
+            });
+        }
+        let commands: Vec<&CompileCommand> = entries.iter().map(|entry| &entry.command).collect();
+        let count = commands.len();
+        self.record(Phase::Serialize, started);
+        let started = Instant::now();
+        let lock = match self.lock_output {
+            true => FileLock::exclusive(Path::new(output_path))?,
+            false => None,
+        };
+        // Serialized straight into the file, fingerprinting rather than keeping the content
+        let fingerprint = write_atomically_with(Path::new(output_path), |file| {
+            let mut writer = Fingerprinting::new(file);
+            self.output_format.write(&mut writer, &commands)?;
+            Ok(writer.finish())
+        })?;
+        self.write_also_outputs(&commands)?;
+        if let Some(staleness) = &self.staleness {
+            staleness.refresh(&entries);
+        }
+        if let Some(sidecar) = &self.sidecar {
+            sidecar.write(&entries, fingerprint, self.active_config.as_deref(), self.staleness.as_ref(), self.clock.now())?;
+        }
+        if let Some(include_map) = &self.include_map {
+            include_map.write(&entries)?;
+        }
+        if let Some(file_list) = &self.file_list {
+            file_list.write(&entries)?;
+        }
+        drop(lock);
+        self.record(Phase::Write, started);
+
+        // Work out which entries were added or changed since the previous write
+        let written: HashMap<String, u64> = entries
+            .iter()
+            .map(|entry| (entry.command.file.clone(), entry_hash(&entry.command)))
+            .collect();
+        let mut removed = 0;
+        let changed: Vec<String> = match &self.last_written {
+            Some(previous) => {
+                let changed: Vec<String> = written
+                    .iter()
+                    .filter(|(file, hash)| previous.get(*file) != Some(*hash))
+                    .map(|(file, _)| file.clone())
+                    .collect();
+                let added = changed.iter().filter(|file| !previous.contains_key(*file)).count();
+                removed = previous.keys().filter(|file| !written.contains_key(*file)).count();
+                if !changed.is_empty() || removed > 0 {
+                    info!(
+                        "Entries changed since the last write: {} added, {} modified, {} removed",
+                        added,
+                        changed.len() - added,
+                        removed
+                    );
+                }
+                if let Some(changes) = &self.changes {
+                    // Sending only fails while nobody is subscribed
+                    for event in entry_changes(previous, &written) {
+                        let _ = changes.send(event);
+                    }
+                }
+                changed
+            }
+            None => Vec::new(), // The first write only establishes the baseline
+        };
+        let tidy_sources: Vec<PathBuf> = match self.tidy.is_some() && !changed.is_empty() {
+            true => {
+                let changed: HashSet<&String> = changed.iter().collect();
+                entries
+                    .iter()
+                    .filter(|entry| changed.contains(&entry.command.file))
+                    .map(|entry| source_path(&entry.command)) // Relative files are relative to the entry's directory
+                    .collect()
+            }
+            false => Vec::new(),
+        };
+        self.last_written = Some(written);
+        self.output_guard.record(Path::new(&self.output_file), fingerprint);
+
+        if let Some(clangd) = &self.clangd {
+            clangd.refresh(Path::new(output_path), &changed);
+        }
+        if let Some(tidy) = &self.tidy {
+            tidy.run(Path::new(output_path), tidy_sources);
+        }
+        info!(
+            "Updated combined compile_commands.json with {} entries.",
+            count
+        );
+        porcelain::event(
+            "merged",
+            serde_json::json!({ "output": output_path, "entries": count, "changed": changed.len(), "removed": removed }),
+        );
+        if let Some(systemd) = &self.systemd {
+            systemd.status(&format!("Merged {} entries into {}", count, self.output_file));
+        }
+        Ok(())
+    }
+}
+
+/// Run the command-line tool with the process arguments
+pub fn run() {
+    run_with(parse_args(std::env::args().collect()));
+}
+
+/// Parse the command line, with the options of the config file and profile it selects before
+/// its own, so that the command line's override them (list options add to them)
+fn parse_args(argv: Vec<String>) -> Args {
+    let args = Args::parse_from(&argv);
+    let path = match args.config.clone() {
+        Some(path) => path,
+        None if Path::new(config::CONFIG_FILE).is_file() => PathBuf::from(config::CONFIG_FILE),
+        None if args.profile.is_some() => {
+            error!("Error: --profile needs a config file, given by --config or found as {}", config::CONFIG_FILE);
+            std::process::exit(2);
+        }
+        None => return args,
+    };
+    match config::arguments(&path, args.profile.as_deref()) {
+        Ok(options) => Args::parse_from(argv[..1].iter().cloned().chain(options).chain(argv[1..].iter().cloned())),
+        Err(e) => {
+            error!("Error: {:#}", e);
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Entry point of ccm-wrap: run the compiler given by the arguments, recording it in $CCM_SPOOL
+pub fn wrap() -> ! {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    std::process::exit(wrap::run(None, &args))
+}
+
+/// Options that only change how the merger reports, leaving what it writes as it is
+const REPORTING_OPTIONS: &[&str] = &["quiet", "color", "porcelain", "tui", "timing", "desktop-notifications", "print-config", "config", "profile"];
+
+/// Checksum of the options that shape the output, as recorded in the sidecar: the same for the
+/// same options across builds of the tool, as the options are serialized with sorted keys
+fn config_hash(args: &Args) -> String {
+    let mut options = serde_json::to_value(args).unwrap_or_default();
+    if let Some(options) = options.as_object_mut() {
+        options.retain(|option, _| !REPORTING_OPTIONS.contains(&option.as_str()));
+    }
+    Fingerprint::of(options.to_string().as_bytes()).checksum()
+}
+
+fn run_with(mut args: Args) {
+    let settings = Arc::new(Settings {
+        excludes: args.exclude.clone(),
+        command_syntax: args.command_syntax,
+        unordered_flags: args.unordered_flags.clone(),
+        pio_envs: args.pio_envs.clone(),
+        lunch_targets: args.lunch_targets.clone(),
+        cargo_logs: args.cargo_log.iter().map(|log| std::path::absolute(log).unwrap_or_else(|_| log.clone())).collect(),
+        mmap: args.mmap,
+        porcelain: args.porcelain,
+        quiet: args.quiet,
+        color: log::use_color(args.color),
+        silent: false,
+    });
+    let _settings = settings.enter();
+    let config_hash = config_hash(&args);
+    if let Some(Command::Wrap { spool, compiler }) = &args.command {
+        std::process::exit(wrap::run(spool.as_deref(), compiler));
+    }
+    #[cfg(target_os = "linux")]
+    if let Some(Command::Intercept { spool, command }) = &args.command {
+        std::process::exit(intercept::run(spool.as_deref(), command));
+    }
+    #[cfg(windows)]
+    if let Some(Command::Service { action }) = &args.command {
+        service::handle(action);
+        return;
+    }
+    if let Some(Command::Completions { shell }) = args.command {
+        clap_complete::generate(shell, &mut Args::command(), "compile_commands_merger", &mut std::io::stdout());
+        return;
+    }
+    if let Some(Command::Export { database, format }) = &args.command {
+        let database = database.clone().unwrap_or_else(|| PathBuf::from(&args.output));
+        let _settings = Arc::new(Settings { excludes: Vec::new(), ..Settings::clone(&settings) }).enter(); // Exports every entry
+        if let Err(e) = export::export(&database, *format, &mut std::io::stdout().lock()) {
+            error!("Error: Failed to export {}: {}", database.display(), e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Some(Command::Orphans { root, database }) = &args.command {
+        let database = database.clone().unwrap_or_else(|| PathBuf::from(&args.output));
+        match orphans::report(root, &database, &mut std::io::stdout().lock()) {
+            Ok(count) => std::process::exit(i32::from(count > 0)),
+            Err(e) => {
+                error!("Error: Failed to check {} against {}: {}", root.display(), database.display(), e);
+                std::process::exit(2);
+            }
+        }
+    }
+    if let Some(Command::UseConfig { name }) = &args.command {
+        match write_atomically(&active_config_path(&args.output), name) {
+            Ok(()) => info!("Switched active configuration to '{}'", name),
+            Err(e) => error!("Error: Failed to switch configuration: {}", e),
+        }
+        return;
+    }
+
+    let mut directories_to_watch = match read_stdin_directories(std::mem::take(&mut args.directories)) {
+        Ok(directories) => directories,
+        Err(e) => {
+            error!("Error: Failed to read directories from stdin: {}", e);
+            return;
+        }
+    };
+    let output_file = args.output.clone();
+    let input_file = args.input.clone();
+    if let Some(workspace) = &args.colcon_workspace {
+        args.workspace_preset = Some(WorkspacePreset::Colcon);
+        if !directories_to_watch.contains(workspace) {
+            directories_to_watch.push(workspace.clone());
+        }
+    }
+    let mut workspace_roots = Vec::new();
+    if let Some(path) = &args.code_workspace {
+        let workspace = match vscode::read(path) {
+            Ok(workspace) => workspace,
+            Err(e) => {
+                error!("Error: {:#}", e);
+                return;
+            }
+        };
+        for file in workspace.files {
+            if !is_same_file(&file, Path::new(&output_file)) {
+                args.files.push(file.display().to_string());
+            }
+        }
+        for dir in workspace.directories {
+            let dir = dir.display().to_string();
+            info!("Workspace root: {}", dir);
+            if !directories_to_watch.contains(&dir) {
+                directories_to_watch.push(dir.clone());
+            }
+            workspace_roots.push(dir);
+        }
+    }
+    if let Some(root) = &args.auto {
+        let found = auto::find_build_dirs(Path::new(root), &input_file);
+        if found.is_empty() {
+            error!("Error: No build directories found under {}", root);
+            return;
+        }
+        info!("Found {} build directories under {}:", found.len(), root);
+        for candidate in found {
+            info!("  {} ({})", candidate.dir.display(), candidate.reason);
+            let dir = candidate.dir.display().to_string();
+            if !directories_to_watch.contains(&dir) {
+                directories_to_watch.push(dir);
+            }
+        }
+    }
+    let mut preset_dirs = Vec::new();
+    if let Some(workspace) = &args.cmake_presets {
+        match cmake::preset_build_dirs(Path::new(workspace)) {
+            Ok(dirs) => preset_dirs = dirs,
+            Err(e) => {
+                error!("Error: {:#}", e);
+                return;
+            }
+        }
+        for (name, dir) in &preset_dirs {
+            let dir = dir.display().to_string();
+            if !Path::new(&dir).is_dir() {
+                info!("Preset {}: {} isn't configured yet, skipping it", name, dir);
+            } else if !directories_to_watch.contains(&dir) {
+                info!("Preset {}: {}", name, dir);
+                directories_to_watch.push(dir);
+            }
+        }
+    }
+    let preset_dir = |name: &str| preset_dirs.iter().find(|(preset, _)| preset == name).map(|(_, dir)| dir.display().to_string());
+    let mut directory_priorities = Vec::new();
+    for (index, name) in args.prefer_presets.iter().enumerate() {
+        let Some(dir) = preset_dir(name) else {
+            error!("Error: Unknown preset '{}' in --prefer-presets", name);
+            return;
+        };
+        let dir = fs::canonicalize(&dir).unwrap_or_else(|_| PathBuf::from(dir));
+        directory_priorities.push((dir, (args.prefer_presets.len() - index) as i32));
+    }
+    let mut configs = Vec::new();
+    for spec in &args.configs {
+        let named = spec.split_once('=').map(|(name, dir)| (name.to_string(), dir.to_string()));
+        let Some((name, dir)) = named.or_else(|| preset_dir(spec).map(|dir| (spec.clone(), dir))) else {
+            error!("Error: Invalid configuration '{}', expected NAME=DIR or a preset name", spec);
+            return;
+        };
+        let dir = dir.as_str();
+        if !directories_to_watch.iter().any(|existing| existing == dir) {
+            directories_to_watch.push(dir.to_string());
+        }
+        let dir = fs::canonicalize(dir).unwrap_or_else(|_| PathBuf::from(dir));
+        configs.push((name, dir));
+    }
+    if let Some(spool) = &args.spool {
+        if let Err(e) = wrap::prepare(spool) {
+            error!("Error: {}", e);
+            return;
+        }
+        let spool = spool.display().to_string();
+        if !directories_to_watch.contains(&spool) {
+            directories_to_watch.push(spool);
+        }
+    }
+    for root in &args.root {
+        let dir = root.path.display().to_string();
+        if !directories_to_watch.contains(&dir) {
+            directories_to_watch.push(dir);
+        }
+    }
+    if let Some(Command::Doctor) = args.command {
+        if doctor::run(&directories_to_watch, &input_file) > 0 {
+            std::process::exit(1);
+        }
+        return;
+    }
+    if args.print_config {
+        let mut config = serde_json::to_value(&args).unwrap_or_default();
+        config["directories"] = serde_json::json!(directories_to_watch);
+        if let Some(config) = config.as_object_mut() {
+            config.remove("print-config");
+        }
+        println!("{}", serde_json::to_string_pretty(&config).unwrap_or_default());
+        return;
+    }
+    let mut remotes: Vec<RemoteSource> = args
+        .urls
+        .iter()
+        .map(|url| RemoteSource::Http(HttpSource::new(url)))
+        .collect();
+    for spec in &args.remote {
+        match SshSource::new(spec, &input_file) {
+            Ok(source) => remotes.push(RemoteSource::Ssh(source)),
+            Err(e) => {
+                error!("Error: {}", e);
+                return;
+            }
+        }
+    }
+    let poll_interval = Duration::from_secs(args.poll_interval.max(1));
+
+    for log in &args.cargo_log {
+        args.files.push(log.display().to_string());
+    }
+    for dir in &args.make {
+        match make::generate(dir, args.make_target.as_deref(), &input_file) {
+            Ok(database) => args.files.push(database.display().to_string()),
+            Err(e) => {
+                error!("Error: {}", e);
+                return;
+            }
+        }
+    }
+    if !args.buck2.is_empty() {
+        match buck2::generate(&args.buck2) {
+            Ok(database) => args.files.push(database.display().to_string()),
+            Err(e) => {
+                error!("Error: {}", e);
+                return;
+            }
+        }
+    }
+    if directories_to_watch.is_empty() && args.files.is_empty() && remotes.is_empty() {
+        error!("Error: No directories specified. Use --directories to specify directories to watch, --files for databases, or --urls/--remote for remote sources.");
+        return;
+    }
+
+    info!("Combining existing compile_commands.json files...");
+    let sysroot = match (args.sysroot_preset, &args.sdk_root) {
+        (Some(preset), Some(sdk_root)) => match SysrootRemap::new(preset, sdk_root, &args.sysroot_map) {
+            Ok(remap) => Some(remap),
+            Err(e) => {
+                error!("Error: {}", e);
+                return;
+            }
+        },
+        _ => None,
+    };
+    let mut plugins = Vec::new();
+    for path in &args.plugins {
+        match WasmPlugin::load(path) {
+            Ok(plugin) => plugins.push(plugin),
+            Err(e) => {
+                error!("Error: {}", e);
+                return;
+            }
+        }
+    }
+    let script = match args.script.as_deref().map(Script::load) {
+        Some(Ok(script)) => Some(script),
+        Some(Err(e)) => {
+            error!("Error: {}", e);
+            return;
+        }
+        None => None,
+    };
+    let injections = args
+        .add_target
+        .iter()
+        .map(|spec| Injection::target(spec))
+        .chain(args.add_sysroot.iter().map(|spec| Injection::sysroot(spec)))
+        .collect::<Result<Vec<_>>>();
+    let injections = match injections {
+        Ok(injections) => injections,
+        Err(e) => {
+            error!("Error: {}", e);
+            return;
+        }
+    };
+    let compiler_map = match CompilerMap::new(&args.compiler_map) {
+        Ok(map) => map,
+        Err(e) => {
+            error!("Error: {}", e);
+            return;
+        }
+    };
+    let path_map = match PathMap::new(&args.map_path) {
+        Ok(map) => map,
+        Err(e) => {
+            error!("Error: {}", e);
+            return;
+        }
+    };
+    let generated = match GeneratedSources::new(args.drop_generated, &args.generated, &args.generated_template) {
+        Ok(generated) => generated,
+        Err(e) => {
+            error!("Error: {}", e);
+            return;
+        }
+    };
+    let source_map = match SourceMap::new(&args.map_generated) {
+        Ok(map) => map,
+        Err(e) => {
+            error!("Error: {}", e);
+            return;
+        }
+    };
+    let transforms = Transforms {
+        source_map,
+        generated,
+        path_style: args.translate_paths.and_then(PathStyle::resolve),
+        nix: (!args.nix_profile.is_empty()).then(|| NixProfiles::new(&args.nix_profile)),
+        path_map: path_map.clone(),
+        sysroot,
+        strip_flags: args.workspace_preset.map_or(&[], WorkspacePreset::unsupported_flags),
+        prefix_maps: args.prefix_maps,
+        expand_unity: args.expand_unity,
+        injections,
+        translate_toolchains: args.translate_toolchains,
+        compiler_map,
+        resolve_compilers: args.resolve_compilers,
+        absolute_includes: args.absolute_includes,
+        builtin_includes: args.resolve_builtin_includes.then(BuiltinIncludes::default),
+        pch: if args.rewrite_pch {
+            Some(PchMode::Rewrite)
+        } else {
+            args.strip_pch.then_some(PchMode::Strip)
+        },
+        rebase_directory: args
+            .rebase_directory
+            .as_deref()
+            .map(|root| std::path::absolute(root).unwrap_or_else(|_| root.to_path_buf())),
+        arg_edits: ArgEdits {
+            before: args.extra_arg_before,
+            after: args.extra_arg,
+            remove: args.remove_arg,
+        },
+        custom: Vec::new(),
+        script,
+        plugins,
+        pipe: args.pipe_through.as_deref().map(PipeThrough::new),
+    };
+    if let Some(Command::Bench { iterations }) = args.command {
+        timing::bench(&directories_to_watch, &input_file, args.workspace_preset, transforms, iterations);
+        return;
+    }
+    let mut combined_state = CombinedState::new(
+        &input_file,
+        &output_file,
+        configs,
+        args.workspace_preset,
+        transforms,
+    );
+    combined_state.settings = settings.clone();
+    if args.meta {
+        let sidecar = Sidecar::for_output(&output_file, config_hash);
+        sidecar.invalidate_stale(&output_file);
+        combined_state.sidecar = Some(sidecar);
+    }
+    combined_state.include_map = args.include_map.as_ref().map(IncludeMap::new);
+    combined_state.file_list = args.emit_filelist.as_ref().map(FileList::new);
+    if args.notify_clangd {
+        combined_state.clangd = Some(ClangdRefresh::new(args.invalidate_clangd_index));
+    }
+    combined_state.output_dedup = args.dedup_outputs.map(OutputDedup::new);
+    combined_state.entry_cap = args.max_entries.map(|max| EntryCap::new(max, args.on_max_entries));
+    combined_state.flag_lint = args.lint_flags.then(FlagLint::default);
+    combined_state.missing_compilers = args.check_compilers.then(MissingCompilers::default);
+    combined_state.staleness = args.watch_sources.then(Staleness::default);
+    combined_state.interpolation = args.interpolate.then(Interpolation::default);
+    combined_state.lock_output = args.lock;
+    combined_state.memory_budget = (args.max_memory > 0).then(|| MemoryBudget::new(args.max_memory, &output_file));
+    combined_state.sort_output = args.sort;
+    combined_state.output_format = args.output_format;
+    for spec in &args.also_outputs {
+        match format::parse_also(spec) {
+            Ok(also_output) => combined_state.also_outputs.push(also_output),
+            Err(e) => {
+                error!("Error: {}", e);
+                return;
+            }
+        }
+    }
+    if let Some(age) = &args.entry_ttl {
+        match clock::parse_age(age) {
+            Ok(ttl) => combined_state.entry_ttl = Some(ttl),
+            Err(e) => {
+                error!("Error: {}", e);
+                return;
+            }
+        }
+    }
+    combined_state.external_policy = args.on_external_change;
+    combined_state.directory_priorities = directory_priorities;
+    combined_state.roots = args.root.iter().cloned().map(WatchRoot::resolved).collect();
+    if args.cmake_trees {
+        combined_state.cmake = Some(BuildTrees::default());
+    }
+    if args.desktop_notifications {
+        combined_state.notifier = Some(DesktopNotifier::default());
+    }
+    if args.tidy_on_change {
+        if Path::new(&output_file).file_name().is_some_and(|name| name != "compile_commands.json") {
+            warn!("Warning: clang-tidy only reads databases named compile_commands.json");
+        }
+        combined_state.tidy = Some(TidyRunner::new(&args.tidy_args));
+    }
+    if args.timing {
+        combined_state.timings = Some(Timings::default());
+    }
+    let mut replay = None;
+    if let Some(path) = &args.replay {
+        match replay::load(Path::new(path)) {
+            Ok(events) => {
+                let clock = Arc::new(ManualClock::new(replay::REPLAY_START));
+                combined_state.clock = clock.clone();
+                replay = Some(Replay { events, clock });
+            }
+            Err(e) => {
+                error!("Error: {:#}", e);
+                return;
+            }
+        }
+    }
+    combined_state.scan(&directories_to_watch);
+    for file in &args.files {
+        combined_state.add_file(Path::new(file));
+    }
+    let control_file = (!combined_state.configs.is_empty()).then(|| active_config_path(&output_file));
+    if let Some(name) = control_file.as_ref().and_then(|path| fs::read_to_string(path).ok()) {
+        combined_state.set_active_config(name.trim());
+    }
+    if args.verify == Some(0) || replay.is_some() {
+        combined_state.poll_remotes(&mut remotes); // Otherwise polled in the background from the start
+    }
+    if args.verify == Some(0) {
+        match combined_state.verify_output() {
+            Ok(drifted) => std::process::exit(i32::from(drifted)),
+            Err(e) => {
+                error!("Error: Failed to verify {}: {:#}", output_file, e);
+                std::process::exit(2);
+            }
+        }
+    }
+    combined_state.systemd = Systemd::from_env();
+    if let Err(e) = combined_state.write_to_file() {
+        error!("Error: Failed to write {}: {}", output_file, e);
+        return;
+    }
+    combined_state.log_timings();
+    combined_state.enforce_memory_budget();
+    if let Some(systemd) = &combined_state.systemd {
+        systemd.ready(&format!("Watching, {} entries merged", combined_state.merged_entries().count()));
+    }
+
+    match &replay {
+        Some(replay) => info!("Replaying {} recorded events...", replay.events.len()),
+        None => info!("Watching for changes to compile_commands.json files..."),
+    }
+    let options = WatchOptions {
+        directories: directories_to_watch,
+        poll_interval,
+        debounce: Duration::from_millis(args.debounce_ms),
+        rescan_interval: (args.rescan_interval > 0).then(|| Duration::from_secs(args.rescan_interval)),
+        verify_interval: args.verify.filter(|&secs| secs > 0).map(Duration::from_secs),
+        code_workspace: args.code_workspace.clone().map(|path| (path, workspace_roots)),
+        min_write_interval: Duration::from_secs(args.min_write_interval),
+        prune_interval: (args.prune_missing > 0).then(|| Duration::from_secs(args.prune_missing)),
+        watch_depth: args.watch_depth,
+        replay,
+        control_file,
+        #[cfg(feature = "grpc")]
+        grpc: args.grpc,
+        events: args.events,
+        tui: args.tui,
+        systemd: combined_state.systemd.clone(),
+    };
+    if let Some(link_output) = &args.link_output {
+        // Link commands get the path rewrites only, the others being about compiling
+        let transforms = Transforms {
+            path_style: args.translate_paths.and_then(PathStyle::resolve),
+            nix: (!args.nix_profile.is_empty()).then(|| NixProfiles::new(&args.nix_profile)),
+            path_map,
+            ..Transforms::default()
+        };
+        let mut link_state = CombinedState::new(&args.link_input, link_output, Vec::new(), None, transforms);
+        link_state.settings = settings.clone();
+        link_state.sort_output = args.sort;
+        link_state.lock_output = args.lock;
+        info!("Combining existing {} files into {}...", args.link_input, link_output);
+        link_state.scan(&options.directories);
+        if let Err(e) = link_state.write_to_file() {
+            error!("Error: Failed to write {}: {}", link_output, e);
+            return;
+        }
+        let link_options = WatchOptions {
+            directories: options.directories.clone(),
+            poll_interval,
+            debounce: options.debounce,
+            rescan_interval: options.rescan_interval,
+            verify_interval: None,
+            code_workspace: None,
+            min_write_interval: options.min_write_interval,
+            prune_interval: None,
+            watch_depth: options.watch_depth,
+            replay: None,
+            control_file: None,
+            #[cfg(feature = "grpc")]
+            grpc: None,
+            events: None,
+            tui: false,
+            systemd: None,
+        };
+        std::thread::spawn(move || watch::start_watching(link_state, Vec::new(), link_options));
+    }
+    watch::start_watching(combined_state, remotes, options);
+}
+
+/// Key under which an entry is merged: its file, or with dedup off the whole entry, so that
+/// only identical commands collapse
+fn entry_key(command: &CompileCommand, dedup: bool) -> String {
+    if dedup {
+        command.file.clone()
+    } else {
+        let normalized = compiler::normalized_command(&command.command, &command.directory);
+        format!("{}\n{}\n{}", command.file, command.directory, normalized)
+    }
+}
+
+/// Hash of what an entry compiles and how, insensitive to the quoting and spacing of its command,
+/// to how its compiler is spelled and to the order of flags whose order doesn't matter
+fn entry_hash(command: &CompileCommand) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    command.file.hash(&mut hasher);
+    command.directory.hash(&mut hasher);
+    unordered::canonical(split_args(&compiler::normalized_command(&command.command, &command.directory))).hash(&mut hasher);
+    command.output.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Events for the entries added, updated and removed between two sets of entry hashes by file
+fn entry_changes(previous: &HashMap<String, u64>, current: &HashMap<String, u64>) -> Vec<MergeEvent> {
+    let mut events: Vec<MergeEvent> = current
+        .iter()
+        .filter_map(|(file, hash)| match previous.get(file) {
+            None => Some(MergeEvent::Added { file: file.clone() }),
+            Some(before) if before != hash => Some(MergeEvent::Updated { file: file.clone() }),
+            Some(_) => None,
+        })
+        .collect();
+    events.extend(
+        previous
+            .keys()
+            .filter(|file| !current.contains_key(*file))
+            .map(|file| MergeEvent::Removed { file: file.clone() }),
+    );
+    events
+}
+
+/// File through which `use-config` tells a running watcher which configuration to write
+fn active_config_path(output_file: &str) -> PathBuf {
+    PathBuf::from(format!("{}.active-config", output_file))
+}
+
+/// Write a file by renaming a fully written temporary file over it, so readers never see partial content
+fn write_atomically(path: &Path, content: &str) -> std::io::Result<()> {
+    write_atomically_with(path, |file| file.write_all(content.as_bytes()))
+}
+
+/// Like `write_atomically`, with the content produced by a function writing to the file
+fn write_atomically_with<T>(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<fs::File>) -> std::io::Result<T>,
+) -> std::io::Result<T> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+    let mut file = BufWriter::new(fs::File::create(&temp_path)?);
+    let written = write(&mut file)?;
+    file.into_inner().map_err(|e| e.into_error())?;
+    fs::rename(&temp_path, path)?;
+    Ok(written)
+}
+
+/// Replace a `-` among the directories with those read from stdin, one per line until it closes
+fn read_stdin_directories(directories: Vec<String>) -> std::io::Result<Vec<String>> {
+    if !directories.iter().any(|dir| dir == "-") {
+        return Ok(directories);
+    }
+    let mut from_stdin = Vec::new();
+    for line in std::io::stdin().lines() {
+        let line = line?;
+        let line = line.trim();
+        if !line.is_empty() && !from_stdin.iter().any(|dir| dir == line) {
+            from_stdin.push(line.to_string());
+        }
+    }
+    info!("Read {} directories from stdin", from_stdin.len());
+    Ok(directories
+        .into_iter()
+        .flat_map(|dir| match dir.as_str() {
+            "-" => std::mem::take(&mut from_stdin),
+            _ => vec![dir],
+        })
+        .collect())
+}
+
+/// Path of an entry's source file, resolved against its directory
+fn source_path(command: &CompileCommand) -> PathBuf {
+    Path::new(&command.directory).join(&command.file)
+}
+
+/// Whether two paths refer to the same existing file
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Find all databases named `filename` (e.g. compile_commands.json) under the specified root folder, up to 5 levels deep
+fn find_compile_commands(root: &Path, filename: &str) -> Vec<PathBuf> {
+    let mut results = Vec::new();
+    if root.is_dir() {
+        let mut walker = walkdir::WalkDir::new(root)
+            .into_iter();
+
+        while let Some(entry) = walker.next() {
+            match entry {
+                Ok(entry)
+                    if entry.file_type().is_file()
+                        && compressed::is_database(entry.path(), filename)
+                        && platformio::is_selected(entry.path()) =>
+                {
+                    results.push(entry.path().to_path_buf());
+                    walker.skip_current_dir(); // Skip further entries in the current directory
+                }
+                Ok(_) => {}
+                Err(err) => error!("Error reading directory entry: {}", err),
+            }
+        }
+    }
+    results
+}
+
+/// Read a database, or the databases named `input_file` inside an archive
+fn read_database(path: &Path, input_file: &str) -> Result<Vec<CompileCommand>> {
+    match archive::is_archive(path) {
+        true => archive::read(path, input_file),
+        false if cargo::is_log(path) => cargo::read(path),
+        false if buck2::is_buck_out(path) => read_compile_commands(path).map(buck2::normalize_entries),
+        false if aosp::is_compdb(path) => read_compile_commands(path).map(|commands| aosp::normalize_entries(path, commands)),
+        false => read_compile_commands(path),
+    }
+}
+
+/// Read a compile_commands.json file, resolving relative directories (as written by e.g. some
+/// qmake and SCons setups) against the file's location
+fn read_compile_commands(path: &Path) -> Result<Vec<CompileCommand>> {
+    let _lock = FileLock::shared(path)?;
+    let mut file = fs::File::open(path)?;
+    let parsed = match compressed::is_compressed(path) {
+        true => None,
+        false => mmap::parse(&file),
+    };
+    let mut commands = match parsed {
+        Some(parsed) => parsed?,
+        // Decompressed as they are parsed, compressed databases being the biggest
+        None if compressed::is_compressed(path) => parse::parse_stream(BufReader::new(compressed::decompress(path, file)?))
+            .or_else(|_| parse::parse_stream_located(BufReader::new(compressed::decompress(path, fs::File::open(path)?)?)))?,
+        None if file.metadata()?.len() >= parse::STREAM_THRESHOLD => parse::parse_stream(BufReader::new(file))
+            .or_else(|_| parse::parse_stream_located(BufReader::new(fs::File::open(path)?)))?,
+        None => {
+            let mut content = Vec::new();
+            file.read_to_end(&mut content)?;
+            parse::parse(&content)?
+        }
+    };
+    let location = std::path::absolute(path)?;
+    let base = location.parent().unwrap_or(Path::new(""));
+    for command in &mut commands {
+        if !paths::is_absolute_anywhere(&command.directory) {
+            command.directory = paths::normalize(&base.join(&command.directory)).display().to_string();
+        }
+    }
+    Ok(commands)
+}
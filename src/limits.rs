@@ -0,0 +1,32 @@
+use std::fs;
+use std::path::Path;
+
+/// `EMFILE`: the per-process limit of open file descriptors (or inotify instances) was hit
+const EMFILE: i32 = 24;
+
+/// Whether registering a watch failed because a kernel watch limit was exhausted
+pub fn is_watch_limit(error: &notify::Error) -> bool {
+    match &error.kind {
+        notify::ErrorKind::MaxFilesWatch => true,
+        notify::ErrorKind::Io(e) => e.raw_os_error() == Some(EMFILE),
+        _ => false,
+    }
+}
+
+/// The system-wide inotify watch limit, where the platform exposes one
+pub fn max_user_watches() -> Option<u64> {
+    fs::read_to_string("/proc/sys/fs/inotify/max_user_watches")
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Number of watches a recursive watch of `root` needs, one per directory
+pub fn count_directories(root: &Path) -> usize {
+    walkdir::WalkDir::new(root)
+        .into_iter()
+        .flatten()
+        .filter(|entry| entry.file_type().is_dir())
+        .count()
+}
@@ -0,0 +1,125 @@
+use crate::compiler::{self, compiler_index, executable_name};
+use crate::shell::split_args;
+use crate::{CompileCommand, Entry};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+/// Warns about entries whose commands contradict themselves, which the compiler silently resolves
+/// by letting the last option win
+#[derive(Default)]
+pub struct FlagLint {
+    reported: RefCell<HashSet<(String, String)>>, // Files and the problems already warned about
+}
+
+impl FlagLint {
+    /// Warn about the problems of the entries about to be written, each once per file
+    pub fn check(&self, entries: &[&Entry]) {
+        for entry in entries {
+            for problem in problems(&entry.command) {
+                if self.reported.borrow_mut().insert((entry.command.file.clone(), problem.clone())) {
+                    warn!("Warning: {} has conflicting flags: {}", entry.command.file, problem);
+                }
+            }
+        }
+    }
+}
+
+/// Warns about compilers of entries that can't be found on this machine, through PATH or as written;
+/// the usual reason clangd can't find headers after a database moved between machines
+#[derive(Default)]
+pub struct MissingCompilers {
+    reported: RefCell<HashSet<String>>, // Compilers already warned about
+}
+
+impl MissingCompilers {
+    /// Warn about each missing compiler of the entries about to be written, once
+    pub fn check(&self, entries: &[&Entry]) {
+        let mut users: HashMap<String, Vec<&str>> = HashMap::new(); // Missing compilers and the files they compile
+        for entry in entries {
+            let args = split_args(&entry.command.command);
+            let Some(compiler) = compiler_index(&args).map(|index| &args[index]) else {
+                continue;
+            };
+            if self.reported.borrow().contains(compiler) || compiler::resolve(compiler, &entry.command.directory).is_some() {
+                continue;
+            }
+            users.entry(compiler.clone()).or_default().push(&entry.command.file);
+        }
+        let mut users: Vec<_> = users.into_iter().collect();
+        users.sort();
+        for (compiler, files) in users {
+            warn!(
+                "Warning: Compiler {} of {} entries (e.g. {}) is not found on this machine",
+                compiler,
+                files.len(),
+                files[0]
+            );
+            self.reported.borrow_mut().insert(compiler);
+        }
+    }
+}
+
+/// The contradictions in a command: differing language standards, optimization levels, or values
+/// given to the same macro
+fn problems(command: &CompileCommand) -> Vec<String> {
+    let args = split_args(&command.command);
+    let start = compiler_index(&args);
+    let compiler = start.map_or("", |index| executable_name(&args[index]));
+    let options = &args[start.map_or(args.len(), |index| index + 1)..];
+    // Slash options only mean something to MSVC-style compilers, elsewhere they are paths
+    let msvc = matches!(compiler.to_ascii_lowercase().as_str(), "cl" | "clang-cl");
+    let option = |arg: &str| arg.strip_prefix('-').or_else(|| arg.strip_prefix('/').filter(|_| msvc)).map(str::to_string);
+
+    let mut standards: Vec<String> = Vec::new();
+    let mut levels: Vec<String> = Vec::new();
+    let mut defines: HashMap<String, String> = HashMap::new();
+    let mut redefinitions = Vec::new();
+    let mut options = options.iter();
+    while let Some(arg) = options.next() {
+        let Some(name) = option(arg) else {
+            continue;
+        };
+        if let Some(standard) = name.strip_prefix("std=").or_else(|| name.strip_prefix("std:")) {
+            if !standards.iter().any(|seen| seen == standard) {
+                standards.push(standard.to_string());
+            }
+        } else if let Some(level) = name.strip_prefix('O').filter(|level| is_optimization_level(level)) {
+            let level = if level.is_empty() { "1" } else { level }; // Plain -O is -O1
+            if !levels.iter().any(|seen| seen == level) {
+                levels.push(level.to_string());
+            }
+        } else if let Some(define) = name.strip_prefix('D') {
+            let define = match define.is_empty() {
+                true => options.next().map(String::as_str).unwrap_or_default(),
+                false => define,
+            };
+            let (macro_name, value) = define.split_once('=').unwrap_or((define, "1"));
+            if let Some(previous) = defines.insert(macro_name.to_string(), value.to_string()) {
+                if previous != value {
+                    redefinitions.push(format!("{} defined as both {} and {}", macro_name, previous, value));
+                }
+            }
+        } else if let Some(undefine) = name.strip_prefix('U') {
+            let undefine = match undefine.is_empty() {
+                true => options.next().map(String::as_str).unwrap_or_default(),
+                false => undefine,
+            };
+            defines.remove(undefine); // Redefining after -U is deliberate
+        }
+    }
+    let mut problems = Vec::new();
+    if standards.len() > 1 {
+        problems.push(format!("standards {}", standards.join(", ")));
+    }
+    if levels.len() > 1 {
+        let levels: Vec<String> = levels.iter().map(|level| format!("-O{}", level)).collect();
+        problems.push(format!("optimization levels {}", levels.join(", ")));
+    }
+    problems.extend(redefinitions);
+    problems
+}
+
+/// Whether what follows `-O` is an optimization level rather than another option (e.g. `-Os` is one, `-Oy-` isn't)
+fn is_optimization_level(level: &str) -> bool {
+    matches!(level, "" | "0" | "1" | "2" | "3" | "s" | "z" | "g" | "fast" | "d" | "x" | "t")
+}
@@ -0,0 +1,66 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How long to wait for another process to release a lock before going ahead without it
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Delay between attempts to take a held lock
+const RETRY_DELAY: Duration = Duration::from_millis(50);
+
+/// Advisory lock on `<file>.lock`, the lock file of a database shared with other tools.
+///
+/// Writers hold it exclusively while replacing the file, readers hold it shared while reading
+/// it, so a reader that cannot take it knows an update is in progress. Released on drop.
+pub struct FileLock {
+    _file: File,
+}
+
+/// Lock file guarding a database, e.g. `compile_commands.json.lock`
+pub fn lock_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".lock");
+    path.with_file_name(name)
+}
+
+impl FileLock {
+    /// Lock a file for writing, creating its lock file if needed
+    pub fn exclusive(path: &Path) -> io::Result<Option<Self>> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(lock_path(path))?;
+        Self::acquire(file, path, File::try_lock)
+    }
+
+    /// Lock a file for reading, if a writer uses the lock file convention for it
+    pub fn shared(path: &Path) -> io::Result<Option<Self>> {
+        let file = match File::open(lock_path(path)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        Self::acquire(file, path, File::try_lock_shared)
+    }
+
+    fn acquire(file: File, path: &Path, try_lock: fn(&File) -> Result<(), TryLockError>) -> io::Result<Option<Self>> {
+        let started = Instant::now();
+        loop {
+            match try_lock(&file) {
+                Ok(()) => return Ok(Some(FileLock { _file: file })),
+                Err(TryLockError::WouldBlock) if started.elapsed() < LOCK_TIMEOUT => std::thread::sleep(RETRY_DELAY),
+                Err(TryLockError::WouldBlock) => {
+                    warn!(
+                        "Warning: {} is still locked after {}s, going ahead without the lock",
+                        lock_path(path).display(),
+                        LOCK_TIMEOUT.as_secs()
+                    );
+                    return Ok(None);
+                }
+                Err(TryLockError::Error(e)) => return Err(e),
+            }
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use std::io::IsTerminal;
+use std::sync::Mutex;
+
+/// Severity of a console message
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+    Info,
+    Warn,
+    Error,
+}
+
+/// When warnings and errors are colored on the console
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ColorChoice {
+    /// When writing to a terminal and NO_COLOR is not set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// Messages held back from the console while an interactive view owns the terminal
+static CAPTURED: Mutex<Option<Vec<(Level, String)>>> = Mutex::new(None);
+
+/// Extra destination for messages, such as the Windows event log
+type Sink = fn(Level, &str);
+
+/// Sink every message is also sent to
+static SINK: Mutex<Option<Sink>> = Mutex::new(None);
+
+/// Print a message, or hold it for the interactive view if capturing
+pub fn emit(level: Level, message: String) {
+    let settings = crate::settings::current();
+    if settings.silent {
+        return;
+    }
+    if let Some(sink) = *SINK.lock().unwrap_or_else(|e| e.into_inner()) {
+        sink(level, &message);
+    }
+    if level == Level::Error {
+        crate::porcelain::event("error", serde_json::json!({ "message": message }));
+    }
+    let mut captured = CAPTURED.lock().unwrap_or_else(|e| e.into_inner());
+    match captured.as_mut() {
+        Some(messages) => messages.push((level, message)),
+        None if level != Level::Error && settings.quiet => {}
+        None if level == Level::Info && !settings.porcelain => println!("{}", message),
+        None => eprintln!("{}", paint(level, &message, settings.color)),
+    }
+}
+
+/// Whether warnings and errors are colored on the console for a --color choice
+pub fn use_color(color: ColorChoice) -> bool {
+    match color {
+        ColorChoice::Auto => std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()) && std::io::stderr().is_terminal(),
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+    }
+}
+
+/// A warning in yellow or an error in bold red, when coloring
+fn paint(level: Level, message: &str, color: bool) -> String {
+    if !color {
+        return message.to_string();
+    }
+    match level {
+        Level::Info => message.to_string(),
+        Level::Warn => format!("\x1b[33m{}\x1b[0m", message),
+        Level::Error => format!("\x1b[1;31m{}\x1b[0m", message),
+    }
+}
+
+/// Also send every message to `sink`
+#[cfg_attr(not(windows), allow(dead_code))]
+pub fn set_sink(sink: Sink) {
+    *SINK.lock().unwrap_or_else(|e| e.into_inner()) = Some(sink);
+}
+
+/// Start holding messages back instead of printing them
+pub fn start_capture() {
+    *CAPTURED.lock().unwrap_or_else(|e| e.into_inner()) = Some(Vec::new());
+}
+
+/// Stop capturing and return the messages that were still held
+pub fn stop_capture() -> Vec<(Level, String)> {
+    CAPTURED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take()
+        .unwrap_or_default()
+}
+
+/// Take the messages captured so far
+pub fn drain() -> Vec<(Level, String)> {
+    CAPTURED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_mut()
+        .map(std::mem::take)
+        .unwrap_or_default()
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => { $crate::log::emit($crate::log::Level::Info, format!($($arg)*)) };
+}
+
+macro_rules! warn {
+    ($($arg:tt)*) => { $crate::log::emit($crate::log::Level::Warn, format!($($arg)*)) };
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => { $crate::log::emit($crate::log::Level::Error, format!($($arg)*)) };
+}
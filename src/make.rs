@@ -0,0 +1,173 @@
+use crate::compiler::{executable_name, is_compiler, source_and_output};
+use crate::paths::normalize;
+use crate::shell::{join_args, split_args};
+use crate::{write_atomically, CompileCommand};
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Have make print the commands of a full rebuild without running them (`make -nB`), and write
+/// the compile commands among them to a database in the directory, returning its path
+pub fn generate(dir: &Path, target: Option<&str>, input_file: &str) -> Result<PathBuf> {
+    let dir = std::path::absolute(dir)?;
+    info!("Generating the compilation database of {} with make -nB", dir.display());
+    // -w prints the directories recursive makes (`$(MAKE) -C sub`) enter and leave
+    let output = Command::new("make")
+        .args(["-nBw"])
+        .args(target)
+        .current_dir(&dir)
+        .output()
+        .context("Failed to run make")?;
+    if !output.status.success() {
+        bail!("make -nB failed in {}: {}", dir.display(), String::from_utf8_lossy(&output.stderr).trim());
+    }
+    let commands = parse(&String::from_utf8_lossy(&output.stdout), &dir);
+    info!("Found {} compiler invocations in the output of make -nB", commands.len());
+    let database = dir.join(input_file);
+    write_atomically(&database, &serde_json::to_string_pretty(&commands)?)?;
+    Ok(database)
+}
+
+/// The compile commands among the commands make printed, in the directories they run in
+fn parse(output: &str, dir: &Path) -> Vec<CompileCommand> {
+    let mut directories = vec![dir.to_path_buf()]; // Entered by recursive makes, innermost last
+    let mut commands = Vec::new();
+    let mut line = String::new();
+    for printed in output.lines() {
+        // Recipe lines continued with a backslash are printed as they are written
+        if let Some(continued) = printed.strip_suffix('\\') {
+            line.push_str(continued);
+            continue;
+        }
+        line.push_str(printed);
+        let current = directories.last().cloned().unwrap_or_else(|| dir.to_path_buf());
+        if let Some(entered) = directory_message(&line, "Entering directory ") {
+            directories.push(normalize(&current.join(entered)));
+        } else if directory_message(&line, "Leaving directory ").is_some() {
+            if directories.len() > 1 {
+                directories.pop();
+            }
+        } else {
+            commands.extend(line_commands(&line, &current));
+        }
+        line.clear();
+    }
+    commands
+}
+
+/// The directory of a make `Entering directory '...'` or `Leaving directory '...'` message
+fn directory_message<'a>(line: &'a str, message: &str) -> Option<&'a str> {
+    let (make, rest) = line.split_once(": ")?;
+    if !executable_name(make.split('[').next().unwrap_or(make)).ends_with("make") {
+        return None;
+    }
+    Some(rest.strip_prefix(message)?.trim_matches(['\'', '`', '"']))
+}
+
+/// The compile commands of one recipe line, which runs in its own shell starting in `dir`
+fn line_commands(line: &str, dir: &Path) -> Vec<CompileCommand> {
+    let mut directory = dir.to_path_buf();
+    let mut commands = Vec::new();
+    for segment in segments(&split_args(line)) {
+        match segment.as_slice() {
+            [cd, target, ..] if cd == "cd" => directory = normalize(&directory.join(target)),
+            _ => commands.extend(entry(&segment, &directory)),
+        }
+    }
+    commands
+}
+
+/// Split a shell command line at its `&&`, `||` and `;` separators
+fn segments(args: &[String]) -> Vec<Vec<String>> {
+    let mut segments = vec![Vec::new()];
+    for arg in args {
+        match arg.as_str() {
+            "&&" | "||" | ";" => segments.push(Vec::new()),
+            _ => match arg.strip_suffix(';') {
+                Some(last) => {
+                    segments.last_mut().unwrap().push(last.to_string());
+                    segments.push(Vec::new());
+                }
+                None => segments.last_mut().unwrap().push(arg.clone()),
+            },
+        }
+    }
+    segments.retain(|segment| !segment.is_empty());
+    segments
+}
+
+/// The entry of a command, if it runs a compiler on a single source; wrappers before the
+/// compiler, such as `libtool --mode=compile` or `ccache`, are left out
+fn entry(args: &[String], directory: &Path) -> Option<CompileCommand> {
+    let start = args.iter().position(|arg| is_compiler(executable_name(arg)))?;
+    let args = &args[start..];
+    if !args.iter().any(|arg| arg == "-c") {
+        return None; // Linking
+    }
+    let (file, output) = source_and_output(args);
+    Some(CompileCommand {
+        directory: directory.display().to_string(),
+        command: join_args(args),
+        file: file?,
+        output,
+        extra: Default::default(),
+        original_compiler: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Output of `make -nBw` in /src/proj, whose Makefile builds lib/ with a recursive make
+    const TRANSCRIPT: &str = "\
+make: Entering directory '/src/proj'
+gcc -O2 -c main.c -o main.o
+make -C lib all
+make[1]: Entering directory '/src/proj/lib'
+gcc -O2 -fPIC \\
+\t-Iinclude -c util.c -o util.o
+cd sub && gcc -c ../extra.c -o extra.o
+make[1]: Leaving directory '/src/proj/lib'
+cd tools; cc -DTOOL -c tool.c
+gcc main.o lib/util.o -o app
+make: Leaving directory '/src/proj'
+";
+
+    fn entries(output: &str) -> Vec<(String, String, String)> {
+        parse(output, Path::new("/src/proj"))
+            .into_iter()
+            .map(|command| (command.directory, command.file, command.command))
+            .collect()
+    }
+
+    #[test]
+    fn tracks_the_directory_of_each_command() {
+        let expected = [
+            ("/src/proj", "main.c", "gcc -O2 -c main.c -o main.o"),
+            ("/src/proj/lib", "util.c", "gcc -O2 -fPIC -Iinclude -c util.c -o util.o"),
+            ("/src/proj/lib/sub", "../extra.c", "gcc -c ../extra.c -o extra.o"),
+            ("/src/proj/tools", "tool.c", "cc -DTOOL -c tool.c"),
+        ];
+        let expected: Vec<(String, String, String)> = expected
+            .iter()
+            .map(|(directory, file, command)| (directory.to_string(), file.to_string(), command.to_string()))
+            .collect();
+        assert_eq!(entries(TRANSCRIPT), expected);
+    }
+
+    #[test]
+    fn enters_relative_directories_and_ignores_unmatched_leaves() {
+        let output = "\
+make[1]: Leaving directory '/elsewhere'
+gcc -c a.c
+gmake[2]: Entering directory `sub'
+gcc -c b.c
+gmake[2]: Leaving directory `sub'
+gcc -c c.c
+";
+        let directories: Vec<String> =
+            entries(output).into_iter().map(|(directory, file, _)| format!("{}:{}", directory, file)).collect();
+        assert_eq!(directories, ["/src/proj:a.c", "/src/proj/sub:b.c", "/src/proj:c.c"]);
+    }
+}
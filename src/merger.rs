@@ -0,0 +1,464 @@
+use crate::clock::SystemClock;
+use crate::settings::Settings;
+use crate::transform::{CustomTransform, Transforms};
+use crate::{entry_changes, entry_hash, read_compile_commands, CombinedState, CompileCommand};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub use crate::clock::{Clock, ManualClock};
+pub use crate::policy::{Candidate, KeepDistinct, MergePolicy, PreferPriority, Resolution};
+pub use crate::roots::WatchRoot;
+pub use crate::shell::CommandSyntax;
+pub use crate::unordered::UnorderedFlags;
+
+/// Something that happened while merging, as delivered to subscribers
+#[derive(Debug, Clone)]
+pub enum MergeEvent {
+    /// A database was read and its entries merged
+    Merged { source: String, entries: usize },
+    /// A database could not be read
+    Failed { source: String, error: String },
+    /// A file got its first merged entry
+    Added { file: String },
+    /// The merged entry of a file changed
+    Updated { file: String },
+    /// A file no longer has a merged entry
+    Removed { file: String },
+}
+
+/// A filesystem change fed to [`Merger::follow`]
+#[derive(Debug, Clone)]
+pub enum FileEvent {
+    /// A database was created or written
+    Modified(PathBuf),
+    /// A database, or a directory of them, was deleted or moved away
+    Removed(PathBuf),
+}
+
+/// Where [`Merger::follow`] takes its changes from, instead of watching the filesystem; any
+/// iterator of events is one, e.g. a recorded sequence in a test
+pub trait EventSource {
+    /// The next change, or `None` once there are no more
+    fn next_event(&mut self) -> Option<FileEvent>;
+}
+
+impl<I: Iterator<Item = FileEvent>> EventSource for I {
+    fn next_event(&mut self) -> Option<FileEvent> {
+        self.next()
+    }
+}
+
+/// Configures a [`Merger`]
+pub struct MergerBuilder {
+    directories: Vec<PathBuf>,
+    roots: Vec<WatchRoot>,
+    filename: String,
+    dedup: bool,
+    transforms: Vec<CustomTransform>,
+    clock: Arc<dyn Clock>,
+    policy: Arc<dyn MergePolicy>,
+    settings: Settings,
+}
+
+impl MergerBuilder {
+    /// Add a directory searched by [`Merger::scan`]
+    pub fn add_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.directories.push(dir.into());
+        self
+    }
+
+    /// Add a directory searched by [`Merger::scan`] with settings of its own: the name of its
+    /// databases, filters and edits of their entries, and their priority (its `poll` is ignored)
+    pub fn root(mut self, root: WatchRoot) -> Self {
+        self.directories.push(root.path.clone());
+        self.roots.push(root.resolved());
+        self
+    }
+
+    /// Name of the databases to look for [default: compile_commands.json]
+    pub fn filename(mut self, filename: impl Into<String>) -> Self {
+        self.filename = filename.into();
+        self
+    }
+
+    /// Keep one entry per file, the highest-priority or latest one [default: true].
+    /// When off, only entries with identical commands are collapsed.
+    pub fn dedup(mut self, dedup: bool) -> Self {
+        self.dedup = dedup;
+        self
+    }
+
+    /// Rewrite every entry as it is merged; transforms run in the order they were added
+    pub fn transform(mut self, transform: impl Fn(CompileCommand) -> CompileCommand + Send + Sync + 'static) -> Self {
+        self.transforms.push(Arc::new(transform));
+        self
+    }
+
+    /// Take the time entries are ingested at from this clock [default: the system's], e.g. a
+    /// [`ManualClock`] for reproducible expiry
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Settle entries for a file that already has one with this policy [default: [`PreferPriority`]],
+    /// e.g. [`KeepDistinct`] or one of the embedder's own
+    pub fn policy(mut self, policy: impl MergePolicy + 'static) -> Self {
+        self.policy = Arc::new(policy);
+        self
+    }
+
+    /// Drop the entries of source files matching this glob from every database merged
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.settings.excludes.push(pattern.into());
+        self
+    }
+
+    /// Split and join the commands of entries with this syntax [default: [`CommandSyntax::Auto`]]
+    pub fn command_syntax(mut self, syntax: CommandSyntax) -> Self {
+        self.settings.command_syntax = syntax;
+        self
+    }
+
+    /// Also ignore the order of these flags when telling whether an entry changed
+    pub fn unordered_flags(mut self, flags: &[UnorderedFlags]) -> Self {
+        self.settings.unordered_flags = flags.to_vec();
+        self
+    }
+
+    /// Only merge the databases of this PlatformIO environment, and of others added the same way
+    /// [default: all of them]
+    pub fn pio_env(mut self, environment: impl Into<String>) -> Self {
+        self.settings.pio_envs.push(environment.into());
+        self
+    }
+
+    /// Parse databases of 16 MiB or more straight from a memory mapping of the file [default: false].
+    /// A generator truncating a database in place while it is parsed can then fault the process.
+    pub fn mmap(mut self, mmap: bool) -> Self {
+        self.settings.mmap = mmap;
+        self
+    }
+
+    /// Create the merger. Its console messages are dropped, failures are reported to subscribers instead.
+    pub fn build(self) -> Merger {
+        let transforms = Transforms {
+            custom: self.transforms,
+            ..Default::default()
+        };
+        let mut state = CombinedState::new(&self.filename, "", Vec::new(), None, transforms);
+        state.dedup = self.dedup;
+        state.clock = self.clock;
+        state.policy = self.policy;
+        state.roots = self.roots;
+        state.settings = Arc::new(self.settings);
+        Merger {
+            state,
+            directories: self.directories,
+            subscribers: Vec::new(),
+            hashes: HashMap::new(),
+        }
+    }
+}
+
+/// In-process merger for embedders such as IDE plugins
+pub struct Merger {
+    state: CombinedState,
+    directories: Vec<PathBuf>,
+    subscribers: Vec<Sender<MergeEvent>>,
+    hashes: HashMap<String, u64>, // Merged entries by file as last reported to subscribers
+}
+
+impl Merger {
+    pub fn builder() -> MergerBuilder {
+        MergerBuilder {
+            directories: Vec::new(),
+            roots: Vec::new(),
+            filename: "compile_commands.json".to_string(),
+            dedup: true,
+            transforms: Vec::new(),
+            clock: Arc::new(SystemClock),
+            policy: Arc::new(PreferPriority),
+            settings: Settings {
+                silent: true,
+                ..Settings::default()
+            },
+        }
+    }
+
+    /// Merge every database found under the configured directories, returning how many were merged
+    pub fn scan(&mut self) -> usize {
+        let _settings = self.state.settings.enter();
+        let databases: Vec<PathBuf> = self
+            .directories
+            .iter()
+            .flat_map(|dir| self.state.discover(dir))
+            .collect();
+        databases.iter().filter(|path| self.merge_file(path).is_ok()).count()
+    }
+
+    /// Merge a single database, returning its number of entries
+    pub fn merge_file(&mut self, path: impl AsRef<Path>) -> Result<usize> {
+        let _settings = self.state.settings.enter();
+        let path = path.as_ref();
+        let source = path.display().to_string();
+        match read_compile_commands(path) {
+            Ok(commands) => {
+                let entries = commands.len();
+                self.state.add_entries_from_file(path, Ok(commands));
+                self.publish(MergeEvent::Merged { source, entries });
+                self.publish_changes();
+                Ok(entries)
+            }
+            Err(e) => {
+                self.publish(MergeEvent::Failed { source, error: e.to_string() });
+                Err(e)
+            }
+        }
+    }
+
+    /// Apply changes until the source runs out: databases modified are merged again, and the
+    /// entries of those removed dropped. Returns how many changes were applied.
+    pub fn follow(&mut self, mut source: impl EventSource) -> usize {
+        let _settings = self.state.settings.enter();
+        let mut applied = 0;
+        while let Some(event) = source.next_event() {
+            match event {
+                FileEvent::Modified(path) => {
+                    let _ = self.merge_file(&path); // Failures are reported to subscribers
+                }
+                FileEvent::Removed(path) => {
+                    self.state.remove_entries_under(&path);
+                    self.publish_changes();
+                }
+            }
+            applied += 1;
+        }
+        applied
+    }
+
+    /// Drop the entries of databases last merged longer ago than `ttl` by the merger's clock,
+    /// returning how many were dropped
+    pub fn expire(&mut self, ttl: Duration) -> usize {
+        let _settings = self.state.settings.enter();
+        let (removed, _) = self.state.expire_entries(ttl);
+        if removed > 0 {
+            self.publish_changes();
+        }
+        removed
+    }
+
+    /// The merged entries as they would be written out
+    pub fn snapshot(&self) -> Vec<CompileCommand> {
+        self.state
+            .merged_entries()
+            .map(|entry| entry.command.clone())
+            .collect()
+    }
+
+    /// Borrow the merged entries without copying them
+    pub fn database(&self) -> Database<'_> {
+        Database { state: &self.state }
+    }
+
+    /// Receive an event for every database merged from now on, followed by events for the
+    /// entries it added, updated or removed
+    pub fn subscribe(&mut self) -> Receiver<MergeEvent> {
+        let _settings = self.state.settings.enter();
+        if self.subscribers.is_empty() {
+            self.hashes = self.entry_hashes(); // Changes are reported from here on
+        }
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.push(tx);
+        rx
+    }
+
+    /// Report the entries changed since the last report, if anyone is listening
+    fn publish_changes(&mut self) {
+        if self.subscribers.is_empty() {
+            return;
+        }
+        let hashes = self.entry_hashes();
+        for event in entry_changes(&self.hashes, &hashes) {
+            self.publish(event);
+        }
+        self.hashes = hashes;
+    }
+
+    fn entry_hashes(&self) -> HashMap<String, u64> {
+        self.state
+            .merged_entries()
+            .map(|entry| (entry.command.file.clone(), entry_hash(&entry.command)))
+            .collect()
+    }
+
+    /// Send an event to all subscribers, forgetting those that went away
+    fn publish(&mut self, event: MergeEvent) {
+        self.subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+}
+
+/// Read-only view of the merged entries
+pub struct Database<'a> {
+    state: &'a CombinedState,
+}
+
+impl<'a> Database<'a> {
+    /// Iterate over the merged entries, in no particular order
+    pub fn entries(&self) -> impl Iterator<Item = &'a CompileCommand> + 'a {
+        self.state.merged_entries().map(|entry| &entry.command)
+    }
+
+    /// Iterate over the merged entries accepted by `filter`
+    pub fn entries_where<F>(&self, filter: F) -> impl Iterator<Item = &'a CompileCommand> + 'a
+    where
+        F: Fn(&CompileCommand) -> bool + 'a,
+    {
+        self.entries().filter(move |command| filter(command))
+    }
+
+    /// Number of merged entries
+    pub fn len(&self) -> usize {
+        self.entries().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries().next().is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::SystemTime;
+
+    /// A directory of its own for each test, emptied first
+    fn fixture(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("compile_commands_merger_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Write a database of entries for the given files and commands below `dir`
+    fn database(dir: &Path, entries: &[(&str, &str)]) -> PathBuf {
+        fs::create_dir_all(dir).unwrap();
+        let entries: Vec<serde_json::Value> = entries
+            .iter()
+            .map(|(file, command)| serde_json::json!({ "directory": "/src", "command": command, "file": file }))
+            .collect();
+        let path = dir.join("compile_commands.json");
+        fs::write(&path, serde_json::to_string(&entries).unwrap()).unwrap();
+        path
+    }
+
+    fn files(merger: &Merger) -> Vec<String> {
+        let mut files: Vec<String> = merger.snapshot().into_iter().map(|command| command.file).collect();
+        files.sort();
+        files
+    }
+
+    fn command_of(merger: &Merger, file: &str) -> String {
+        merger.database().entries().find(|command| command.file == file).unwrap().command.clone()
+    }
+
+    #[test]
+    fn replays_changes_and_expires_by_its_clock() {
+        let dir = fixture("replay");
+        database(&dir.join("a"), &[("a.c", "cc -c a.c"), ("b.c", "cc -c b.c")]);
+        let clock = Arc::new(ManualClock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000)));
+        let mut merger = Merger::builder().add_dir(&dir).clock(clock.clone()).build();
+        assert_eq!(merger.scan(), 1);
+        assert_eq!(files(&merger), ["a.c", "b.c"]);
+
+        let events = merger.subscribe();
+        clock.advance(Duration::from_secs(3600));
+        let second = database(&dir.join("b"), &[("b.c", "cc -O2 -c b.c"), ("c.c", "cc -c c.c")]);
+        let applied = merger.follow([FileEvent::Modified(second.clone())].into_iter());
+        assert_eq!(applied, 1);
+        assert_eq!(files(&merger), ["a.c", "b.c", "c.c"]);
+        assert_eq!(command_of(&merger, "b.c"), "cc -O2 -c b.c");
+        let mut changes: Vec<String> = events
+            .try_iter()
+            .map(|event| match event {
+                MergeEvent::Merged { entries, .. } => format!("merged {}", entries),
+                MergeEvent::Failed { error, .. } => format!("failed {}", error),
+                MergeEvent::Added { file } => format!("added {}", file),
+                MergeEvent::Updated { file } => format!("updated {}", file),
+                MergeEvent::Removed { file } => format!("removed {}", file),
+            })
+            .collect();
+        changes[1..].sort();
+        assert_eq!(changes, ["merged 2", "added c.c", "updated b.c"]);
+
+        // The first database was merged three hours ago, the second two
+        clock.advance(Duration::from_secs(2 * 3600));
+        assert_eq!(merger.expire(Duration::from_secs(150 * 60)), 1);
+        assert_eq!(files(&merger), ["b.c", "c.c"]);
+
+        fs::remove_dir_all(second.parent().unwrap()).unwrap();
+        merger.follow([FileEvent::Removed(dir.join("b"))].into_iter());
+        assert!(merger.database().is_empty());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    /// Combines the entries of a file into one with the flags of both
+    struct Union;
+
+    impl MergePolicy for Union {
+        fn resolve(&self, existing: &Candidate, incoming: &Candidate) -> Resolution {
+            let extra = incoming.command.command.split(' ').filter(|flag| !existing.command.command.contains(flag));
+            let command = std::iter::once(existing.command.command.as_str()).chain(extra).collect::<Vec<_>>().join(" ");
+            Resolution::Combine(CompileCommand {
+                command,
+                ..existing.command.clone()
+            })
+        }
+    }
+
+    #[test]
+    fn settles_entries_with_its_policy() {
+        let dir = fixture("policy");
+        database(&dir.join("a"), &[("a.c", "cc -c a.c")]);
+        database(&dir.join("b"), &[("a.c", "cc -DB -c a.c")]);
+
+        let mut combined = Merger::builder().add_dir(dir.join("a")).add_dir(dir.join("b")).policy(Union).build();
+        combined.scan();
+        assert_eq!(combined.snapshot().len(), 1);
+        assert_eq!(command_of(&combined, "a.c"), "cc -c a.c -DB");
+
+        let mut distinct = Merger::builder()
+            .add_dir(dir.join("a"))
+            .add_dir(dir.join("b"))
+            .policy(KeepDistinct)
+            .dedup(false)
+            .build();
+        distinct.scan();
+        let mut commands: Vec<String> = distinct.snapshot().into_iter().map(|command| command.command).collect();
+        commands.sort();
+        assert_eq!(commands, ["cc -DB -c a.c", "cc -c a.c"]);
+
+        let mut preferred = Merger::builder().add_dir(dir.join("a")).add_dir(dir.join("b")).build();
+        preferred.scan();
+        assert_eq!(command_of(&preferred, "a.c"), "cc -DB -c a.c");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn configures_each_merger_on_its_own() {
+        let dir = fixture("settings");
+        database(&dir, &[("a.c", "cc -c a.c"), ("third_party/b.c", "cc -c third_party/b.c")]);
+        let mut excluding = Merger::builder().add_dir(&dir).exclude("third_party/*").build();
+        let mut everything = Merger::builder().add_dir(&dir).build();
+        excluding.scan();
+        everything.scan();
+        assert_eq!(files(&excluding), ["a.c"]);
+        assert_eq!(files(&everything), ["a.c", "third_party/b.c"]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,150 @@
+use crate::external::Fingerprint;
+use crate::stale::Staleness;
+use crate::{write_atomically, Entry};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Sidecar file describing where each merged entry came from, kept out of the strict main output
+pub struct Sidecar {
+    path: PathBuf,
+    config_hash: String,
+}
+
+/// Version of the sidecar's layout, raised whenever a reader of the previous one would misread it
+pub const SIDECAR_FORMAT: u32 = 2;
+
+#[derive(Serialize)]
+struct SidecarContent<'a> {
+    format: u32,
+    version: &'static str,
+    output_checksum: String, // Of the output the sidecar describes, which it is stale for otherwise
+    config_hash: &'a str,
+    generated_at: u64,
+    active_config: Option<&'a str>,
+    entries: Vec<EntryMeta<'a>>, // By file and directory, a file having several with --no-dedup
+}
+
+#[derive(Serialize)]
+struct EntryMeta<'a> {
+    file: &'a str,
+    directory: &'a str,
+    source: &'a str,
+    ingested_at: u64,
+    priority: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    derived_from: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unity_members: Option<usize>, // Of a unity file, the number of sources it was expanded into
+    #[serde(skip_serializing_if = "Option::is_none")]
+    original_compiler: Option<&'a str>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    interpolated: bool, // Guessed from the sibling in `derived_from`, with --interpolate
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stale: bool, // The source changed after the database, with --watch-sources
+}
+
+/// The header of a sidecar, telling whether it can be trusted
+#[derive(Deserialize)]
+struct SidecarHeader {
+    format: Option<u32>,
+    output_checksum: Option<String>,
+}
+
+/// Key of the sidecar's checksum of its own content: of the rest of it, serialized compactly
+/// with sorted keys, so that a truncated or edited sidecar isn't trusted
+const CHECKSUM_KEY: &str = "checksum";
+
+/// Checksum of a sidecar's content without its checksum
+fn content_checksum(content: &serde_json::Map<String, serde_json::Value>) -> String {
+    Fingerprint::of(serde_json::to_string(content).unwrap_or_default().as_bytes()).checksum()
+}
+
+impl Sidecar {
+    /// Place the sidecar next to the output, e.g. `compile_commands.meta.json`
+    pub fn for_output(output_file: &str, config_hash: String) -> Self {
+        let output = Path::new(output_file);
+        let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+        Sidecar {
+            path: output.with_file_name(format!("{}.meta.json", stem)),
+            config_hash,
+        }
+    }
+
+    /// Remove a sidecar left by an earlier run that doesn't describe the output as it is, having
+    /// another format (e.g. from an older version), a damaged content or another output's checksum,
+    /// so that nothing reads it until the next write rebuilds it
+    pub fn invalidate_stale(&self, output_file: &str) {
+        let Ok(content) = fs::read(&self.path) else {
+            return;
+        };
+        let output = fs::read(output_file).ok().map(|output| Fingerprint::of(&output).checksum());
+        let damaged = !serde_json::from_slice::<serde_json::Map<String, serde_json::Value>>(&content).is_ok_and(|mut content| {
+            let recorded = content.remove(CHECKSUM_KEY);
+            recorded.as_ref().and_then(serde_json::Value::as_str) == Some(content_checksum(&content).as_str())
+        });
+        let reason = match serde_json::from_slice::<SidecarHeader>(&content) {
+            Err(_) => "unreadable".to_string(),
+            Ok(header) if header.format != Some(SIDECAR_FORMAT) => match header.format {
+                Some(format) => format!("format {}, expected {}", format, SIDECAR_FORMAT),
+                None => "no format".to_string(),
+            },
+            Ok(_) if damaged => "content not matching its checksum".to_string(),
+            Ok(header) if header.output_checksum.is_none() || header.output_checksum != output => {
+                "describes another output".to_string()
+            }
+            Ok(_) => return,
+        };
+        info!("Invalidating sidecar {} ({}), it is rebuilt on the next write", self.path.display(), reason);
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!("Warning: Failed to remove {}: {}", self.path.display(), e);
+        }
+    }
+
+    /// Write provenance for the given merged entries, and the output they were written to
+    pub fn write(
+        &self,
+        entries: &[&Entry],
+        output: Fingerprint,
+        active_config: Option<&str>,
+        staleness: Option<&Staleness>,
+        generated_at: SystemTime,
+    ) -> std::io::Result<()> {
+        let mut content = SidecarContent {
+            format: SIDECAR_FORMAT,
+            version: env!("CARGO_PKG_VERSION"),
+            output_checksum: output.checksum(),
+            config_hash: &self.config_hash,
+            generated_at: unix_time(generated_at),
+            active_config,
+            entries: entries
+                .iter()
+                .map(|entry| EntryMeta {
+                    file: &entry.command.file,
+                    directory: &entry.command.directory,
+                    source: &entry.source,
+                    ingested_at: unix_time(entry.ingested_at),
+                    priority: entry.priority,
+                    derived_from: entry.derived_from.as_deref(),
+                    unity_members: entry.unity_members,
+                    original_compiler: entry.command.original_compiler.as_deref(),
+                    interpolated: entry.interpolated,
+                    stale: staleness.is_some_and(|staleness| staleness.is_stale(entry)),
+                })
+                .collect(),
+        };
+        content.entries.sort_by(|a, b| (a.file, a.directory, a.source).cmp(&(b.file, b.directory, b.source)));
+        let mut content = serde_json::to_value(&content)?;
+        if let Some(content) = content.as_object_mut() {
+            let checksum = content_checksum(content);
+            content.insert(CHECKSUM_KEY.to_string(), checksum.into());
+        }
+        write_atomically(&self.path, &serde_json::to_string_pretty(&content)?)
+    }
+}
+
+/// Seconds since the Unix epoch
+pub fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs())
+}
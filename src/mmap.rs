@@ -0,0 +1,21 @@
+use crate::parse;
+use crate::settings;
+use crate::CompileCommand;
+use anyhow::Result;
+use std::fs::File;
+
+/// Databases at least this big are mapped rather than read, when mapping is enabled
+const MMAP_THRESHOLD: u64 = 16 * 1024 * 1024;
+
+/// Parse a database from a mapping of the file, if the state's --mmap enables mapping and the
+/// file is large enough to benefit; `None` means it should be read normally
+pub fn parse(file: &File) -> Option<Result<Vec<CompileCommand>>> {
+    if !settings::current().mmap || file.metadata().ok()?.len() < MMAP_THRESHOLD {
+        return None;
+    }
+    // SAFETY: the mapping is only read while parsing. A writer truncating the file in place
+    // meanwhile can fault the process, which is why mapping is opt-in; generators that replace
+    // the file instead (such as CMake) leave the mapped original intact.
+    let map = unsafe { memmap2::Mmap::map(file) }.ok()?;
+    Some(parse::parse(&map))
+}
@@ -0,0 +1,85 @@
+use crate::paths::PathMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+/// Where Nix keeps its store, unless NIX_STORE_DIR says otherwise
+const DEFAULT_STORE: &str = "/nix/store";
+
+/// How deep into a profile its links into the store are looked for
+const MAX_DEPTH: usize = 4;
+
+/// Rewrites `/nix/store/<hash>-<name>` paths, which change with every rebuild, to where profile,
+/// `result` or GC-root symlinks expose them, so entries stay the same across rebuilds
+pub struct NixProfiles {
+    profiles: Vec<PathBuf>,
+    store: PathBuf,
+    mapped: Mutex<(Vec<Option<PathBuf>>, PathMap)>, // Store paths the profiles resolved to, and the mapping they give
+}
+
+impl NixProfiles {
+    pub fn new(profiles: &[String]) -> Self {
+        let store = std::env::var_os("NIX_STORE_DIR").map_or_else(|| PathBuf::from(DEFAULT_STORE), PathBuf::from);
+        NixProfiles {
+            profiles: profiles.iter().map(PathBuf::from).collect(),
+            store,
+            mapped: Mutex::new((Vec::new(), PathMap::default())),
+        }
+    }
+
+    /// The mapping of the profiles' current generations to rewrite a batch of entries with,
+    /// worked out again when one was switched
+    pub fn map(&self) -> PathMap {
+        let targets: Vec<Option<PathBuf>> = self.profiles.iter().map(|profile| fs::canonicalize(profile).ok()).collect();
+        let mut mapped = self.mapped.lock().unwrap_or_else(|e| e.into_inner());
+        if mapped.0 != targets {
+            let mut prefixes = Vec::new();
+            for (profile, target) in self.profiles.iter().zip(&targets) {
+                match target {
+                    Some(target) => self.links(profile, target, &mut prefixes),
+                    None => warn!("Warning: Nix profile {} doesn't resolve to a store path", profile.display()),
+                }
+            }
+            *mapped = (targets, PathMap::from_prefixes(prefixes));
+        }
+        mapped.1.clone()
+    }
+
+    /// Collect the store paths a profile exposes: the one it resolves to, and those whose files it
+    /// links at the same place (as buildEnv and home-manager profiles do), the first profile winning
+    fn links(&self, profile: &Path, target: &Path, prefixes: &mut Vec<(String, String)>) {
+        let mut add = |store_path: PathBuf, at: &Path| {
+            let from = store_path.display().to_string();
+            if !prefixes.iter().any(|(mapped, _)| *mapped == from) {
+                prefixes.push((from, at.display().to_string()));
+            }
+        };
+        if let Some(store_path) = self.store_path(target) {
+            add(store_path, profile);
+        }
+        for entry in walkdir::WalkDir::new(target).min_depth(1).max_depth(MAX_DEPTH).into_iter().flatten() {
+            if !entry.path_is_symlink() {
+                continue;
+            }
+            let Ok(link) = fs::read_link(entry.path()) else {
+                continue;
+            };
+            let Some(store_path) = self.store_path(&link) else {
+                continue;
+            };
+            let relative = entry.path().strip_prefix(target).unwrap_or(entry.path());
+            if link.strip_prefix(&store_path).is_ok_and(|inner| inner == relative) {
+                add(store_path, profile);
+            }
+        }
+    }
+
+    /// The `<store>/<hash>-<name>` path a path lies in, if it lies in the store
+    fn store_path(&self, path: &Path) -> Option<PathBuf> {
+        let inner = path.strip_prefix(&self.store).ok()?;
+        match inner.components().next() {
+            Some(Component::Normal(name)) => Some(self.store.join(name)),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use crate::auto::SKIPPED_DIRS;
+use crate::paths::normalize;
+use crate::{parse, read_compile_commands, source_path};
+use anyhow::Result;
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Extensions of the sources that are compiled, and so should have an entry; headers needn't
+pub const SOURCE_EXTENSIONS: &[&str] = &["c", "cc", "cpp", "cxx", "c++", "C", "m", "mm", "cu"];
+
+/// Files marking a build tree, whose sources are generated rather than forgotten
+const BUILD_TREE_MARKERS: &[&str] = &[crate::cmake::CACHE_FILE, "build.ninja", "meson-private"];
+
+/// How many of the directories without any entry are listed
+const REPORTED_DIRS: usize = 10;
+
+/// List the sources under `root` that have no entry in the database, along with the topmost
+/// directories none of whose sources have one, returning how many sources had none
+pub fn report(root: &Path, database: &Path, out: &mut impl Write) -> Result<usize> {
+    let key = |path: &Path| std::fs::canonicalize(path).unwrap_or_else(|_| normalize(&std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())));
+    let covered: HashSet<PathBuf> = read_compile_commands(database)?.iter().map(|command| key(&source_path(command))).collect();
+    let root = key(root);
+    let mut orphans = Vec::new();
+    let mut dirs: BTreeMap<PathBuf, bool> = BTreeMap::new(); // Directories holding sources, and whether any has an entry
+    let mut sources = 0;
+    let walker = walkdir::WalkDir::new(&root).sort_by_file_name().into_iter().filter_entry(|entry| {
+        let name = entry.file_name().to_string_lossy();
+        let skipped = SKIPPED_DIRS.iter().any(|skipped| name == *skipped) || (name.starts_with('.') && entry.depth() > 0);
+        let build_tree = entry.depth() > 0
+            && entry.file_type().is_dir()
+            && BUILD_TREE_MARKERS.iter().any(|marker| entry.path().join(marker).exists());
+        !(skipped || build_tree)
+    });
+    for entry in walker.flatten() {
+        let path = entry.path();
+        let is_source = entry.file_type().is_file()
+            && path.extension().is_some_and(|extension| SOURCE_EXTENSIONS.iter().any(|source| extension == *source));
+        let relative = path.strip_prefix(&root).unwrap_or(path);
+        if !is_source || parse::is_excluded(&path.display().to_string()) || parse::is_excluded(&relative.display().to_string()) {
+            continue;
+        }
+        sources += 1;
+        let has_entry = covered.contains(&key(path));
+        for dir in relative.ancestors().skip(1).filter(|dir| !dir.as_os_str().is_empty()) {
+            *dirs.entry(dir.to_path_buf()).or_default() |= has_entry;
+        }
+        if !has_entry {
+            writeln!(out, "{}", relative.display())?;
+            orphans.push(relative.to_path_buf());
+        }
+    }
+    let forgotten: Vec<&PathBuf> = dirs
+        .iter()
+        .filter(|(dir, has_entry)| !**has_entry && dir.parent().is_none_or(|parent| dirs.get(parent).is_none_or(|&parent| parent)))
+        .map(|(dir, _)| dir)
+        .collect();
+    info!("{} of {} sources under {} have no entry in {}", orphans.len(), sources, root.display(), database.display());
+    if !forgotten.is_empty() {
+        let listed: Vec<String> = forgotten.iter().take(REPORTED_DIRS).map(|dir| dir.display().to_string()).collect();
+        let more = forgotten.len().saturating_sub(REPORTED_DIRS);
+        info!(
+            "Directories without any entry, perhaps not built or watched: {}{}",
+            listed.join(", "),
+            if more > 0 { format!(" and {} more", more) } else { String::new() }
+        );
+    }
+    Ok(orphans.len())
+}
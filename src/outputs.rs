@@ -0,0 +1,86 @@
+use crate::paths::normalize;
+use crate::shell::split_args;
+use crate::{CompileCommand, Entry};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// What to do about entries producing the same object file
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputDedupMode {
+    /// Warn about them
+    Report,
+    /// Keep only the highest-priority, most recently ingested one
+    Collapse,
+}
+
+/// Detects entries that produce the same output artifact
+pub struct OutputDedup {
+    mode: OutputDedupMode,
+    reported: RefCell<HashSet<String>>, // Outputs already warned about
+}
+
+impl OutputDedup {
+    pub fn new(mode: OutputDedupMode) -> Self {
+        OutputDedup {
+            mode,
+            reported: RefCell::default(),
+        }
+    }
+
+    /// Report or collapse duplicate outputs among the entries about to be written
+    pub fn apply<'a>(&self, entries: Vec<&'a Entry>) -> Vec<&'a Entry> {
+        let mut by_output: HashMap<String, Vec<&'a Entry>> = HashMap::new();
+        let mut result = Vec::with_capacity(entries.len());
+        for entry in entries {
+            match output_of(&entry.command) {
+                Some(output) => by_output.entry(output).or_default().push(entry),
+                None => result.push(entry),
+            }
+        }
+
+        let mut outputs: Vec<_> = by_output.into_iter().collect();
+        outputs.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for (output, mut producers) in outputs {
+            if producers.len() == 1 {
+                result.extend(producers);
+                continue;
+            }
+            producers.sort_by(|a, b| {
+                (b.priority, b.ingested_at)
+                    .cmp(&(a.priority, a.ingested_at))
+                    .then_with(|| a.command.file.cmp(&b.command.file))
+            });
+            if self.reported.borrow_mut().insert(output.clone()) {
+                let files: Vec<&str> = producers.iter().map(|entry| entry.command.file.as_str()).collect();
+                warn!("Warning: {} is produced by {} entries: {}", output, files.len(), files.join(", "));
+            }
+            match self.mode {
+                OutputDedupMode::Report => result.extend(producers),
+                OutputDedupMode::Collapse => result.push(producers[0]),
+            }
+        }
+        result
+    }
+}
+
+/// Object file an entry produces, from its `output` field or `-o`/`/Fo` in its command
+fn output_of(command: &CompileCommand) -> Option<String> {
+    let output = command.output.clone().or_else(|| {
+        let args = split_args(&command.command);
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "-o" {
+                return args.next();
+            }
+            if let Some(output) = arg.strip_prefix("/Fo").or_else(|| arg.strip_prefix("-Fo")) {
+                return (!output.is_empty()).then(|| output.to_string());
+            }
+        }
+        None
+    })?;
+    Some(normalize(&Path::new(&command.directory).join(output)).display().to_string())
+}
@@ -0,0 +1,131 @@
+use crate::compiler::glob_match;
+use crate::inject::ArgEdits;
+use crate::paths::normalize;
+use crate::shell::{join_args, split_args};
+use crate::CompileCommand;
+use anyhow::Result;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the files adding local filters and edits to the entries of sources below them
+pub const OVERRIDE_FILE: &str = ".ccm.toml";
+
+/// Directories not searched for override files, holding no sources of their own
+const SKIPPED_DIRS: &[&str] = &[".git", ".hg", ".svn", "node_modules"];
+
+/// What a `.ccm.toml` does to the entries of sources below its directory:
+/// ```toml
+/// exclude = ["tests/*"]          # Globs over paths relative to the directory, or absolute
+/// extra-args = ["-DUSE_FAKE_HAL"]
+/// extra-args-before = []
+/// remove-args = ["-Werror*"]
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct LocalConfig {
+    #[serde(default)]
+    exclude: Vec<String>,
+    #[serde(default)]
+    extra_args: Vec<String>,
+    #[serde(default)]
+    extra_args_before: Vec<String>,
+    #[serde(default)]
+    remove_args: Vec<String>,
+}
+
+/// An override file as applied
+struct Local {
+    dir: PathBuf,
+    exclude: Vec<String>,
+    edits: ArgEdits,
+}
+
+/// The override files found under the watched directories
+#[derive(Default)]
+pub struct Overrides {
+    configs: Vec<Local>, // Outer directories first
+}
+
+impl Overrides {
+    /// Find the override files below a directory, replacing those found there before
+    pub fn discover(&mut self, root: &Path) {
+        let Ok(root) = std::path::absolute(root) else {
+            return;
+        };
+        self.configs.retain(|local| !local.dir.starts_with(&root));
+        let walker = walkdir::WalkDir::new(&root)
+            .into_iter()
+            .filter_entry(|entry| !SKIPPED_DIRS.iter().any(|skipped| entry.file_name() == *skipped));
+        for entry in walker.flatten() {
+            if entry.file_type().is_file() && entry.file_name() == OVERRIDE_FILE {
+                self.load(entry.path());
+            }
+        }
+    }
+
+    /// Read an override file again after it changed, or forget it if it was removed
+    pub fn reload(&mut self, path: &Path) {
+        let Some(dir) = std::path::absolute(path).ok().and_then(|path| path.parent().map(Path::to_path_buf)) else {
+            return;
+        };
+        self.configs.retain(|local| local.dir != dir);
+        if path.is_file() {
+            self.load(path);
+        }
+    }
+
+    fn load(&mut self, path: &Path) {
+        let Some(dir) = std::path::absolute(path).ok().and_then(|path| path.parent().map(Path::to_path_buf)) else {
+            return;
+        };
+        match read(path) {
+            Ok(config) => {
+                info!("Applying overrides of {} to entries below {}", path.display(), dir.display());
+                let edits = ArgEdits {
+                    before: config.extra_args_before,
+                    after: config.extra_args,
+                    remove: config.remove_args,
+                };
+                self.configs.push(Local { dir, exclude: config.exclude, edits });
+                self.configs.sort_by_key(|local| local.dir.components().count());
+            }
+            Err(e) => warn!("Warning: Ignoring {}: {:#}", path.display(), e),
+        }
+    }
+
+    /// Apply the override files of the directories above each entry's source, outermost first,
+    /// dropping the entries they exclude
+    pub fn apply_all(&self, commands: Vec<CompileCommand>) -> Vec<CompileCommand> {
+        if self.configs.is_empty() {
+            return commands;
+        }
+        commands.into_iter().filter_map(|command| self.apply(command)).collect()
+    }
+
+    fn apply(&self, mut command: CompileCommand) -> Option<CompileCommand> {
+        let source = normalize(&Path::new(&command.directory).join(&command.file));
+        let mut args: Option<Vec<String>> = None; // Split only when a file edits them
+        for local in &self.configs {
+            let Ok(relative) = source.strip_prefix(&local.dir) else {
+                continue;
+            };
+            let relative = relative.display().to_string().replace('\\', "/");
+            let absolute = source.display().to_string().replace('\\', "/");
+            if local.exclude.iter().any(|pattern| glob_match(pattern, &relative) || glob_match(pattern, &absolute)) {
+                return None;
+            }
+            if !local.edits.is_empty() {
+                local.edits.apply(args.get_or_insert_with(|| split_args(&command.command)));
+            }
+        }
+        if let Some(args) = args {
+            command.command = join_args(&args);
+        }
+        Some(command)
+    }
+}
+
+fn read(path: &Path) -> Result<LocalConfig> {
+    Ok(toml::from_str(&fs::read_to_string(path)?)?)
+}
@@ -0,0 +1,492 @@
+use crate::compiler::glob_match;
+use crate::settings;
+use crate::shell::join_args;
+use crate::CompileCommand;
+use anyhow::{anyhow, bail, Result};
+use serde::de::{self, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use serde_path_to_error::{Path, Segment, Track};
+use std::borrow::Cow;
+use std::fmt;
+use std::io::BufRead;
+use std::marker::PhantomData;
+
+/// Databases at least this big are parsed as a stream of entries rather than read whole, so
+/// memory grows with the entries kept instead of the size of the file
+pub const STREAM_THRESHOLD: u64 = 256 * 1024 * 1024;
+
+/// Keys under which wrapped databases hold their entries
+const WRAPPED_KEYS: &[&str] = &["commands", "entries", "compile_commands"];
+
+/// Whether a source file is dropped by the state's --exclude
+pub fn is_excluded(file: &str) -> bool {
+    settings::current().excludes.iter().any(|pattern| glob_match(pattern, file))
+}
+
+/// An entry whose strings borrow from the database's bytes where they need no unescaping, so
+/// that nothing is copied out of the entries --exclude drops
+struct BorrowedCommand<'a> {
+    directory: Cow<'a, str>,
+    command: Option<Cow<'a, str>>,
+    arguments: Option<Vec<Cow<'a, str>>>, // Used instead of `command` by some generators (e.g. qbs)
+    file: Cow<'a, str>,
+    output: Option<Cow<'a, str>>,
+    extra: serde_json::Map<String, serde_json::Value>, // Fields of other tools, rare enough to parse as they are
+}
+
+/// A string of a database, borrowed unless it has escapes; serde only borrows a bare `Cow`,
+/// not one in an `Option` or a `Vec`
+#[derive(Deserialize)]
+struct Str<'a>(#[serde(borrow)] Cow<'a, str>);
+
+/// A field of an entry
+enum Field {
+    Directory,
+    Command,
+    Arguments,
+    File,
+    Output,
+    Other(String),
+}
+
+impl<'de> Deserialize<'de> for Field {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FieldVisitor;
+
+        impl Visitor<'_> for FieldVisitor {
+            type Value = Field;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a field name")
+            }
+
+            fn visit_str<E: de::Error>(self, name: &str) -> Result<Field, E> {
+                Ok(match name {
+                    "directory" => Field::Directory,
+                    "command" => Field::Command,
+                    "arguments" => Field::Arguments,
+                    "file" => Field::File,
+                    "output" => Field::Output,
+                    _ => Field::Other(name.to_string()),
+                })
+            }
+        }
+
+        deserializer.deserialize_identifier(FieldVisitor)
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for BorrowedCommand<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(CommandVisitor(PhantomData))
+    }
+}
+
+struct CommandVisitor<'a>(PhantomData<&'a ()>);
+
+impl<'de: 'a, 'a> Visitor<'de> for CommandVisitor<'a> {
+    type Value = BorrowedCommand<'a>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a compile command")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let (mut directory, mut command, mut arguments, mut file, mut output) = (None, None, None, None, None);
+        let mut extra = serde_json::Map::new();
+        while let Some(field) = map.next_key::<Field>()? {
+            match field {
+                Field::Directory => set(&mut directory, "directory", map.next_value::<Str>()?.0)?,
+                Field::Command => set(&mut command, "command", map.next_value::<Option<Str>>()?.map(|command| command.0))?,
+                Field::Arguments => {
+                    let value = map.next_value::<Option<Vec<Str>>>()?;
+                    set(&mut arguments, "arguments", value.map(|arguments| arguments.into_iter().map(|argument| argument.0).collect()))?
+                }
+                Field::File => set(&mut file, "file", map.next_value::<Str>()?.0)?,
+                Field::Output => set(&mut output, "output", map.next_value::<Option<Str>>()?.map(|output| output.0))?,
+                Field::Other(key) => {
+                    extra.insert(key, map.next_value()?);
+                }
+            }
+        }
+        Ok(BorrowedCommand {
+            directory: directory.ok_or_else(|| de::Error::missing_field("directory"))?,
+            command: command.flatten(),
+            arguments: arguments.flatten(),
+            file: file.ok_or_else(|| de::Error::missing_field("file"))?,
+            output: output.flatten(),
+            extra,
+        })
+    }
+}
+
+/// Fill a field's slot, which a second value of the field finds taken
+fn set<T, E: de::Error>(slot: &mut Option<T>, name: &'static str, value: T) -> Result<(), E> {
+    match slot.replace(value) {
+        Some(_) => Err(E::duplicate_field(name)),
+        None => Ok(()),
+    }
+}
+
+/// The first object of a database starting with one: a wrapper around its entries, as some
+/// tools write it (`{"commands": [...]}`), or `None` where it is an entry, the first of
+/// newline-delimited ones
+struct WrappedCommands<'a>(Option<Vec<BorrowedCommand<'a>>>);
+
+impl<'de: 'a, 'a> Deserialize<'de> for WrappedCommands<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(Wrapper(PhantomData)).map(WrappedCommands)
+    }
+}
+
+impl BorrowedCommand<'_> {
+    fn into_owned(self) -> Result<CompileCommand> {
+        let command = match (self.command, self.arguments) {
+            (Some(command), _) => command.into_owned(),
+            (None, Some(arguments)) => join_args(&arguments),
+            (None, None) => bail!("The entry of {} has neither a command nor arguments", self.file),
+        };
+        Ok(CompileCommand {
+            directory: self.directory.into_owned(),
+            command,
+            file: self.file.into_owned(),
+            output: self.output.map(Cow::into_owned),
+            extra: self.extra,
+            original_compiler: None,
+        })
+    }
+}
+
+/// Parse a database, copying out only the entries not dropped by --exclude. Besides the
+/// standard array, an array wrapped in an object and newline-delimited entries are accepted.
+pub fn parse(bytes: &[u8]) -> Result<Vec<CompileCommand>> {
+    let start = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes); // UTF-8 byte order mark
+    let borrowed: Vec<BorrowedCommand> = match start.iter().find(|byte| !byte.is_ascii_whitespace()) {
+        Some(b'{') => {
+            let mut deserializer = serde_json::Deserializer::from_slice(start);
+            match WrappedCommands::deserialize(&mut deserializer) {
+                Ok(WrappedCommands(Some(commands))) => {
+                    deserializer.end()?;
+                    commands
+                }
+                Ok(WrappedCommands(None)) => parse_lines(start)?,
+                Err(e) => return Err(located::<WrappedCommands>(start, e)),
+            }
+        }
+        _ => serde_json::from_slice(start).map_err(|e| located::<Vec<BorrowedCommand>>(start, e))?,
+    };
+    borrowed
+        .into_iter()
+        .enumerate()
+        .filter(|(_, command)| !is_excluded(&command.file))
+        .map(|(index, command)| command.into_owned().map_err(|e| anyhow!("Entry {}: {}", index, e)))
+        .collect()
+}
+
+/// Reads the entries of a wrapper, skipping the fields of an entry
+struct Wrapper<'a>(PhantomData<&'a ()>);
+
+impl<'de: 'a, 'a> Visitor<'de> for Wrapper<'a> {
+    type Value = Option<Vec<BorrowedCommand<'a>>>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an object with a commands array, or a compile command")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut commands = None;
+        while let Some(key) = map.next_key::<Str>()? {
+            match commands.is_none() && WRAPPED_KEYS.contains(&key.0.as_ref()) {
+                true => commands = Some(map.next_value()?),
+                false => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(commands)
+    }
+}
+
+/// Parse newline-delimited entries, telling which of them is broken
+fn parse_lines(bytes: &[u8]) -> Result<Vec<BorrowedCommand<'_>>> {
+    let mut stream = serde_json::Deserializer::from_slice(bytes).into_iter::<BorrowedCommand>();
+    let mut commands = Vec::new();
+    loop {
+        let offset = stream.byte_offset();
+        match stream.next() {
+            None => return Ok(commands),
+            Some(Ok(command)) => commands.push(command),
+            Some(Err(e)) => {
+                let path = tracked_path::<BorrowedCommand>(&bytes[offset..]);
+                let error = describe(Some(entry_location(commands.len(), path.iter().flat_map(Path::iter))), bytes, e);
+                bail!("Neither an object with a commands array nor newline-delimited entries: {}", error);
+            }
+        }
+    }
+}
+
+/// Describe where a database is broken, given how parsing it failed: the entry and its field,
+/// found by parsing it again keeping track of where (too slow to do every time), and the line,
+/// column and byte offset
+fn located<'a, T: Deserialize<'a>>(bytes: &'a [u8], error: serde_json::Error) -> anyhow::Error {
+    let path = tracked_path::<T>(bytes);
+    let mut segments = path.iter().flat_map(Path::iter).skip_while(|segment| !matches!(segment, Segment::Seq { .. }));
+    let location = match segments.next() {
+        Some(Segment::Seq { index }) => Some(entry_location(*index, segments)),
+        _ => None,
+    };
+    describe(location, bytes, error)
+}
+
+/// Where deserializing a document fails, if it does
+fn tracked_path<'a, T: Deserialize<'a>>(bytes: &'a [u8]) -> Option<Path> {
+    let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+    serde_path_to_error::deserialize::<_, T>(&mut deserializer).err().map(|e| e.path().clone())
+}
+
+/// An entry, and the field a path within it points at (e.g. entry 3, field `arguments[2]`)
+fn entry_location<'a>(index: usize, segments: impl Iterator<Item = &'a Segment>) -> String {
+    let mut field = String::new();
+    for segment in segments {
+        match segment {
+            Segment::Seq { index } => field.push_str(&format!("[{}]", index)),
+            Segment::Map { key } if field.is_empty() => field.push_str(key),
+            Segment::Map { key } => field.push_str(&format!(".{}", key)),
+            _ => {} // Where a key couldn't be read
+        }
+    }
+    match field.is_empty() {
+        true => format!("Entry {}", index),
+        false => format!("Entry {}, field `{}`", index, field),
+    }
+}
+
+/// A parse error with where it happened, adding the byte offset to its line and column
+fn describe(location: Option<String>, bytes: &[u8], error: serde_json::Error) -> anyhow::Error {
+    let offset = match error.line() {
+        0 => String::new(),
+        line => {
+            let line_start: usize = bytes.split_inclusive(|&byte| byte == b'\n').take(line - 1).map(<[u8]>::len).sum();
+            format!(" (byte {})", line_start + error.column().saturating_sub(1))
+        }
+    };
+    match location {
+        Some(location) => anyhow!("{}: {}{}", location, error, offset),
+        None => anyhow!("{}{}", error, offset),
+    }
+}
+
+/// Parse a database from a reader one entry at a time, keeping only the entries not dropped by
+/// --exclude; accepts the same forms as `parse`
+pub fn parse_stream(reader: impl BufRead) -> Result<Vec<CompileCommand>> {
+    stream(reader, false)
+}
+
+/// Parse a database `parse_stream` failed on again, keeping track of where to tell which entry
+/// and field are broken
+pub fn parse_stream_located(reader: impl BufRead) -> Result<Vec<CompileCommand>> {
+    stream(reader, true)
+}
+
+fn stream(mut reader: impl BufRead, tracked: bool) -> Result<Vec<CompileCommand>> {
+    let buffered = reader.fill_buf()?;
+    if buffered.starts_with(b"\xEF\xBB\xBF") {
+        reader.consume(3);
+    }
+    let first = loop {
+        let buffered = reader.fill_buf()?;
+        match buffered.iter().position(|byte| !byte.is_ascii_whitespace()) {
+            Some(position) => break Some(buffered[position]),
+            None if buffered.is_empty() => break None,
+            None => {
+                let consumed = buffered.len();
+                reader.consume(consumed);
+            }
+        }
+    };
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    let mut track = Track::new();
+    if first != Some(b'{') {
+        let commands = match tracked {
+            true => serde_path_to_error::Deserializer::new(&mut deserializer, &mut track)
+                .deserialize_seq(Entries)
+                .map_err(|e| streamed(track, e))?,
+            false => deserializer.deserialize_seq(Entries)?,
+        };
+        deserializer.end()?;
+        return Ok(commands);
+    }
+    let first = match tracked {
+        true => serde_path_to_error::Deserializer::new(&mut deserializer, &mut track)
+            .deserialize_map(First)
+            .map_err(|e| streamed(track, e))?,
+        false => deserializer.deserialize_map(First)?,
+    };
+    match first {
+        FirstObject::Wrapped(commands) => {
+            deserializer.end()?;
+            Ok(commands)
+        }
+        FirstObject::Entry(first) => {
+            let mut commands: Vec<CompileCommand> = first.into_iter().collect();
+            for (index, command) in deserializer.into_iter::<BorrowedCommand<'static>>().enumerate() {
+                let command = command.map_err(|e| {
+                    anyhow!("Neither an object with a commands array nor newline-delimited entries: Entry {}: {}", index + 1, e)
+                })?;
+                if !is_excluded(&command.file) {
+                    commands.push(command.into_owned()?);
+                }
+            }
+            Ok(commands)
+        }
+    }
+}
+
+/// A parse error of a streamed database with where it happened, whose byte offset isn't known
+fn streamed(track: Track, error: serde_json::Error) -> anyhow::Error {
+    let path = track.path();
+    let mut segments = path.iter().skip_while(|segment| !matches!(segment, Segment::Seq { .. }));
+    match segments.next() {
+        Some(Segment::Seq { index }) => anyhow!("{}: {}", entry_location(*index, segments), error),
+        _ => error.into(),
+    }
+}
+
+/// Collects the entries of an array as they are parsed. Readers lend nothing, so the entries'
+/// strings are always owned and the lifetime is free.
+struct Entries;
+
+impl<'de> Visitor<'de> for Entries {
+    type Value = Vec<CompileCommand>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an array of compile commands")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut commands = Vec::new();
+        while let Some(command) = seq.next_element::<BorrowedCommand<'de>>()? {
+            if !is_excluded(&command.file) {
+                commands.push(command.into_owned().map_err(de::Error::custom)?);
+            }
+        }
+        Ok(commands)
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for Entries {
+    type Value = Vec<CompileCommand>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+/// The first object of a database starting with one: a wrapper around its entries, or the
+/// first of newline-delimited entries (`None` when --exclude drops it)
+enum FirstObject {
+    Wrapped(Vec<CompileCommand>),
+    Entry(Option<CompileCommand>),
+}
+
+/// Tells which kind of object a database starts with
+struct First;
+
+impl<'de> Visitor<'de> for First {
+    type Value = FirstObject;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an object with a commands array, or a compile command")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut fields = serde_json::Map::new();
+        let mut commands = None;
+        while let Some(key) = map.next_key::<String>()? {
+            if commands.is_none() && WRAPPED_KEYS.contains(&key.as_str()) {
+                commands = Some(map.next_value_seed(Entries)?);
+            } else if commands.is_some() {
+                map.next_value::<IgnoredAny>()?;
+            } else {
+                fields.insert(key, map.next_value()?);
+            }
+        }
+        if let Some(commands) = commands {
+            return Ok(FirstObject::Wrapped(commands));
+        }
+        let command = BorrowedCommand::deserialize(serde_json::Value::Object(fields)).map_err(de::Error::custom)?;
+        match is_excluded(&command.file) {
+            true => Ok(FirstObject::Entry(None)),
+            false => Ok(FirstObject::Entry(Some(command.into_owned().map_err(de::Error::custom)?))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::Settings;
+    use std::sync::Arc;
+
+    fn files(commands: &[CompileCommand]) -> Vec<&str> {
+        commands.iter().map(|command| command.file.as_str()).collect()
+    }
+
+    fn error(bytes: &[u8]) -> String {
+        parse(bytes).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn parses_every_shape() {
+        let shapes: &[&[u8]] = &[
+            br#"[{"directory":"/d","command":"cc -c a.c","file":"a.c"},{"directory":"/d","command":"cc -c b.c","file":"b.c"}]"#,
+            br#"{"commands":[{"directory":"/d","command":"cc -c a.c","file":"a.c"},{"directory":"/d","command":"cc -c b.c","file":"b.c"}],"version":2}"#,
+            br#"{"version":2,"entries":[{"directory":"/d","command":"cc -c a.c","file":"a.c"},{"directory":"/d","command":"cc -c b.c","file":"b.c"}]}"#,
+            b"{\"directory\":\"/d\",\"command\":\"cc -c a.c\",\"file\":\"a.c\"}\n{\"directory\":\"/d\",\"command\":\"cc -c b.c\",\"file\":\"b.c\"}\n",
+            b"\xEF\xBB\xBF[{\"directory\":\"/d\",\"command\":\"cc -c a.c\",\"file\":\"a.c\"},{\"directory\":\"/d\",\"command\":\"cc -c b.c\",\"file\":\"b.c\"}]",
+        ];
+        for shape in shapes {
+            let commands = parse(shape).unwrap();
+            assert_eq!(files(&commands), ["a.c", "b.c"], "{}", String::from_utf8_lossy(shape));
+            assert_eq!(commands[0].command, "cc -c a.c");
+            let streamed = parse_stream(*shape).unwrap();
+            assert_eq!(files(&streamed), ["a.c", "b.c"], "{}", String::from_utf8_lossy(shape));
+        }
+    }
+
+    #[test]
+    fn joins_arguments_and_keeps_other_fields() {
+        let commands = parse(br#"[{"directory":"/d","arguments":["cc","-c","a b.c"],"file":"a b.c","output":"a.o","language":"c"}]"#).unwrap();
+        assert_eq!(commands[0].command, join_args(&["cc", "-c", "a b.c"]));
+        assert_eq!(commands[0].output.as_deref(), Some("a.o"));
+        assert_eq!(commands[0].extra["language"], "c");
+    }
+
+    #[test]
+    fn locates_errors_by_entry_and_field() {
+        let array = error(br#"[{"directory":"/d","arguments":["cc","-c",3],"file":"a.c"}]"#);
+        assert!(array.starts_with("Entry 0, field `arguments[2]`: invalid type"), "{}", array);
+        let wrapped = error(br#"{"entries":[{"directory":"/d","command":"cc","file":"a.c"},{"directory":"/d","file":1}]}"#);
+        assert!(wrapped.starts_with("Entry 1, field `file`: invalid type"), "{}", wrapped);
+        let lines = error(b"{\"directory\":\"/d\",\"command\":\"cc a.c\",\"file\":\"a.c\"}\n{\"directory\":\"/d\",\"file\":5}\n");
+        assert!(lines.contains("Entry 1, field `file`: invalid type"), "{}", lines);
+        let duplicate = error(br#"{"commands":[{"directory":"/d","command":"cc","file":"a.c","file":"b.c"}]}"#);
+        assert!(duplicate.starts_with("Entry 0: duplicate field `file`"), "{}", duplicate);
+        let missing = error(br#"[{"directory":"/d","command":"cc -c a.c"}]"#);
+        assert!(missing.starts_with("Entry 0: missing field `file`"), "{}", missing);
+    }
+
+    #[test]
+    fn drops_excluded_entries_of_the_state_parsing() {
+        let bytes = br#"[{"directory":"/d","command":"cc -c a.c","file":"a.c"},{"directory":"/d","command":"cc -c tp/b.c","file":"tp/b.c"}]"#;
+        let settings = Arc::new(Settings {
+            excludes: vec!["tp/*".to_string()],
+            ..Settings::default()
+        });
+        {
+            let _settings = settings.enter();
+            assert_eq!(files(&parse(bytes).unwrap()), ["a.c"]);
+        }
+        assert_eq!(files(&parse(bytes).unwrap()), ["a.c", "tp/b.c"]);
+    }
+}
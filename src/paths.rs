@@ -0,0 +1,303 @@
+use crate::compiler::compiler_index;
+use anyhow::{bail, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::path::{Component, Path, PathBuf};
+
+/// Path convention used when translating between WSL and Windows
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PathStyle {
+    /// Pick the convention of the environment the merger runs in
+    Auto,
+    /// `C:\...` paths for Windows-native tooling
+    Windows,
+    /// `/mnt/c/...` paths for tooling inside WSL
+    Wsl,
+}
+
+impl PathStyle {
+    /// Resolve `Auto` to a concrete style, or `None` when not running under WSL or Windows
+    pub fn resolve(self) -> Option<PathStyle> {
+        match self {
+            PathStyle::Auto if cfg!(windows) => Some(PathStyle::Windows),
+            PathStyle::Auto if is_wsl() => Some(PathStyle::Wsl),
+            PathStyle::Auto => None,
+            style => Some(style),
+        }
+    }
+}
+
+/// Detect whether we are running inside WSL with Windows interop available
+fn is_wsl() -> bool {
+    std::env::var_os("WSL_DISTRO_NAME").is_some()
+        || Path::new("/proc/sys/fs/binfmt_misc/WSLInterop").exists()
+}
+
+/// Translate every WSL or Windows path found in `text` to the given style
+pub fn translate(text: &str, style: PathStyle) -> String {
+    let bytes = text.as_bytes();
+    let mut result = String::with_capacity(text.len());
+    let mut i = 0;
+    let mut copied = 0;
+    while i < bytes.len() {
+        let drive = match style {
+            PathStyle::Windows => wsl_drive_at(bytes, i),
+            _ => windows_drive_at(bytes, i),
+        };
+        match drive {
+            Some((letter, prefix_len)) if at_path_boundary(text, i, style != PathStyle::Windows) => {
+                let quoted = i > 0 && matches!(bytes[i - 1], b'"' | b'\'');
+                let end = path_end(bytes, i + prefix_len, quoted);
+                let rest = &text[i + prefix_len..end];
+                result.push_str(&text[copied..i]);
+                match style {
+                    PathStyle::Windows => {
+                        result.push(letter.to_ascii_uppercase());
+                        result.push_str(":\\");
+                        result.push_str(&rest.replace('/', "\\"));
+                    }
+                    _ => {
+                        result.push_str("/mnt/");
+                        result.push(letter.to_ascii_lowercase());
+                        if !rest.is_empty() {
+                            result.push('/');
+                        }
+                        result.push_str(&rest.replace('\\', "/"));
+                    }
+                }
+                i = end;
+                copied = end;
+            }
+            _ => i += 1,
+        }
+    }
+    result.push_str(&text[copied..]);
+    result
+}
+
+/// Match `/mnt/<letter>` at `i`, returning the drive letter and the length of the prefix
+fn wsl_drive_at(bytes: &[u8], i: usize) -> Option<(char, usize)> {
+    let rest = &bytes[i..];
+    if rest.len() < 6 || &rest[..5] != b"/mnt/" || !rest[5].is_ascii_alphabetic() {
+        return None;
+    }
+    match rest.get(6) {
+        None => Some((rest[5] as char, 6)),
+        Some(b'/') => Some((rest[5] as char, 7)),
+        Some(c) if c.is_ascii_whitespace() || matches!(c, b'"' | b'\'') => Some((rest[5] as char, 6)),
+        Some(_) => None,
+    }
+}
+
+/// Match `<letter>:\` or `<letter>:/` at `i`, returning the drive letter and the length of the prefix
+fn windows_drive_at(bytes: &[u8], i: usize) -> Option<(char, usize)> {
+    let rest = &bytes[i..];
+    if rest.len() >= 3 && rest[0].is_ascii_alphabetic() && rest[1] == b':' && matches!(rest[2], b'\\' | b'/') {
+        Some((rest[0] as char, 3))
+    } else {
+        None
+    }
+}
+
+/// Whether a path is absolute in either Unix or Windows form, whichever host reads it
+pub fn is_absolute_anywhere(path: &str) -> bool {
+    path.starts_with(['/', '\\']) || windows_drive_at(path.as_bytes(), 0).is_some()
+}
+
+/// Whether a path starting at `i` begins a new path rather than continuing another word.
+/// `msvc_options` also accepts joined values of `/`-prefixed options such as `/IC:\include`.
+fn at_path_boundary(text: &str, i: usize, msvc_options: bool) -> bool {
+    let before = &text[..i];
+    match before.chars().next_back() {
+        None => true,
+        Some(c) if c.is_whitespace() || matches!(c, '"' | '\'' | '=' | ',' | ';') => true,
+        // Joined option values such as `-I/mnt/c/include` or `-isystemC:\include`
+        Some(c) if c.is_ascii_alphabetic() => before
+            .rsplit(char::is_whitespace)
+            .next()
+            .is_some_and(|word| {
+                let is_option = word.starts_with('-') || (msvc_options && word.starts_with('/'));
+                is_option && !word[1..].contains(['/', '\\'])
+            }),
+        Some(_) => false,
+    }
+}
+
+/// Find where a path starting at `start` ends
+fn path_end(bytes: &[u8], start: usize, quoted: bool) -> usize {
+    bytes[start..]
+        .iter()
+        .position(|&c| matches!(c, b'"' | b'\'') || (!quoted && c.is_ascii_whitespace()))
+        .map_or(bytes.len(), |offset| start + offset)
+}
+
+/// Lexically resolve `.` and `..` components without touching the filesystem
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if matches!(result.components().next_back(), Some(Component::Normal(_))) => {
+                result.pop();
+            }
+            component => result.push(component),
+        }
+    }
+    result
+}
+
+/// Options taking an include directory, either joined (`-Iinc`) or as the next argument
+const INCLUDE_OPTIONS: &[&str] = &["-isystem", "-iquote", "-idirafter", "-I"];
+
+/// Resolve relative include directories against the entry's directory, returning whether any changed
+pub fn absolutize_includes(args: &mut [String], directory: &str) -> bool {
+    let directory = Path::new(directory);
+    let resolve = |dir: &str| {
+        let path = Path::new(dir);
+        (!dir.is_empty() && path.is_relative()).then(|| normalize(&directory.join(path)).display().to_string())
+    };
+    let mut changed = false;
+    let mut separate_value = false; // The previous argument was an option expecting this directory
+    for arg in args.iter_mut() {
+        if std::mem::take(&mut separate_value) {
+            if let Some(resolved) = resolve(arg) {
+                *arg = resolved;
+                changed = true;
+            }
+            continue;
+        }
+        let Some(option) = INCLUDE_OPTIONS.iter().find(|option| arg.starts_with(*option)) else {
+            continue;
+        };
+        if arg.len() == option.len() {
+            separate_value = true;
+        } else if let Some(resolved) = resolve(&arg[option.len()..]) {
+            *arg = format!("{}{}", option, resolved);
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Options taking a path, either joined (`-Iinc`, `-oout.o`, `--sysroot=dir`) or as the next argument
+const PATH_OPTIONS: &[&str] = &[
+    "-isystem", "-iquote", "-idirafter", "-isysroot", "-include", "-imacros", "--sysroot=", "--sysroot", "-MF", "-I", "-L", "-B",
+    "-o",
+];
+
+/// Options whose next argument is a value rather than a path, even when it looks like one
+const VALUE_OPTIONS: &[&str] = &[
+    "-x", "-MT", "-MQ", "-arch", "-target", "-Xclang", "-Xlinker", "-Xassembler", "-Xpreprocessor", "-D", "-U",
+];
+
+/// A relative path, given from `directory`, as seen from `root`: relative to it when inside it,
+/// absolute otherwise. `None` if it needs no change.
+pub fn rebase_path(path: &str, directory: &Path, root: &Path) -> Option<String> {
+    if path.is_empty() || Path::new(path).is_absolute() {
+        return None;
+    }
+    let absolute = normalize(&directory.join(path));
+    let rebased = match absolute.strip_prefix(root) {
+        Ok(relative) if relative.as_os_str().is_empty() => ".".to_string(),
+        Ok(relative) => relative.display().to_string(),
+        Err(_) => absolute.display().to_string(),
+    };
+    (rebased != path).then_some(rebased)
+}
+
+/// Make the relative paths among a command's arguments work from `root` rather than from the
+/// entry's directory, returning whether any changed
+pub fn rebase_args(args: &mut [String], directory: &str, root: &Path) -> bool {
+    let directory = Path::new(directory);
+    let compiler = compiler_index(args).unwrap_or(0);
+    let mut changed = false;
+    let mut next = None; // Whether the previous argument was an option expecting a path, or another value
+    for (index, arg) in args.iter_mut().enumerate() {
+        let rebased = match next.take() {
+            Some(true) => rebase_path(arg, directory, root),
+            Some(false) => None,
+            // Launchers and compilers are looked up in PATH unless they name a path
+            None if index <= compiler => arg.contains(['/', '\\']).then(|| rebase_path(arg, directory, root)).flatten(),
+            None if VALUE_OPTIONS.contains(&arg.as_str()) => {
+                next = Some(false);
+                None
+            }
+            None => match PATH_OPTIONS.iter().find(|option| arg.starts_with(*option)) {
+                Some(option) if arg.len() == option.len() => {
+                    next = Some(true);
+                    None
+                }
+                Some(option) => rebase_path(&arg[option.len()..], directory, root).map(|path| format!("{}{}", option, path)),
+                None if arg.starts_with('@') => rebase_path(&arg[1..], directory, root).map(|path| format!("@{}", path)),
+                None if arg.starts_with('-') => None,
+                // Sources and objects, told apart from other values by an extension or a directory
+                None if arg.contains(['/', '\\']) || Path::new(arg.as_str()).extension().is_some() => {
+                    rebase_path(arg, directory, root)
+                }
+                None => None,
+            },
+        };
+        if let Some(rebased) = rebased {
+            *arg = rebased;
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Path prefixes rewritten in entries (`FROM=TO`), e.g. a CI build tree to the local checkout
+#[derive(Clone, Debug, Default)]
+pub struct PathMap {
+    prefixes: Vec<(String, String)>,
+}
+
+impl PathMap {
+    /// Parse `FROM=TO` mappings, the first matching one applying to each path
+    pub fn new(specs: &[String]) -> Result<Self> {
+        let prefixes = specs
+            .iter()
+            .map(|spec| {
+                let trim = |path: &str| path.trim_end_matches(['/', '\\']).to_string();
+                match spec.split_once('=').map(|(from, to)| (trim(from), trim(to))) {
+                    Some((from, to)) if !from.is_empty() => Ok((from, to)),
+                    _ => bail!("Invalid path mapping '{}', expected FROM=TO", spec),
+                }
+            })
+            .collect::<Result<_>>()?;
+        Ok(PathMap { prefixes })
+    }
+
+    /// Mappings worked out rather than given, in the order they apply
+    pub fn from_prefixes(prefixes: Vec<(String, String)>) -> Self {
+        PathMap { prefixes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prefixes.is_empty()
+    }
+
+    /// Rewrite the mapped prefixes of every path in a text, such as a command
+    pub fn apply(&self, text: &str) -> String {
+        let mut result = String::with_capacity(text.len());
+        let mut i = 0;
+        'scan: while i < text.len() {
+            if at_path_boundary(text, i, true) {
+                for (from, to) in &self.prefixes {
+                    let ends_path = text[i..].strip_prefix(from.as_str()).is_some_and(|rest| {
+                        rest.chars().next().is_none_or(|c| c.is_whitespace() || matches!(c, '/' | '\\' | '"' | '\'' | ',' | ';'))
+                    });
+                    if ends_path {
+                        result.push_str(to);
+                        i += from.len();
+                        continue 'scan;
+                    }
+                }
+            }
+            let c = text[i..].chars().next().unwrap_or_default();
+            result.push(c);
+            i += c.len_utf8();
+        }
+        result
+    }
+}
@@ -0,0 +1,80 @@
+use crate::compiler::{compiler_index, executable_name};
+use std::path::Path;
+
+/// How precompiled-header flags are handled
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PchMode {
+    /// Remove them
+    Strip,
+    /// Include the source header instead where it can be found, removing the rest
+    Rewrite,
+}
+
+/// Header extensions tried when looking for the source of a precompiled header
+const HEADER_EXTENSIONS: &[&str] = &["h", "hh", "hpp", "hxx"];
+
+/// Strip or rewrite precompiled-header flags, returning whether the arguments changed
+pub fn apply(args: &mut Vec<String>, mode: PchMode, directory: &str) -> bool {
+    let is_msvc = compiler_index(args).is_some_and(|index| matches!(executable_name(&args[index]), "cl" | "clang-cl"));
+    let mut result = Vec::with_capacity(args.len());
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i];
+        // CMake passes clang's PCH through the driver as `-Xclang -include-pch -Xclang FILE`
+        if arg == "-Xclang" && args.get(i + 1).is_some_and(|next| next == "-include-pch") {
+            if let (Some(_), Some(pch)) = (args.get(i + 2), args.get(i + 3)) {
+                if mode == PchMode::Rewrite {
+                    // CMake also force-includes the header itself, so it usually needn't be added
+                    if let Some(header) = source_header(pch, directory).filter(|header| !args.contains(header)) {
+                        result.extend(["-include".to_string(), header]);
+                    }
+                }
+                i += 4;
+                continue;
+            }
+        }
+        if arg == "-include-pch" {
+            if let (PchMode::Rewrite, Some(header)) = (mode, args.get(i + 1).and_then(|pch| source_header(pch, directory))) {
+                result.extend(["-include".to_string(), header]);
+            }
+            i += 2;
+            continue;
+        }
+        // MSVC: `/Yuheader.h` uses a PCH built from `header.h`, which can be force-included instead
+        let msvc = arg.strip_prefix('/').or_else(|| arg.strip_prefix('-')).filter(|_| is_msvc);
+        if let Some(header) = msvc.and_then(|option| option.strip_prefix("Yu")) {
+            if mode == PchMode::Rewrite && !header.is_empty() {
+                result.push(format!("/FI{}", header));
+            }
+            i += 1;
+            continue;
+        }
+        let is_pch_flag = msvc.is_some_and(|option| option.starts_with("Yc") || option.starts_with("Fp") || option == "Y-")
+            || arg.starts_with("-fpch-")
+            || arg.starts_with("-fno-pch-");
+        if !is_pch_flag {
+            result.push(arg.clone());
+        }
+        i += 1;
+    }
+    let changed = result != *args;
+    *args = result;
+    changed
+}
+
+/// The header a precompiled header was built from, if it exists next to it
+fn source_header(pch: &str, directory: &str) -> Option<String> {
+    let path = Path::new(pch);
+    let stem = path.with_extension(""); // `x.h.pch` -> `x.h`, `x.pch` -> `x`
+    let mut candidates = vec![stem.clone()];
+    candidates.extend(HEADER_EXTENSIONS.iter().map(|extension| stem.with_extension(extension)));
+    candidates
+        .into_iter()
+        .filter(|candidate| {
+            candidate
+                .extension()
+                .is_some_and(|extension| HEADER_EXTENSIONS.iter().any(|known| extension == *known))
+        })
+        .find(|candidate| Path::new(directory).join(candidate).is_file())
+        .map(|candidate| candidate.display().to_string())
+}
@@ -0,0 +1,87 @@
+use crate::CompileCommand;
+use anyhow::{bail, Context, Result};
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+
+/// External filter program that entries are streamed through as JSON lines.
+///
+/// Each database's entries are written to the program's stdin, one JSON object per line, and the
+/// entries it prints back in the same form replace them; it may drop, edit or add entries.
+pub struct PipeThrough {
+    command: String,
+}
+
+impl PipeThrough {
+    pub fn new(command: &str) -> Self {
+        PipeThrough {
+            command: command.to_string(),
+        }
+    }
+
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    /// Run a batch of entries through the program
+    pub fn run(&self, commands: &[CompileCommand]) -> Result<Vec<CompileCommand>> {
+        let mut child = shell_command(&self.command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("failed to start")?;
+
+        // Feed stdin from another thread, so a program writing as it reads can't deadlock
+        let mut stdin = child.stdin.take().context("no stdin")?;
+        let mut input = Vec::new();
+        for command in commands {
+            serde_json::to_writer(&mut input, command)?;
+            input.push(b'\n');
+        }
+        let writer = std::thread::spawn(move || stdin.write_all(&input));
+
+        let stdout = child.stdout.take().context("no stdout")?;
+        let transformed = read_entries(BufReader::new(stdout));
+        if transformed.is_err() {
+            let _ = child.kill();
+        }
+        let status = child.wait()?;
+        let transformed = transformed?;
+        if let Ok(Err(e)) = writer.join() {
+            // The program may legitimately stop reading early, e.g. `head`
+            if e.kind() != std::io::ErrorKind::BrokenPipe {
+                return Err(e.into());
+            }
+        }
+        if !status.success() {
+            bail!("exited with {}", status);
+        }
+        Ok(transformed)
+    }
+}
+
+/// Read entries printed one JSON object per line, skipping blank lines
+fn read_entries(output: impl BufRead) -> Result<Vec<CompileCommand>> {
+    let mut commands = Vec::new();
+    for (number, line) in output.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let command = serde_json::from_str(&line).with_context(|| format!("invalid entry on line {}", number + 1))?;
+        commands.push(command);
+    }
+    Ok(commands)
+}
+
+/// Run a command line through the platform shell
+fn shell_command(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C").arg(command);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c").arg(command);
+        shell
+    }
+}
@@ -0,0 +1,45 @@
+use crate::settings;
+use std::path::{Path, PathBuf};
+
+/// Where PlatformIO keeps the build directory of each environment of a project
+const BUILD_DIR: &str = ".pio/build";
+
+/// The environment a database was built for, if it lies in a PlatformIO build directory
+fn environment(database: &Path) -> Option<&str> {
+    let env_dir = database.parent()?;
+    env_dir.parent().is_some_and(|build| build.ends_with(BUILD_DIR)).then(|| env_dir.file_name()?.to_str())?
+}
+
+/// Whether a database belongs to an environment of the state's --pio-envs (any, where it names
+/// none), or to no environment at all
+pub fn is_selected(database: &Path) -> bool {
+    let selected = &settings::current().pio_envs;
+    match environment(database) {
+        Some(environment) if !selected.is_empty() => selected.iter().any(|name| name == environment),
+        _ => true,
+    }
+}
+
+/// Databases of a project's selected environments (`.pio/build/<env>/`, where projects setting
+/// `COMPILATIONDB_PATH` to `$BUILD_DIR` have `pio run -t compiledb` write them), given the project
+/// or its build directory. Projects without them have the one database at their root.
+pub fn discover(root: &Path, filename: &str) -> Vec<PathBuf> {
+    let build = if root.join(BUILD_DIR).is_dir() { root.join(BUILD_DIR) } else { root.to_path_buf() };
+    let mut databases: Vec<PathBuf> = std::fs::read_dir(build)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path().join(filename))
+        .filter(|database| database.is_file() && is_selected(database))
+        .collect();
+    databases.sort();
+    if databases.is_empty() && root.join(filename).is_file() {
+        return vec![root.join(filename)];
+    }
+    for name in &settings::current().pio_envs {
+        if !databases.iter().any(|database| environment(database) == Some(name.as_str())) {
+            warn!("Warning: Environment {} of {} has no {} (run pio run -e {} -t compiledb)", name, root.display(), filename, name);
+        }
+    }
+    databases
+}
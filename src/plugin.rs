@@ -0,0 +1,71 @@
+use crate::CompileCommand;
+use anyhow::{anyhow, bail, Result};
+use std::sync::Mutex;
+use wasmi::{Engine, Instance, Linker, Memory, Module, Store, TypedFunc};
+
+/// A WASM module rewriting or dropping entries.
+///
+/// The module exports `memory`, `alloc(len: i32) -> i32` returning a buffer the entry is copied
+/// into as JSON, and `transform(ptr: i32, len: i32) -> i64` returning the rewritten entry's JSON
+/// as `ptr << 32 | len`, or 0 to drop the entry.
+pub struct WasmPlugin {
+    path: String,
+    runtime: Mutex<Runtime>, // Calls need exclusive access to the store
+}
+
+struct Runtime {
+    store: Store<()>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    transform: TypedFunc<(i32, i32), i64>,
+}
+
+impl WasmPlugin {
+    pub fn load(path: &str) -> Result<Self> {
+        let wasm = std::fs::read(path).map_err(|e| anyhow!("Failed to read plugin {}: {}", path, e))?;
+        let engine = Engine::default();
+        let module = Module::new(&engine, wasm).map_err(|e| anyhow!("Invalid plugin {}: {}", path, e))?;
+        let mut store = Store::new(&engine, ());
+        let instance: Instance = Linker::new(&engine)
+            .instantiate_and_start(&mut store, &module)
+            .map_err(|e| anyhow!("Failed to instantiate plugin {}: {}", path, e))?;
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or_else(|| anyhow!("Plugin {} does not export its memory", path))?;
+        let alloc = instance
+            .get_typed_func(&store, "alloc")
+            .map_err(|e| anyhow!("Plugin {} lacks alloc(len: i32) -> i32: {}", path, e))?;
+        let transform = instance
+            .get_typed_func(&store, "transform")
+            .map_err(|e| anyhow!("Plugin {} lacks transform(ptr: i32, len: i32) -> i64: {}", path, e))?;
+        Ok(WasmPlugin {
+            path: path.to_string(),
+            runtime: Mutex::new(Runtime { store, memory, alloc, transform }),
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Run the plugin on an entry, returning `None` if it dropped the entry
+    pub fn transform(&self, command: &CompileCommand) -> Result<Option<CompileCommand>> {
+        let input = serde_json::to_vec(command)?;
+        let mut runtime = self.runtime.lock().unwrap_or_else(|e| e.into_inner());
+        let Runtime { store, memory, alloc, transform } = &mut *runtime;
+
+        let len = i32::try_from(input.len())?;
+        let ptr = alloc.call(&mut *store, len)?;
+        memory.write(&mut *store, ptr as u32 as usize, &input)?;
+        let result = transform.call(&mut *store, (ptr, len))?;
+        if result == 0 {
+            return Ok(None);
+        }
+
+        let (ptr, len) = ((result as u64 >> 32) as usize, (result as u64 & 0xffff_ffff) as usize);
+        let Some(output) = memory.data(&*store).get(ptr..ptr + len) else {
+            bail!("returned an out-of-bounds entry");
+        };
+        Ok(Some(serde_json::from_slice(output)?))
+    }
+}
@@ -0,0 +1,120 @@
+use crate::{compiler, CompileCommand};
+
+/// An entry for a file as a [`MergePolicy`] sees it
+pub struct Candidate<'a> {
+    pub command: &'a CompileCommand,
+    pub source: &'a str, // Database the entry was read from
+    pub priority: i32,
+    pub derived: bool, // Generated by the merger, e.g. from a unity file, rather than read
+}
+
+/// What a [`MergePolicy`] makes of an entry for a file that already has one
+pub enum Resolution {
+    /// Keep the entry already merged
+    KeepExisting,
+    /// Replace it with the incoming one
+    TakeIncoming,
+    /// Replace it with this combination of both
+    Combine(CompileCommand),
+    /// Keep both, as entries of their own
+    KeepBoth,
+}
+
+/// Decides between the merged entry of a file and another one for it from a database being
+/// merged, so that rules of an organisation don't need a fork
+pub trait MergePolicy: Send + Sync {
+    fn resolve(&self, existing: &Candidate, incoming: &Candidate) -> Resolution;
+}
+
+/// The default policy: an entry read wins over a derived one, and otherwise the one from the
+/// higher-priority database, the latest one where they are as high
+pub struct PreferPriority;
+
+impl MergePolicy for PreferPriority {
+    fn resolve(&self, existing: &Candidate, incoming: &Candidate) -> Resolution {
+        let keep_existing = match (existing.derived, incoming.derived) {
+            (false, true) => true,
+            (true, false) => false,
+            _ => existing.priority > incoming.priority,
+        };
+        match keep_existing {
+            true => Resolution::KeepExisting,
+            false => Resolution::TakeIncoming,
+        }
+    }
+}
+
+/// Keeps an entry for every distinct command of a file, deciding between equivalent ones like
+/// [`PreferPriority`], for tools that check each way a file is built
+pub struct KeepDistinct;
+
+impl MergePolicy for KeepDistinct {
+    fn resolve(&self, existing: &Candidate, incoming: &Candidate) -> Resolution {
+        match equivalent(existing.command, incoming.command) && existing.command.directory == incoming.command.directory {
+            true => PreferPriority.resolve(existing, incoming),
+            false => Resolution::KeepBoth,
+        }
+    }
+}
+
+/// Whether two entries run the same command, however their compiler is spelled
+pub fn equivalent(a: &CompileCommand, b: &CompileCommand) -> bool {
+    a.command == b.command
+        || compiler::normalized_command(&a.command, &a.directory) == compiler::normalized_command(&b.command, &b.directory)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(command: &str, directory: &str) -> CompileCommand {
+        CompileCommand {
+            directory: directory.to_string(),
+            command: command.to_string(),
+            file: "a.c".to_string(),
+            output: None,
+            extra: Default::default(),
+            original_compiler: None,
+        }
+    }
+
+    fn candidate<'a>(command: &'a CompileCommand, priority: i32, derived: bool) -> Candidate<'a> {
+        Candidate {
+            command,
+            source: "compile_commands.json",
+            priority,
+            derived,
+        }
+    }
+
+    fn outcome(resolution: Resolution) -> &'static str {
+        match resolution {
+            Resolution::KeepExisting => "keep existing",
+            Resolution::TakeIncoming => "take incoming",
+            Resolution::Combine(_) => "combine",
+            Resolution::KeepBoth => "keep both",
+        }
+    }
+
+    #[test]
+    fn prefers_read_entries_then_priority_then_the_latest() {
+        let (a, b) = (command("cc -c a.c", "/d"), command("cc -O2 -c a.c", "/d"));
+        let resolve = |existing: Candidate, incoming: Candidate| outcome(PreferPriority.resolve(&existing, &incoming));
+        assert_eq!(resolve(candidate(&a, 0, false), candidate(&b, 0, false)), "take incoming");
+        assert_eq!(resolve(candidate(&a, 1, false), candidate(&b, 0, false)), "keep existing");
+        assert_eq!(resolve(candidate(&a, 0, false), candidate(&b, 1, false)), "take incoming");
+        assert_eq!(resolve(candidate(&a, 0, false), candidate(&b, 5, true)), "keep existing");
+        assert_eq!(resolve(candidate(&a, 5, true), candidate(&b, 0, false)), "take incoming");
+    }
+
+    #[test]
+    fn keeps_distinct_commands_apart() {
+        let (a, b) = (command("cc -c a.c", "/d"), command("cc -O2 -c a.c", "/d"));
+        let elsewhere = command("cc -c a.c", "/e");
+        let resolve = |existing: Candidate, incoming: Candidate| outcome(KeepDistinct.resolve(&existing, &incoming));
+        assert_eq!(resolve(candidate(&a, 0, false), candidate(&b, 0, false)), "keep both");
+        assert_eq!(resolve(candidate(&a, 0, false), candidate(&elsewhere, 0, false)), "keep both");
+        assert_eq!(resolve(candidate(&a, 1, false), candidate(&a.clone(), 0, false)), "keep existing");
+        assert_eq!(resolve(candidate(&a, 0, false), candidate(&a.clone(), 0, false)), "take incoming");
+    }
+}
@@ -0,0 +1,22 @@
+use crate::settings;
+use serde_json::{json, Value};
+use std::io::Write;
+
+/// Whether events are printed to stdout, by the state's --porcelain; messages move to stderr,
+/// leaving stdout to the events
+pub fn enabled() -> bool {
+    settings::current().porcelain
+}
+
+/// Print an event as a single-line JSON object on stdout: `{"event": NAME, ...fields}`
+pub fn event(name: &str, fields: Value) {
+    if !enabled() {
+        return;
+    }
+    let mut object = json!({ "event": name });
+    if let (Some(object), Value::Object(fields)) = (object.as_object_mut(), fields) {
+        object.extend(fields);
+    }
+    let mut stdout = std::io::stdout().lock();
+    let _ = writeln!(stdout, "{}", object).and_then(|_| stdout.flush());
+}
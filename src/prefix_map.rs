@@ -0,0 +1,90 @@
+use crate::paths::PathMap;
+use crate::shell::{join_args, split_args};
+use crate::CompileCommand;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::path::Path;
+
+/// Options mapping a path prefix of the build to another in what the compiler emits (OLD=NEW)
+const PREFIX_MAP_OPTIONS: &[&str] = &[
+    "-ffile-prefix-map=",
+    "-fdebug-prefix-map=",
+    "-fmacro-prefix-map=",
+    "-fprofile-prefix-map=",
+    "-fcoverage-prefix-map=",
+];
+
+/// What to do with the prefix-map options of reproducible builds
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PrefixMapMode {
+    /// Rewrite the entry's paths that don't exist to the side of its mappings that does
+    Apply,
+    /// Remove the options, leaving paths as they are
+    Strip,
+}
+
+/// Handle the prefix-map options of an entry, as set by e.g. Debian, Nix and Yocto builds
+pub fn apply(mut command: CompileCommand, mode: PrefixMapMode) -> CompileCommand {
+    let mut args = split_args(&command.command);
+    if !args.iter().any(|arg| is_prefix_map(arg)) {
+        return command;
+    }
+    if mode == PrefixMapMode::Strip {
+        args.retain(|arg| !is_prefix_map(arg));
+        command.command = join_args(&args);
+        return command;
+    }
+    let Some(map) = choose(&command, &mappings(&args)) else {
+        return command;
+    };
+    command.directory = map.apply(&command.directory);
+    command.command = join_args(
+        &args
+            .iter()
+            .map(|arg| if is_prefix_map(arg) { arg.clone() } else { map.apply(arg) })
+            .collect::<Vec<_>>(),
+    );
+    command.file = map.apply(&command.file);
+    command.output = command.output.map(|output| map.apply(&output));
+    command
+}
+
+fn is_prefix_map(arg: &str) -> bool {
+    PREFIX_MAP_OPTIONS.iter().any(|option| arg.starts_with(option))
+}
+
+/// The absolute OLD=NEW pairs of an entry's options; a relative NEW (commonly `.`) names no place
+fn mappings(args: &[String]) -> Vec<(String, String)> {
+    let mut mappings: Vec<(String, String)> = args
+        .iter()
+        .filter_map(|arg| PREFIX_MAP_OPTIONS.iter().find_map(|option| arg.strip_prefix(option)))
+        .filter_map(|mapping| mapping.split_once('='))
+        .filter(|(old, new)| Path::new(old).is_absolute() && Path::new(new).is_absolute())
+        .map(|(old, new)| (old.trim_end_matches('/').to_string(), new.trim_end_matches('/').to_string()))
+        .collect();
+    mappings.dedup();
+    mappings
+}
+
+/// The direction to rewrite an entry's paths in: the first mapping, either way, that takes its
+/// directory (or else its file) from a path that doesn't exist to one that does
+fn choose(command: &CompileCommand, mappings: &[(String, String)]) -> Option<PathMap> {
+    let directions = mappings
+        .iter()
+        .flat_map(|(old, new)| [(old.clone(), new.clone()), (new.clone(), old.clone())]);
+    let file = Path::new(&command.directory).join(&command.file).display().to_string();
+    for path in [&command.directory, &file] {
+        if Path::new(path).exists() {
+            continue;
+        }
+        for (from, to) in directions.clone() {
+            let map = PathMap::from_prefixes(vec![(from, to)]);
+            let mapped = map.apply(path);
+            if mapped != *path && Path::new(&mapped).exists() {
+                return Some(map);
+            }
+        }
+    }
+    None
+}
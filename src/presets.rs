@@ -0,0 +1,133 @@
+use crate::aosp;
+use crate::gn;
+use crate::platformio;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Ecosystem whose workspaces produce several databases per application or build directory
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WorkspacePreset {
+    /// Zephyr, including sysbuild images such as MCUboot
+    Zephyr,
+    /// ESP-IDF application and bootloader builds
+    EspIdf,
+    /// Raspberry Pi Pico SDK builds and their host tools
+    Pico,
+    /// GN (Chromium-style) checkouts with build directories under out/, exported with gn gen if needed
+    Gn,
+    /// ROS colcon and catkin workspaces, with a database per package under build/
+    Colcon,
+    /// PlatformIO projects, with a database per environment under .pio/build/
+    Platformio,
+    /// Android (AOSP) checkouts, with Soong's database of each out directory, picked with --lunch-targets
+    Aosp,
+}
+
+impl WorkspacePreset {
+    /// Find the databases of a workspace, given either the application or its build directory
+    pub fn discover(self, root: &Path, filename: &str) -> Vec<PathBuf> {
+        if self == WorkspacePreset::Gn {
+            return gn::discover(root, filename);
+        }
+        if self == WorkspacePreset::Platformio {
+            return platformio::discover(root, filename);
+        }
+        if self == WorkspacePreset::Aosp {
+            return aosp::discover(root, filename);
+        }
+        let build_dir = if root.join("build").is_dir() {
+            root.join("build")
+        } else {
+            root.to_path_buf()
+        };
+
+        let mut results = Vec::new();
+        let top = build_dir.join(filename);
+        if top.is_file() {
+            results.push(top);
+        }
+        // Secondary images (sysbuild, bootloader, host tools) and colcon packages live one level down
+        if let Ok(entries) = std::fs::read_dir(&build_dir) {
+            let mut nested: Vec<PathBuf> = entries
+                .flatten()
+                .map(|entry| entry.path().join(filename))
+                .filter(|path| path.is_file())
+                .collect();
+            nested.sort();
+            results.extend(nested);
+        }
+        results
+    }
+
+    /// Priority of entries from a database; higher wins when several define the same file
+    pub fn priority(self, database: &Path) -> i32 {
+        let secondary: &[&str] = match self {
+            WorkspacePreset::Gn => return gn::priority(database),
+            WorkspacePreset::Colcon => return 0, // Packages don't share sources
+            WorkspacePreset::Platformio => return 0, // Environments are picked with --pio-envs
+            WorkspacePreset::Aosp => return aosp::priority(database),
+            WorkspacePreset::Zephyr => &["mcuboot", "b0", "b0n", "s1_image"],
+            WorkspacePreset::EspIdf => &["bootloader"],
+            WorkspacePreset::Pico => &["pioasm", "elf2uf2", "picotool"],
+        };
+        let is_secondary = database
+            .parent()
+            .and_then(Path::file_name)
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| secondary.contains(&name));
+        if is_secondary {
+            -1
+        } else {
+            0
+        }
+    }
+
+    /// GCC-only flags emitted by the ecosystem that clang-based tools reject, matched by prefix
+    pub fn unsupported_flags(self) -> &'static [&'static str] {
+        match self {
+            WorkspacePreset::Zephyr => &[
+                "-fno-reorder-functions",
+                "-fno-defer-pop",
+                "-fno-printf-return-value",
+                "-fno-freestanding",
+                "-mfp16-format=",
+                "--param=min-pagesize=",
+                "-specs=",
+                "--specs=",
+            ],
+            WorkspacePreset::EspIdf => &[
+                "-mlongcalls",
+                "-fstrict-volatile-bitfields",
+                "-fno-tree-switch-conversion",
+                "-fno-shrink-wrap",
+                "-mtext-section-literals",
+                "-mdisable-hardware-atomics",
+                "-fzero-init-padding-bits=",
+                "-specs=",
+                "--specs=",
+            ],
+            WorkspacePreset::Pico => &["-specs=", "--specs=", "-fno-reorder-functions"],
+            WorkspacePreset::Gn => &[], // Chromium builds with clang
+            WorkspacePreset::Colcon => &[],
+            WorkspacePreset::Aosp => &[], // Soong builds with clang
+            // Xtensa (ESP32/ESP8266) and ARM GCC flags of the espressif and ststm32 platforms
+            WorkspacePreset::Platformio => &[
+                "-mlongcalls",
+                "-fstrict-volatile-bitfields",
+                "-fno-tree-switch-conversion",
+                "-fno-shrink-wrap",
+                "-mtext-section-literals",
+                "-mdisable-hardware-atomics",
+                "-mfix-esp32-psram-cache-issue",
+                "-mfix-esp32-psram-cache-strategy=",
+                "-fzero-init-padding-bits=",
+                "-fno-tree-loop-distribute-patterns",
+                "-fno-fat-lto-objects",
+                "-specs=",
+                "--specs=",
+            ],
+        }
+    }
+}
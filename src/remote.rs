@@ -0,0 +1,187 @@
+use crate::parse;
+use crate::shell::quote_arg;
+use crate::CompileCommand;
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::HashMap;
+use std::io::Read;
+use std::process::Command;
+use std::time::Duration;
+
+/// Longest wait for a remote host to accept a connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest wait for more of a response, so that a host that stops answering can't stall polling
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A change of a remote database, named by its origin
+pub enum Update {
+    /// It was created or modified, and holds these commands
+    Changed(String, Vec<CompileCommand>),
+    /// It is gone
+    Removed(String),
+}
+
+/// A remote input polled for updates
+pub enum RemoteSource {
+    Http(HttpSource),
+    Ssh(SshSource),
+}
+
+impl RemoteSource {
+    /// Human-readable name of the source
+    pub fn name(&self) -> &str {
+        match self {
+            RemoteSource::Http(source) => &source.url,
+            RemoteSource::Ssh(source) => &source.spec,
+        }
+    }
+
+    /// Poll the source, returning the databases that changed along with their origin
+    pub fn poll(&mut self) -> Result<Vec<Update>> {
+        match self {
+            RemoteSource::Http(source) => Ok(source
+                .fetch()?
+                .map(|commands| Update::Changed(source.url.clone(), commands))
+                .into_iter()
+                .collect()),
+            RemoteSource::Ssh(source) => source.fetch_changed(),
+        }
+    }
+}
+
+/// Poll every source, returning each one's name with its result
+pub fn poll_all(remotes: &mut [RemoteSource]) -> Vec<(String, Result<Vec<Update>>)> {
+    remotes
+        .iter_mut()
+        .map(|remote| {
+            let result = remote.poll();
+            (remote.name().to_string(), result)
+        })
+        .collect()
+}
+
+/// A compile_commands.json served over HTTP(S), re-polled with conditional requests
+pub struct HttpSource {
+    pub url: String,
+    agent: ureq::Agent,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl HttpSource {
+    pub fn new(url: &str) -> Self {
+        HttpSource {
+            url: url.to_string(),
+            agent: ureq::AgentBuilder::new().timeout_connect(CONNECT_TIMEOUT).timeout_read(READ_TIMEOUT).build(),
+            etag: None,
+            last_modified: None,
+        }
+    }
+
+    /// Fetch the remote database, returning `None` if it is unchanged since the last fetch
+    pub fn fetch(&mut self) -> Result<Option<Vec<CompileCommand>>> {
+        let mut request = self.agent.get(&self.url);
+        if let Some(etag) = &self.etag {
+            request = request.set("If-None-Match", etag);
+        }
+        if let Some(last_modified) = &self.last_modified {
+            request = request.set("If-Modified-Since", last_modified);
+        }
+
+        let response = match request.call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(code, _)) => bail!("HTTP {} from {}", code, self.url),
+            Err(err) => return Err(err.into()),
+        };
+        if response.status() == 304 {
+            return Ok(None);
+        }
+
+        self.etag = response.header("ETag").map(str::to_string);
+        self.last_modified = response.header("Last-Modified").map(str::to_string);
+        let mut content = Vec::new();
+        response.into_reader().read_to_end(&mut content)?;
+        Ok(Some(parse::parse(&content)?))
+    }
+}
+
+/// A directory tree on a remote host, enumerated and fetched over SSH
+pub struct SshSource {
+    pub spec: String,
+    host: String,
+    root: String,
+    filename: String,
+    mtimes: HashMap<String, String>, // Last seen modification time keyed by remote path
+}
+
+impl SshSource {
+    /// Parse a `user@host:/path/to/tree` specification
+    pub fn new(spec: &str, filename: &str) -> Result<Self> {
+        let (host, root) = spec
+            .split_once(':')
+            .filter(|(host, root)| !host.is_empty() && !root.is_empty())
+            .ok_or_else(|| anyhow!("Invalid remote '{}', expected user@host:/path", spec))?;
+        Ok(SshSource {
+            spec: spec.to_string(),
+            host: host.to_string(),
+            root: root.to_string(),
+            filename: filename.to_string(),
+            mtimes: HashMap::new(),
+        })
+    }
+
+    /// Enumerate databases under the remote root, fetching those modified since the last poll
+    /// and reporting those deleted. A database that can't be fetched is tried again next poll.
+    pub fn fetch_changed(&mut self) -> Result<Vec<Update>> {
+        // `stat -c` of GNU systems, or `stat -f` of BSD and macOS ones, rather than GNU find's `-printf`
+        let find = format!("find {} -name {} -type f -exec stat", quote_arg(&self.root), quote_arg(&self.filename));
+        let listing = self.ssh(&format!("{0} -c '%Y %n' {{}} + 2>/dev/null || {0} -f '%m %N' {{}} +", find))?;
+        let listed: Vec<(&str, &str)> = listing.lines().filter_map(|line| line.split_once(' ')).collect();
+
+        let mut updates = Vec::new();
+        for &(mtime, path) in &listed {
+            if self.mtimes.get(path).map(String::as_str) == Some(mtime) {
+                continue;
+            }
+            let content = match self.ssh(&format!("cat {}", quote_arg(path))) {
+                Ok(content) => content,
+                Err(e) => {
+                    error!("Error fetching {}:{}: {}", self.host, path, e);
+                    continue;
+                }
+            };
+            self.mtimes.insert(path.to_string(), mtime.to_string());
+            match parse::parse(content.as_bytes()) {
+                Ok(commands) => updates.push(Update::Changed(format!("{}:{}", self.host, path), commands)),
+                Err(e) => error!("Error parsing {}:{}: {}", self.host, path, e),
+            }
+        }
+        let host = &self.host;
+        self.mtimes.retain(|path, _| {
+            let kept = listed.iter().any(|&(_, listed)| listed == path);
+            if !kept {
+                updates.push(Update::Removed(format!("{}:{}", host, path)));
+            }
+            kept
+        });
+        Ok(updates)
+    }
+
+    /// Run a command on the remote host and capture its standard output
+    fn ssh(&self, command: &str) -> Result<String> {
+        let output = Command::new("ssh")
+            .args(["-o", "BatchMode=yes", "-o", &format!("ConnectTimeout={}", CONNECT_TIMEOUT.as_secs())])
+            .args(["-o", &format!("ServerAliveInterval={}", READ_TIMEOUT.as_secs() / 3), "-o", "ServerAliveCountMax=3"])
+            .args([&self.host, command])
+            .output()
+            .context("Failed to run ssh")?;
+        if !output.status.success() {
+            bail!(
+                "ssh {} failed: {}",
+                self.host,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(String::from_utf8(output.stdout)?)
+    }
+}
@@ -0,0 +1,70 @@
+use crate::clock::ManualClock;
+use crate::events::Change;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Time the replay clock starts at, so that replays produce the same timestamps on every run
+pub const REPLAY_START: SystemTime = UNIX_EPOCH;
+
+/// Recorded events fed to the watcher in place of the filesystem's, and the clock they move
+pub struct Replay {
+    pub events: Vec<ReplayEvent>,
+    pub clock: Arc<ManualClock>,
+}
+
+/// A recorded filesystem change of a --replay file, which holds an array of them:
+/// ```json
+/// [
+///   {"at": 0, "kind": "modified", "path": "build/compile_commands.json"},
+///   {"at": 250, "kind": "moved-out", "path": "build"}
+/// ]
+/// ```
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReplayEvent {
+    at: u64, // Milliseconds since the replay started
+    kind: Kind,
+    path: PathBuf,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Kind {
+    Modified,
+    MovedIn,
+    MovedOut,
+    Deleted,
+}
+
+/// Read the events of a replay file, ordered by time
+pub fn load(path: &Path) -> Result<Vec<ReplayEvent>> {
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let mut events: Vec<ReplayEvent> =
+        serde_json::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))?;
+    events.sort_by_key(|event| event.at); // Stable, keeping the order of simultaneous events
+    Ok(events)
+}
+
+/// Group events as the watcher's debouncing would: each batch holds the events arriving within
+/// `debounce` of its first, and is applied when that has passed
+pub fn batches(events: Vec<ReplayEvent>, debounce: Duration) -> Vec<(Duration, Vec<Change>)> {
+    let mut batches: Vec<(Duration, Vec<Change>)> = Vec::new();
+    for event in events {
+        let at = Duration::from_millis(event.at);
+        let change = match event.kind {
+            Kind::Modified => Change::Modified(event.path),
+            Kind::MovedIn => Change::MovedIn(event.path),
+            Kind::MovedOut => Change::MovedOut(event.path),
+            Kind::Deleted => Change::Deleted(event.path),
+        };
+        match batches.last_mut() {
+            Some((applied_at, changes)) if at <= *applied_at => changes.push(change),
+            _ => batches.push((at + debounce, vec![change])),
+        }
+    }
+    batches
+}
@@ -0,0 +1,123 @@
+use crate::compiler::glob_match;
+use crate::inject::ArgEdits;
+use crate::paths::normalize;
+use crate::shell::{join_args, split_args};
+use crate::CompileCommand;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// A watched directory with settings of its own, rather than those of the global options that
+/// apply to every directory alike. Given by `[[root]]` sections of the config file, or `--root`
+/// with the same keys as JSON:
+/// ```toml
+/// [[root]]
+/// path = "build-arm"
+/// filename = "compile_commands*.json" # A glob over the names of its databases, instead of --input
+/// exclude = ["*/third_party/*"]        # Globs over the files of entries of its databases
+/// extra-args = ["-D__ARM_ARCH=7"]
+/// extra-args-before = []
+/// remove-args = ["-mthumb-interwork"]
+/// priority = 10                        # Of its databases' entries over those of other databases
+/// poll = 5                             # Seconds between polls, instead of watching it natively
+/// ```
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct WatchRoot {
+    pub path: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_args: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_args_before: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remove_args: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub poll: Option<u64>,
+}
+
+/// Parse a --root value
+pub fn parse(spec: &str) -> Result<WatchRoot, String> {
+    serde_json::from_str(spec).map_err(|e| format!("invalid root, expected a JSON object of its settings: {}", e))
+}
+
+/// The innermost of the roots holding a path
+pub fn containing<'a>(roots: &'a [WatchRoot], path: &Path) -> Option<&'a WatchRoot> {
+    if roots.is_empty() {
+        return None;
+    }
+    let path = resolve(path);
+    roots
+        .iter()
+        .filter(|root| path.starts_with(&root.path))
+        .max_by_key(|root| root.path.components().count())
+}
+
+impl WatchRoot {
+    /// The root with its path resolved, so that paths below it can be matched against it
+    pub fn resolved(self) -> Self {
+        WatchRoot {
+            path: resolve(&self.path),
+            ..self
+        }
+    }
+
+    /// Name of its databases, a glob, or `input_file` where it names none
+    pub fn database_name<'a>(&'a self, input_file: &'a str) -> &'a str {
+        self.filename.as_deref().unwrap_or(input_file)
+    }
+
+    pub fn poll_interval(&self) -> Option<Duration> {
+        self.poll.map(|seconds| Duration::from_secs(seconds.max(1)))
+    }
+
+    /// Apply its filters and edits to the entries of one of its databases
+    pub fn apply_all(&self, commands: Vec<CompileCommand>) -> Vec<CompileCommand> {
+        let edits = ArgEdits {
+            before: self.extra_args_before.clone(),
+            after: self.extra_args.clone(),
+            remove: self.remove_args.clone(),
+        };
+        if self.exclude.is_empty() && edits.is_empty() {
+            return commands;
+        }
+        commands
+            .into_iter()
+            .filter(|command| !self.excludes(command))
+            .map(|mut command| {
+                if !edits.is_empty() {
+                    let mut args = split_args(&command.command);
+                    if edits.apply(&mut args) {
+                        command.command = join_args(&args);
+                    }
+                }
+                command
+            })
+            .collect()
+    }
+
+    fn excludes(&self, command: &CompileCommand) -> bool {
+        let source = normalize(&Path::new(&command.directory).join(&command.file));
+        let source = source.display().to_string().replace('\\', "/");
+        self.exclude.iter().any(|pattern| glob_match(pattern, &command.file) || glob_match(pattern, &source))
+    }
+}
+
+/// A path as roots are compared by: canonical, so that links to a root count, or its directory
+/// canonical where it was removed
+fn resolve(path: &Path) -> PathBuf {
+    if let Ok(path) = fs::canonicalize(path) {
+        return path;
+    }
+    let absolute = normalize(&std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()));
+    match (absolute.parent().and_then(|dir| fs::canonicalize(dir).ok()), absolute.file_name()) {
+        (Some(dir), Some(name)) => dir.join(name),
+        _ => absolute,
+    }
+}
@@ -0,0 +1,54 @@
+use crate::shell::{join_args, split_args};
+use crate::CompileCommand;
+use anyhow::{anyhow, Result};
+use rhai::{Array, Dynamic, Engine, Scope, AST};
+
+/// A Rhai script whose `transform(entry)` function edits each entry.
+///
+/// The entry is a map with `directory`, `command`, `file` and optionally `output`; the function
+/// returns the (possibly modified) map, or `()` to drop the entry. `split_args(command)` and
+/// `join_args(args)` are available for editing commands.
+pub struct Script {
+    path: String,
+    engine: Engine,
+    ast: AST,
+}
+
+impl Script {
+    pub fn load(path: &str) -> Result<Self> {
+        let mut engine = Engine::new();
+        engine.register_fn("split_args", |command: &str| -> Array {
+            split_args(command).into_iter().map(Dynamic::from).collect()
+        });
+        engine.register_fn("join_args", |args: Array| -> String {
+            let args: Vec<String> = args.into_iter().map(|arg| arg.to_string()).collect();
+            join_args(&args)
+        });
+        let ast = engine
+            .compile_file(path.into())
+            .map_err(|e| anyhow!("Failed to load script {}: {}", path, e))?;
+        Ok(Script {
+            path: path.to_string(),
+            engine,
+            ast,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Run the script on an entry, returning `None` if it dropped the entry
+    pub fn transform(&self, command: &CompileCommand) -> Result<Option<CompileCommand>> {
+        let entry = rhai::serde::to_dynamic(command).map_err(|e| anyhow!("{}", e))?;
+        let result: Dynamic = self
+            .engine
+            .call_fn(&mut Scope::new(), &self.ast, "transform", (entry,))
+            .map_err(|e| anyhow!("{}", e))?;
+        if result.is_unit() {
+            return Ok(None);
+        }
+        let command = rhai::serde::from_dynamic(&result).map_err(|e| anyhow!("{}", e))?;
+        Ok(Some(command))
+    }
+}
@@ -0,0 +1,181 @@
+use crate::log::{self, Level};
+use crate::{parse_args, run_with};
+use anyhow::Result;
+use clap::Subcommand;
+use std::ffi::OsString;
+use std::sync::{mpsc, OnceLock};
+use std::time::Duration;
+use windows_service::service::{
+    ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode, ServiceInfo,
+    ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+};
+use windows_service::service_control_handler::{self, ServiceControlHandlerResult};
+use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+use windows_service::{define_windows_service, service_dispatcher};
+use windows_sys::Win32::System::EventLog::{
+    DeregisterEventSource, RegisterEventSourceW, ReportEventW, EVENTLOG_ERROR_TYPE, EVENTLOG_INFORMATION_TYPE,
+    EVENTLOG_WARNING_TYPE,
+};
+
+/// Name of the service, and of its event log source
+const SERVICE_NAME: &str = "CompileCommandsMerger";
+
+/// Managing and running the watcher as a Windows service
+#[derive(Subcommand, Debug)]
+pub enum ServiceAction {
+    /// Register the service, watching with the given options (use absolute paths)
+    Install {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Stop and remove the service
+    Uninstall,
+    /// Start the installed service
+    Start,
+    /// Stop the running service
+    Stop,
+    /// Entry point used by the service control manager
+    #[command(hide = true)]
+    Run {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+}
+
+/// Watcher options of the service being run, handed from `main` to the service entry point
+static WATCH_ARGS: OnceLock<Vec<String>> = OnceLock::new();
+
+pub fn handle(action: &ServiceAction) {
+    let result = match action {
+        ServiceAction::Install { args } => install(args),
+        ServiceAction::Uninstall => uninstall(),
+        ServiceAction::Start => start(),
+        ServiceAction::Stop => stop(),
+        ServiceAction::Run { args } => {
+            let _ = WATCH_ARGS.set(args.clone());
+            service_dispatcher::start(SERVICE_NAME, ffi_service_main).map_err(Into::into)
+        }
+    };
+    match result {
+        Ok(()) if !matches!(action, ServiceAction::Run { .. }) => info!("Service {}: done", SERVICE_NAME),
+        Ok(()) => {}
+        Err(e) => error!("Error: Service {}: {}", SERVICE_NAME, e),
+    }
+}
+
+fn install(args: &[String]) -> Result<()> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE)?;
+    let launch_arguments = ["service", "run", "--"]
+        .into_iter()
+        .map(String::from)
+        .chain(args.iter().cloned())
+        .map(OsString::from)
+        .collect();
+    let info = ServiceInfo {
+        name: OsString::from(SERVICE_NAME),
+        display_name: OsString::from("Compile Commands Merger"),
+        service_type: ServiceType::OWN_PROCESS,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path: std::env::current_exe()?,
+        launch_arguments,
+        dependencies: Vec::new(),
+        account_name: None, // LocalSystem
+        account_password: None,
+    };
+    let service = manager.create_service(&info, ServiceAccess::CHANGE_CONFIG)?;
+    service.set_description("Merges compile_commands.json files and keeps the result up to date")?;
+    Ok(())
+}
+
+fn uninstall() -> Result<()> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    let access = ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE;
+    let service = manager.open_service(SERVICE_NAME, access)?;
+    if service.query_status()?.current_state != ServiceState::Stopped {
+        service.stop()?;
+    }
+    service.delete()?;
+    Ok(())
+}
+
+fn start() -> Result<()> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    manager
+        .open_service(SERVICE_NAME, ServiceAccess::START)?
+        .start::<&str>(&[])?;
+    Ok(())
+}
+
+fn stop() -> Result<()> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    manager.open_service(SERVICE_NAME, ServiceAccess::STOP)?.stop()?;
+    Ok(())
+}
+
+define_windows_service!(ffi_service_main, service_main);
+
+fn service_main(_arguments: Vec<OsString>) {
+    log::set_sink(report_event);
+    if let Err(e) = run_service() {
+        error!("Error: Service {} failed: {}", SERVICE_NAME, e);
+    }
+}
+
+/// Run the watcher until the service control manager asks the service to stop
+fn run_service() -> Result<()> {
+    let (stop_tx, stop_rx) = mpsc::channel();
+    let status_handle = service_control_handler::register(SERVICE_NAME, move |control| match control {
+        ServiceControl::Stop | ServiceControl::Shutdown => {
+            let _ = stop_tx.send(());
+            ServiceControlHandlerResult::NoError
+        }
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+        _ => ServiceControlHandlerResult::NotImplemented,
+    })?;
+    let status = |current_state, controls_accepted| ServiceStatus {
+        service_type: ServiceType::OWN_PROCESS,
+        current_state,
+        controls_accepted,
+        exit_code: ServiceExitCode::Win32(0),
+        checkpoint: 0,
+        wait_hint: Duration::default(),
+        process_id: None,
+    };
+    status_handle.set_service_status(status(
+        ServiceState::Running,
+        ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+    ))?;
+
+    // The watcher runs until the process exits, which happens once the service reports it stopped
+    let watch_args = WATCH_ARGS.get().cloned().unwrap_or_default();
+    std::thread::spawn(move || {
+        let args = parse_args(std::iter::once("compile_commands_merger".to_string()).chain(watch_args).collect());
+        run_with(args);
+    });
+    let _ = stop_rx.recv();
+    status_handle.set_service_status(status(ServiceState::Stopped, ServiceControlAccept::empty()))?;
+    Ok(())
+}
+
+/// Write a message to the Windows event log
+fn report_event(level: Level, message: &str) {
+    let kind = match level {
+        Level::Info => EVENTLOG_INFORMATION_TYPE,
+        Level::Warn => EVENTLOG_WARNING_TYPE,
+        Level::Error => EVENTLOG_ERROR_TYPE,
+    };
+    let source: Vec<u16> = SERVICE_NAME.encode_utf16().chain(Some(0)).collect();
+    let text: Vec<u16> = message.encode_utf16().chain(Some(0)).collect();
+    let strings = [text.as_ptr()];
+    // SAFETY: all strings are NUL-terminated UTF-16 that outlives the calls, and the handle is
+    // only used between registering and deregistering it
+    unsafe {
+        let handle = RegisterEventSourceW(std::ptr::null(), source.as_ptr());
+        if handle.is_null() {
+            return;
+        }
+        ReportEventW(handle, kind, 0, 0, std::ptr::null_mut(), 1, 0, strings.as_ptr(), std::ptr::null());
+        DeregisterEventSource(handle);
+    }
+}
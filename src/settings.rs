@@ -0,0 +1,71 @@
+use crate::shell::CommandSyntax;
+use crate::unordered::UnorderedFlags;
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::sync::{Arc, LazyLock};
+
+/// How a merger reads and compares entries: held by its state rather than by the process, so
+/// that mergers embedded in one program can be configured independently. Work done for a state
+/// runs with its settings entered on the thread doing it; code below reads them with
+/// [`current`].
+#[derive(Debug, Clone, Default)]
+pub struct Settings {
+    /// Patterns of source files whose entries are dropped while parsing, with --exclude
+    pub excludes: Vec<String>,
+    /// How the commands of entries are split and joined, with --command-syntax
+    pub command_syntax: CommandSyntax,
+    /// Flags compared regardless of their order, with --unordered-flags
+    pub unordered_flags: Vec<UnorderedFlags>,
+    /// PlatformIO environments whose databases are merged, with --pio-envs; all when empty
+    pub pio_envs: Vec<String>,
+    /// Lunch targets whose AOSP builds are merged, with --lunch-targets; all when empty
+    pub lunch_targets: Vec<String>,
+    /// Logs of `cargo build -vv` read for the commands cc-rs ran rather than as databases, with
+    /// --cargo-log; absolute
+    pub cargo_logs: Vec<PathBuf>,
+    /// Parse large databases straight from a memory mapping of the file, with --mmap
+    pub mmap: bool,
+    /// Print events as JSON objects on stdout, moving messages to stderr, with --porcelain
+    pub porcelain: bool,
+    /// Only print errors to the console, with --quiet
+    pub quiet: bool,
+    /// Color warnings and errors on the console, as --color resolved for the terminal
+    pub color: bool,
+    /// Drop console messages, as when embedded in another program through the library API
+    pub silent: bool,
+}
+
+/// Settings of work not done for any state
+static DEFAULT: LazyLock<Arc<Settings>> = LazyLock::new(Default::default);
+
+thread_local! {
+    /// Settings of the state this thread is working for
+    static CURRENT: RefCell<Option<Arc<Settings>>> = const { RefCell::new(None) };
+}
+
+/// Restores the settings a thread had before [`Settings::enter`]
+pub struct Entered(Option<Arc<Settings>>);
+
+impl Drop for Entered {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+impl Settings {
+    /// Apply these settings to this thread's work until the guard is dropped
+    pub fn enter(self: &Arc<Self>) -> Entered {
+        Entered(CURRENT.with(|current| current.replace(Some(self.clone()))))
+    }
+
+    /// Apply these settings to all of this thread's work, as for the threads of a watcher's runtime
+    pub fn install(self: &Arc<Self>) {
+        CURRENT.with(|current| *current.borrow_mut() = Some(self.clone()));
+    }
+}
+
+/// Settings of the state this thread is working for
+pub fn current() -> Arc<Settings> {
+    CURRENT.with(|current| current.borrow().clone()).unwrap_or_else(|| DEFAULT.clone())
+}
@@ -0,0 +1,262 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use crate::settings;
+
+/// How the `command` strings of entries quote their arguments
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CommandSyntax {
+    /// Pick the convention of the platform the merger runs on, as clang tools do
+    #[default]
+    Auto,
+    /// POSIX shell quoting
+    Posix,
+    /// The rules of CommandLineToArgvW, with no shell involved
+    Windows,
+}
+
+/// Whether the commands of entries are split and joined by the rules of CommandLineToArgvW, as
+/// the state's --command-syntax says
+fn is_windows() -> bool {
+    match settings::current().command_syntax {
+        CommandSyntax::Auto => cfg!(windows),
+        syntax => syntax == CommandSyntax::Windows,
+    }
+}
+
+/// Split the command of an entry into arguments
+pub fn split_args(command: &str) -> Vec<String> {
+    match is_windows() {
+        true => split_windows(command),
+        false => split_posix(command),
+    }
+}
+
+/// Split a command line into arguments using POSIX shell quoting rules
+pub fn split_posix(command: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                for c in chars.by_ref() {
+                    if c == '\'' {
+                        break;
+                    }
+                    current.push(c);
+                }
+            }
+            '"' => {
+                in_word = true;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => match chars.next() {
+                            Some(next @ ('"' | '\\' | '$' | '`')) => current.push(next),
+                            Some(next) => {
+                                current.push('\\');
+                                current.push(next);
+                            }
+                            None => current.push('\\'),
+                        },
+                        c => current.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                if let Some(next) = chars.next() {
+                    current.push(next);
+                }
+            }
+            c if c.is_whitespace() => {
+                if in_word {
+                    args.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                current.push(c);
+            }
+        }
+    }
+    if in_word {
+        args.push(current);
+    }
+    args
+}
+
+/// Split a command line into arguments the way CommandLineToArgvW does: backslashes are literal
+/// unless they precede a quote, where each pair gives one and an odd one out escapes the quote,
+/// `""` within quotes is a literal quote that also ends them (unlike in msvcrt since 2008), and
+/// the program name is taken as it is up to its closing quote, where the next argument starts,
+/// or the first whitespace
+pub fn split_windows(command: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut chars = command.trim_start_matches([' ', '\t']).chars().peekable();
+    if chars.peek().is_none() {
+        return args;
+    }
+    let program = match chars.next_if_eq(&'"') {
+        Some(_) => chars.by_ref().take_while(|&c| c != '"').collect(),
+        None => chars.by_ref().take_while(|&c| c != ' ' && c != '\t').collect(),
+    };
+    args.push(program);
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                in_word = true;
+                let mut backslashes = 1;
+                while chars.next_if_eq(&'\\').is_some() {
+                    backslashes += 1;
+                }
+                match chars.peek() {
+                    Some('"') => {
+                        current.extend(std::iter::repeat_n('\\', backslashes / 2));
+                        if backslashes % 2 == 1 {
+                            current.push('"');
+                            chars.next();
+                        }
+                    }
+                    _ => current.extend(std::iter::repeat_n('\\', backslashes)),
+                }
+            }
+            '"' => {
+                in_word = true;
+                // Count the run of quotes as CommandLineToArgvW does: every third, counting the
+                // one opening quotes, is a literal quote, and leaves them closed
+                let mut count = usize::from(quoted) + 1;
+                while chars.next_if_eq(&'"').is_some() {
+                    count += 1;
+                    if count == 3 {
+                        current.push('"');
+                        count = 0;
+                    }
+                }
+                quoted = count == 1;
+            }
+            ' ' | '\t' if !quoted => {
+                if in_word {
+                    args.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                current.push(c);
+            }
+        }
+    }
+    if in_word {
+        args.push(current);
+    }
+    args
+}
+
+/// Join arguments into the command of an entry, quoting those that need it
+pub fn join_args<S: AsRef<str>>(args: &[S]) -> String {
+    let windows = is_windows();
+    args.iter()
+        .map(|arg| match windows {
+            true => quote_windows(arg.as_ref()),
+            false => quote_arg(arg.as_ref()),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Quote a single argument for a POSIX shell if it contains special characters
+pub fn quote_arg(arg: &str) -> String {
+    let is_plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_=+./,:@%^".contains(c));
+    if is_plain {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// Quote a single argument so that CommandLineToArgvW gives it back: backslashes are doubled
+/// only where they precede a quote, including the closing one
+pub fn quote_windows(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '\n', '\x0b', '"']) {
+        return arg.to_string();
+    }
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes * 2 + 1));
+                quoted.push('"');
+                backslashes = 0;
+            }
+            c => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes));
+                quoted.push(c);
+                backslashes = 0;
+            }
+        }
+    }
+    quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Outputs of CommandLineToArgvW, whose rules for quotes differ from msvcrt's since 2008
+    #[test]
+    fn splits_like_command_line_to_argv() {
+        let cases: &[(&str, &[&str])] = &[
+            (r#""abc" d e"#, &["abc", "d", "e"]),
+            (r#"cl a\\\b d"e f"g h"#, &["cl", r"a\\\b", "de fg", "h"]),
+            (r#"cl a\\\"b c d"#, &["cl", r#"a\"b"#, "c", "d"]),
+            (r#"cl a\\\\"b c" d e"#, &["cl", r"a\\b c", "d", "e"]),
+            (r#"cl a"b"" c d"#, &["cl", r#"ab""#, "c", "d"]),
+            (r#"cl "a""" b"#, &["cl", r#"a" b"#]),
+            (r#"cl "a"""" b"#, &["cl", r#"a""#, "b"]),
+            (r#"cl """a b"#, &["cl", r#""a"#, "b"]),
+            (r#"cl "" x"#, &["cl", "", "x"]),
+            (r#""C:\Program Files\cl.exe"/c a.c"#, &[r"C:\Program Files\cl.exe", "/c", "a.c"]),
+            (r"C:\bin\cl.exe /c", &[r"C:\bin\cl.exe", "/c"]),
+        ];
+        for (command, expected) in cases {
+            assert_eq!(split_windows(command), *expected, "splitting {}", command);
+        }
+    }
+
+    #[test]
+    fn quotes_for_command_line_to_argv() {
+        let args = [
+            r"C:\Program Files\LLVM\bin\clang-cl.exe",
+            "/DNAME=\"quoted value\"",
+            r"C:\dir with space\",
+            r#"a\\"b"#,
+            "",
+            "tab\there",
+            r"plain\path.c",
+        ];
+        let joined: Vec<String> = args.iter().map(|arg| quote_windows(arg)).collect();
+        assert_eq!(split_windows(&joined.join(" ")), args);
+        assert_eq!(quote_windows(r"plain\path.c"), r"plain\path.c");
+        assert_eq!(quote_windows(r"a b\"), r#""a b\\""#);
+    }
+
+    #[test]
+    fn splits_posix() {
+        assert_eq!(split_posix(r#"gcc -DA="b c" 'd e' f\ g"#), ["gcc", "-DA=b c", "d e", "f g"]);
+    }
+}
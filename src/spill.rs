@@ -0,0 +1,172 @@
+use crate::external::{checksum, Fingerprint};
+use crate::meta::unix_time;
+use crate::timing::{format_bytes, resident_memory};
+use crate::{write_atomically, CompileCommand, Entry};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Version of the spill files' layout, raised whenever it changes
+const SPILL_FORMAT: u32 = 1;
+
+/// First line of a spill file, vouching for the entries on the following ones
+#[derive(Serialize, Deserialize)]
+struct SpillHeader {
+    format: u32,
+    checksum: String, // Of the entries as written
+}
+
+/// An entry as written to disk, with the fields the output leaves out
+#[derive(Serialize)]
+struct SpillingEntry<'a> {
+    key: &'a str,
+    command: &'a CompileCommand,
+    original_compiler: Option<&'a str>,
+    priority: i32,
+    source: &'a str,
+    ingested_at: u64,
+    derived_from: Option<&'a str>,
+    unity_members: Option<usize>,
+}
+
+/// An entry as read back from disk
+#[derive(Deserialize)]
+struct SpilledEntry {
+    key: String,
+    command: CompileCommand,
+    original_compiler: Option<String>,
+    priority: i32,
+    source: String,
+    ingested_at: u64,
+    derived_from: Option<String>,
+    unity_members: Option<usize>,
+}
+
+/// Limit on the memory of the process, above which merged state not needed for the output is
+/// moved to files in `<OUTPUT>.spill` until it is needed again
+pub struct MemoryBudget {
+    limit: u64,
+    dir: PathBuf,
+    spilled: HashSet<String>, // Configurations whose entries are on disk
+    warned: bool,
+}
+
+impl MemoryBudget {
+    pub fn new(limit_mib: u64, output_file: &str) -> Self {
+        let mut dir = Path::new(output_file).as_os_str().to_os_string();
+        dir.push(".spill");
+        MemoryBudget {
+            limit: limit_mib * 1024 * 1024,
+            dir: PathBuf::from(dir),
+            spilled: HashSet::new(),
+            warned: false,
+        }
+    }
+
+    /// The memory in use, if it exceeds the budget
+    pub fn exceeded(&mut self) -> Option<u64> {
+        let Some(used) = resident_memory() else {
+            if !self.warned {
+                warn!("Warning: Memory use cannot be measured on this platform; --max-memory has no effect.");
+                self.warned = true;
+            }
+            return None;
+        };
+        (used > self.limit).then_some(used)
+    }
+
+    /// Warn that the budget is still exceeded with nothing left to spill
+    pub fn warn_exceeded(&mut self, used: u64) {
+        if !self.warned {
+            warn!(
+                "Warning: Using {} of memory, above --max-memory {}, with only the entries of the output left in memory.",
+                format_bytes(used),
+                format_bytes(self.limit)
+            );
+            self.warned = true;
+        }
+    }
+
+    /// Write a configuration's entries to disk, after which they can be dropped from memory
+    pub fn spill(&mut self, config: &str, entries: &HashMap<String, Entry>) -> Result<()> {
+        let spilled: Vec<SpillingEntry> = entries
+            .iter()
+            .map(|(key, entry)| SpillingEntry {
+                key,
+                command: &entry.command,
+                original_compiler: entry.command.original_compiler.as_deref(),
+                priority: entry.priority,
+                source: &entry.source,
+                ingested_at: unix_time(entry.ingested_at),
+                derived_from: entry.derived_from.as_deref(),
+                unity_members: entry.unity_members,
+            })
+            .collect();
+        let content = serde_json::to_string(&spilled)?;
+        let header = SpillHeader { format: SPILL_FORMAT, checksum: Fingerprint::of(content.as_bytes()).checksum() };
+        fs::create_dir_all(&self.dir)?;
+        write_atomically(&self.path(config), &format!("{}\n{}", serde_json::to_string(&header)?, content))?;
+        info!("Spilled {} entries of configuration '{}' to {}", spilled.len(), config, self.dir.display());
+        self.spilled.insert(config.to_string());
+        Ok(())
+    }
+
+    /// Read a configuration's entries back from disk, if they were spilled, failing if the file
+    /// isn't exactly as written
+    pub fn restore(&mut self, config: &str) -> Option<Result<HashMap<String, Entry>>> {
+        if !self.spilled.remove(config) {
+            return None;
+        }
+        let path = self.path(config);
+        let restored = fs::read(&path).map_err(Into::into).and_then(|content| {
+            let (header, content) = content.split_at(content.iter().position(|&b| b == b'\n').unwrap_or(content.len()));
+            let header: SpillHeader = serde_json::from_slice(header).context("Missing header")?;
+            let content = content.get(1..).unwrap_or_default();
+            if header.format != SPILL_FORMAT {
+                bail!("Format {} of {}, expected {}", header.format, path.display(), SPILL_FORMAT);
+            }
+            if header.checksum != Fingerprint::of(content).checksum() {
+                bail!("Checksum mismatch in {}", path.display());
+            }
+            let spilled: Vec<SpilledEntry> = serde_json::from_slice(content)?;
+            Ok(spilled
+                .into_iter()
+                .map(|spilled| {
+                    let mut command = spilled.command;
+                    command.original_compiler = spilled.original_compiler;
+                    let entry = Entry {
+                        command,
+                        priority: spilled.priority,
+                        source: spilled.source,
+                        ingested_at: UNIX_EPOCH + Duration::from_secs(spilled.ingested_at),
+                        derived_from: spilled.derived_from,
+                        unity_members: spilled.unity_members,
+                        interpolated: false, // Only added while writing, never merged
+                    };
+                    (spilled.key, entry)
+                })
+                .collect())
+        });
+        let _ = fs::remove_file(&path);
+        Some(restored)
+    }
+
+    /// The spill file of a configuration, named after it with what a file name can't hold
+    /// replaced, and told apart from names that only differ there by their checksum
+    fn path(&self, config: &str) -> PathBuf {
+        let name: String = config
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        self.dir.join(format!("{}-{:08x}.json", name, checksum(config.as_bytes())))
+    }
+}
+
+impl Drop for MemoryBudget {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
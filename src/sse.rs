@@ -0,0 +1,49 @@
+use crate::merger::MergeEvent;
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// Serve the entry changes of each write as server-sent events on an address, any path being
+/// the stream; each client gets its own thread
+pub fn serve(addr: SocketAddr, changes: broadcast::Sender<MergeEvent>) -> Result<()> {
+    let listener = TcpListener::bind(addr).with_context(|| format!("Failed to listen on {}", addr))?;
+    info!("Streaming entry changes on http://{}/", addr);
+    std::thread::spawn(move || {
+        for client in listener.incoming().flatten() {
+            let changes = changes.subscribe();
+            std::thread::spawn(move || stream(client, changes));
+        }
+    });
+    Ok(())
+}
+
+/// Answer a client's request with the event stream, until it disconnects
+fn stream(client: TcpStream, mut changes: broadcast::Receiver<MergeEvent>) {
+    // The request itself doesn't matter, only that it was sent
+    let mut reader = BufReader::new(&client);
+    let mut line = String::new();
+    while reader.read_line(&mut line).is_ok_and(|read| read > 0) && line.trim_end() != "" {
+        line.clear();
+    }
+    let mut client = &client;
+    let headers = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\nAccess-Control-Allow-Origin: *\r\n\r\n";
+    if client.write_all(headers.as_bytes()).is_err() {
+        return;
+    }
+    loop {
+        let (name, data) = match changes.blocking_recv() {
+            Ok(MergeEvent::Added { file }) => ("added", json!({ "file": file })),
+            Ok(MergeEvent::Updated { file }) => ("updated", json!({ "file": file })),
+            Ok(MergeEvent::Removed { file }) => ("removed", json!({ "file": file })),
+            Ok(_) => continue,
+            // The client fell behind and should re-read the output to catch up
+            Err(RecvError::Lagged(missed)) => ("lagged", json!({ "missed": missed })),
+            Err(RecvError::Closed) => return,
+        };
+        if write!(client, "event: {}\ndata: {}\n\n", name, data).and_then(|_| client.flush()).is_err() {
+            return; // Disconnected
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use crate::paths::normalize;
+use crate::{source_path, Entry};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Sources changed after the database that recorded their entries, whose flags may be outdated
+/// until the database is generated again
+#[derive(Default)]
+pub struct Staleness {
+    stale: RefCell<HashSet<PathBuf>>, // Keyed by `key`
+}
+
+impl Staleness {
+    /// Check the given sources (as `key`s) against the databases of their entries, returning
+    /// whether any became stale
+    pub fn check(&self, entries: &[&Entry], sources: &HashSet<PathBuf>) -> bool {
+        let mut stale = self.stale.borrow_mut();
+        let mut added = false;
+        for entry in entries {
+            let source = key(&source_path(&entry.command));
+            if sources.contains(&source) && !stale.contains(&source) && is_newer(&source, Path::new(&entry.source)) {
+                info!("Stale: {} changed after {}", source.display(), entry.source);
+                stale.insert(source);
+                added = true;
+            }
+        }
+        added
+    }
+
+    /// Forget the sources whose databases were written again since they changed, returning
+    /// whether there were any
+    pub fn refresh(&self, entries: &[&Entry]) -> bool {
+        let mut stale = self.stale.borrow_mut();
+        if stale.is_empty() {
+            return false;
+        }
+        let current: HashSet<PathBuf> = entries
+            .iter()
+            .filter_map(|entry| {
+                let source = key(&source_path(&entry.command));
+                (stale.contains(&source) && is_newer(&source, Path::new(&entry.source))).then_some(source)
+            })
+            .collect();
+        let refreshed = current.len() < stale.len();
+        *stale = current;
+        refreshed
+    }
+
+    /// Whether the source of an entry changed after its database
+    pub fn is_stale(&self, entry: &Entry) -> bool {
+        let stale = self.stale.borrow();
+        !stale.is_empty() && stale.contains(&key(&source_path(&entry.command)))
+    }
+
+    pub fn count(&self) -> usize {
+        self.stale.borrow().len()
+    }
+}
+
+/// How sources are compared, whether they come from entries or from watch events
+pub fn key(path: &Path) -> PathBuf {
+    normalize(&std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()))
+}
+
+/// Whether a file was modified after a database; false when either can't be read, as for remote databases
+fn is_newer(source: &Path, database: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    match (modified(source), modified(database)) {
+        (Some(source), Some(database)) => source > database,
+        _ => false,
+    }
+}
@@ -0,0 +1,160 @@
+use anyhow::{bail, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+
+/// Embedded build system whose sysroot layout should be remapped
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SysrootPreset {
+    /// Detect both Yocto and Buildroot layouts
+    Auto,
+    /// `tmp/work/<arch>/<recipe>/<version>/recipe-sysroot[-native]`
+    Yocto,
+    /// `output/host[/<tuple>/sysroot]` and `output/staging`
+    Buildroot,
+}
+
+/// Layout names accepted in the mapping table, with their default templates
+const DEFAULT_MAPPINGS: &[(&str, Option<&str>)] = &[
+    ("recipe-sysroot", Some("{sdk}/sysroots/{arch}")),
+    ("recipe-sysroot-native", Some("{sdk}/sysroots/x86_64-pokysdk-linux")),
+    ("sysroot", Some("{sdk}/{arch}/sysroot")),
+    ("host", Some("{sdk}")),
+    ("staging", None),
+];
+
+/// Rewrites sysroot and include paths pointing into a build tree to a stable SDK location
+#[derive(Debug)]
+pub struct SysrootRemap {
+    preset: SysrootPreset,
+    sdk_root: String,
+    mappings: Vec<(String, Option<String>)>, // Template per layout name
+}
+
+impl SysrootRemap {
+    /// Create a remapping with the default table, overridden by `LAYOUT=TEMPLATE` pairs
+    pub fn new(preset: SysrootPreset, sdk_root: &str, overrides: &[String]) -> Result<Self> {
+        let mut mappings: Vec<(String, Option<String>)> = DEFAULT_MAPPINGS
+            .iter()
+            .map(|(layout, template)| (layout.to_string(), template.map(str::to_string)))
+            .collect();
+        for spec in overrides {
+            let Some((layout, template)) = spec.split_once('=') else {
+                bail!("Invalid sysroot mapping '{}', expected LAYOUT=TEMPLATE", spec);
+            };
+            match mappings.iter_mut().find(|(name, _)| name == layout) {
+                Some((_, slot)) => *slot = Some(template.to_string()),
+                None => bail!("Unknown sysroot layout '{}' in mapping '{}'", layout, spec),
+            }
+        }
+        Ok(SysrootRemap {
+            preset,
+            sdk_root: sdk_root.trim_end_matches('/').to_string(),
+            mappings,
+        })
+    }
+
+    /// Rewrite every recognized sysroot path in `text`
+    pub fn apply(&self, text: &str) -> String {
+        let mut result = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some((start, end, replacement)) = self.find_next(rest) {
+            result.push_str(&rest[..start]);
+            result.push_str(&replacement);
+            rest = &rest[end..];
+        }
+        result.push_str(rest);
+        result
+    }
+
+    /// Find the next rewritable path, returning its byte range and replacement
+    fn find_next(&self, text: &str) -> Option<(usize, usize, String)> {
+        let mut offset = 0;
+        while offset < text.len() {
+            let candidates = [
+                (self.preset != SysrootPreset::Buildroot).then(|| text[offset..].find("/tmp/work/")),
+                (self.preset != SysrootPreset::Yocto).then(|| text[offset..].find("/output/")),
+            ];
+            let marker = candidates.into_iter().flatten().flatten().min()? + offset;
+            let start = path_start(text, marker);
+            let matched = if text[marker..].starts_with("/tmp/work/") {
+                self.match_yocto(text, marker)
+            } else {
+                self.match_buildroot(text, marker)
+            };
+            if let Some((end, replacement)) = matched {
+                return Some((start, end, replacement));
+            }
+            offset = marker + 1;
+        }
+        None
+    }
+
+    /// Match `/tmp/work/<arch>/<recipe>/<version>/recipe-sysroot[-native]` at `marker`
+    fn match_yocto(&self, text: &str, marker: usize) -> Option<(usize, String)> {
+        let after = marker + "/tmp/work/".len();
+        let segments = path_segments(text, after, 4);
+        let [(arch, _), _, _, (layout, end)] = segments.as_slice() else {
+            return None;
+        };
+        if *layout != "recipe-sysroot" && *layout != "recipe-sysroot-native" {
+            return None;
+        }
+        Some((*end, self.expand(layout, arch)?))
+    }
+
+    /// Match `/output/host/<tuple>/sysroot`, `/output/host` or `/output/staging` at `marker`
+    fn match_buildroot(&self, text: &str, marker: usize) -> Option<(usize, String)> {
+        let after = marker + "/output/".len();
+        let segments = path_segments(text, after, 3);
+        match segments.as_slice() {
+            [("host", _), (tuple, _), ("sysroot", end), ..] => Some((*end, self.expand("sysroot", tuple)?)),
+            [("host", end), ..] => Some((*end, self.expand("host", "")?)),
+            [("staging", end), ..] => Some((*end, self.expand("staging", "")?)),
+            _ => None,
+        }
+    }
+
+    /// Expand the template for `layout`, if one is configured
+    fn expand(&self, layout: &str, arch: &str) -> Option<String> {
+        let (_, template) = self.mappings.iter().find(|(name, _)| name == layout)?;
+        Some(
+            template
+                .as_ref()?
+                .replace("{sdk}", &self.sdk_root)
+                .replace("{arch}", arch),
+        )
+    }
+}
+
+/// Find where the path containing `marker` starts, skipping a joined option such as `-I`
+fn path_start(text: &str, marker: usize) -> usize {
+    let start = text[..marker]
+        .rfind(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '='))
+        .map_or(0, |i| i + 1);
+    if text[start..].starts_with('-') {
+        text[start..marker].find('/').map_or(marker, |i| start + i)
+    } else {
+        start
+    }
+}
+
+/// Split up to `count` path components starting at `start`, returning each with its end offset
+fn path_segments(text: &str, start: usize, count: usize) -> Vec<(&str, usize)> {
+    let mut segments = Vec::new();
+    let mut position = start;
+    while segments.len() < count {
+        let end = text[position..]
+            .find(|c: char| c == '/' || c.is_whitespace() || matches!(c, '"' | '\''))
+            .map_or(text.len(), |i| position + i);
+        if end == position {
+            break;
+        }
+        segments.push((&text[position..end], end));
+        if !text[end..].starts_with('/') {
+            break;
+        }
+        position = end + 1;
+    }
+    segments
+}
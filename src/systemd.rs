@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+/// Notifications to systemd when running as a `Type=notify` unit (see sd_notify(3))
+#[derive(Clone, Debug)]
+pub struct Systemd {
+    #[cfg_attr(not(unix), allow(dead_code))]
+    socket: String,              // NOTIFY_SOCKET; a leading `@` denotes an abstract socket
+    watchdog: Option<Duration>, // Interval at which the unit expects keep-alive pings
+}
+
+impl Systemd {
+    /// Connect to the notification socket systemd passed, if any
+    pub fn from_env() -> Option<Self> {
+        let socket = std::env::var("NOTIFY_SOCKET").ok().filter(|socket| !socket.is_empty())?;
+        let for_us = std::env::var("WATCHDOG_PID").map_or(true, |pid| pid == std::process::id().to_string());
+        let watchdog = std::env::var("WATCHDOG_USEC")
+            .ok()
+            .filter(|_| for_us)
+            .and_then(|usec| usec.parse().ok())
+            .filter(|&usec| usec > 0)
+            .map(Duration::from_micros);
+        Some(Systemd { socket, watchdog })
+    }
+
+    /// How often to ping the watchdog: half the interval systemd enforces, as it recommends
+    pub fn watchdog_interval(&self) -> Option<Duration> {
+        self.watchdog.map(|interval| interval / 2)
+    }
+
+    /// Tell systemd that startup finished
+    pub fn ready(&self, status: &str) {
+        self.notify(&format!("READY=1\nSTATUS={}", status));
+    }
+
+    /// Update the status line shown by `systemctl status`
+    pub fn status(&self, status: &str) {
+        self.notify(&format!("STATUS={}", status));
+    }
+
+    /// Tell the watchdog the service is still alive
+    pub fn ping(&self) {
+        self.notify("WATCHDOG=1");
+    }
+
+    #[cfg(unix)]
+    fn notify(&self, message: &str) {
+        use std::os::unix::net::UnixDatagram;
+        let result = UnixDatagram::unbound().and_then(|socket| {
+            match self.socket.strip_prefix('@') {
+                #[cfg(target_os = "linux")]
+                Some(name) => {
+                    use std::os::linux::net::SocketAddrExt;
+                    let address = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+                    socket.send_to_addr(message.as_bytes(), &address)
+                }
+                _ => socket.send_to(message.as_bytes(), &self.socket),
+            }
+        });
+        if let Err(e) = result {
+            warn!("Warning: Failed to notify systemd: {}", e);
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn notify(&self, _message: &str) {}
+}
@@ -0,0 +1,84 @@
+use crate::settings;
+use crate::shell::split_args;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc::{self, Sender};
+use std::sync::OnceLock;
+
+/// A run asked for: the directory of the database, and the sources to check
+type Job = (PathBuf, Vec<PathBuf>);
+
+/// Runs clang-tidy on entries that were added or changed by a merge, on a thread of its own so
+/// that a slow run doesn't hold up merging; runs asked for during one are collapsed into the next
+pub struct TidyRunner {
+    args: Vec<String>,
+    jobs: OnceLock<Sender<Job>>, // To the thread running clang-tidy, started by the first run
+}
+
+impl TidyRunner {
+    /// Create a runner passing the given extra arguments (e.g. `-checks=...`) to clang-tidy
+    pub fn new(args: &str) -> Self {
+        TidyRunner {
+            args: split_args(args),
+            jobs: OnceLock::new(),
+        }
+    }
+
+    /// Run clang-tidy against the sources using the database at `output_path`, without waiting
+    /// for it
+    pub fn run(&self, output_path: &Path, sources: Vec<PathBuf>) {
+        if sources.is_empty() {
+            return;
+        }
+        let build_dir = match output_path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let jobs = self.jobs.get_or_init(|| {
+            let (jobs, received) = mpsc::channel::<Job>();
+            let args = self.args.clone();
+            let settings = settings::current(); // Of the state whose writes ask for runs
+            std::thread::spawn(move || {
+                let _settings = settings.enter();
+                while let Ok((mut build_dir, mut sources)) = received.recv() {
+                    for (latest, more) in received.try_iter() {
+                        build_dir = latest;
+                        sources.extend(more);
+                    }
+                    sources.retain(|source| source.is_file());
+                    sources.sort();
+                    sources.dedup();
+                    if !sources.is_empty() {
+                        tidy(&args, &build_dir, &sources);
+                    }
+                }
+            });
+            jobs
+        });
+        let _ = jobs.send((build_dir, sources)); // The thread lives as long as the process
+    }
+}
+
+fn tidy(args: &[String], build_dir: &Path, sources: &[PathBuf]) {
+    info!("Running clang-tidy on {} changed entries...", sources.len());
+    let output = Command::new("clang-tidy")
+        .arg("-p")
+        .arg(build_dir)
+        .args(args)
+        .args(sources)
+        .output();
+    match output {
+        Ok(output) => {
+            for line in String::from_utf8_lossy(&output.stdout).lines() {
+                info!("{}", line);
+            }
+            for line in String::from_utf8_lossy(&output.stderr).lines() {
+                warn!("{}", line);
+            }
+            if !output.status.success() {
+                error!("clang-tidy exited with {}", output.status);
+            }
+        }
+        Err(e) => error!("Error: Failed to run clang-tidy: {}", e),
+    }
+}
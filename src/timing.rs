@@ -0,0 +1,183 @@
+use crate::log;
+use crate::presets::WorkspacePreset;
+use crate::transform::Transforms;
+use crate::CombinedState;
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+
+/// Stages of producing the output whose time is measured by --timing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Discovery, // Finding databases under the watched directories
+    Parse,     // Reading and deserializing databases
+    Merge,     // Transforming and deduplicating entries
+    Serialize, // Collecting and ordering the merged entries
+    Write,     // Serializing the output into its file, and writing the sidecar
+}
+
+impl Phase {
+    pub const ALL: [Phase; 5] = [Phase::Discovery, Phase::Parse, Phase::Merge, Phase::Serialize, Phase::Write];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Discovery => "discovery",
+            Phase::Parse => "parse",
+            Phase::Merge => "merge",
+            Phase::Serialize => "serialize",
+            Phase::Write => "write",
+        }
+    }
+}
+
+/// Time spent per phase since the last report
+#[derive(Default)]
+pub struct Timings {
+    current: RefCell<Report>,
+}
+
+/// Measured times, with the databases parsed and how long each took
+#[derive(Clone, Default)]
+pub struct Report {
+    pub phases: [Duration; 5],
+    pub files: Vec<(String, Duration, usize)>, // Database, parse time and number of entries
+}
+
+impl Timings {
+    /// Add the time elapsed since `started` to a phase
+    pub fn record(&self, phase: Phase, started: Instant) {
+        self.current.borrow_mut().phases[phase as usize] += started.elapsed();
+    }
+
+    /// Add the time spent parsing a database
+    pub fn record_parse(&self, file: &str, elapsed: Duration, entries: usize) {
+        let mut current = self.current.borrow_mut();
+        current.phases[Phase::Parse as usize] += elapsed;
+        current.files.push((file.to_string(), elapsed, entries));
+    }
+
+    /// Take the times measured since the last report
+    pub fn take(&self) -> Report {
+        std::mem::take(&mut *self.current.borrow_mut())
+    }
+}
+
+impl Report {
+    pub fn total(&self) -> Duration {
+        self.phases.iter().sum()
+    }
+
+    /// Log the times, slowest databases first
+    pub fn log(mut self) {
+        self.files.sort_by_key(|(_, elapsed, _)| std::cmp::Reverse(*elapsed));
+        for (file, elapsed, entries) in &self.files {
+            info!("Timing: parsed {} in {} ({} entries)", file, format_duration(*elapsed), entries);
+        }
+        let phases: Vec<String> = Phase::ALL
+            .iter()
+            .map(|phase| format!("{} {}", phase.name(), format_duration(self.phases[*phase as usize])))
+            .collect();
+        info!(
+            "Timing: {}, total {}; peak memory {}",
+            phases.join(", "),
+            format_duration(self.total()),
+            peak_memory().map_or_else(|| "unavailable".to_string(), format_bytes)
+        );
+    }
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+/// Memory high-water mark of the process, in bytes
+#[cfg(target_os = "linux")]
+pub fn peak_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn peak_memory() -> Option<u64> {
+    None
+}
+
+/// Memory currently resident for the process, in bytes
+#[cfg(target_os = "linux")]
+pub fn resident_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn resident_memory() -> Option<u64> {
+    None
+}
+
+/// Repeat the initial merge of the directories, writing to a scratch file, and print the time
+/// each phase took across the iterations
+pub fn bench(
+    directories: &[String],
+    input_file: &str,
+    preset: Option<WorkspacePreset>,
+    mut transforms: Transforms,
+    iterations: usize,
+) {
+    let output = std::env::temp_dir().join(format!("compile_commands_merger_bench_{}.json", std::process::id()));
+    let mut reports: Vec<Report> = Vec::new();
+    let mut entries = 0;
+    for iteration in 0..iterations.max(1) {
+        log::start_capture();
+        let mut state = CombinedState::new(input_file, &output.display().to_string(), Vec::new(), preset, transforms);
+        state.timings = Some(Timings::default());
+        state.scan(directories);
+        let written = state.write_to_file();
+        let messages = log::stop_capture();
+        if iteration == 0 {
+            // Repeat problems with the inputs once, rather than every merge's progress messages
+            for (level, message) in messages.into_iter().filter(|(level, _)| *level != log::Level::Info) {
+                log::emit(level, message);
+            }
+        }
+        if let Err(e) = written {
+            error!("Error: Failed to write {}: {}", output.display(), e);
+            break;
+        }
+        entries = state.merged_entries().count();
+        reports.extend(state.timings.as_ref().map(Timings::take));
+        transforms = std::mem::take(&mut state.transforms);
+    }
+    let _ = std::fs::remove_file(&output);
+    if reports.is_empty() {
+        return;
+    }
+
+    info!(
+        "Benchmark: {} iterations over {} directories, {} entries",
+        reports.len(),
+        directories.len(),
+        entries
+    );
+    let summary = |name: &str, mut times: Vec<Duration>| {
+        times.sort();
+        info!(
+            "  {:<10} min {:>9}  median {:>9}  max {:>9}",
+            name,
+            format_duration(times[0]),
+            format_duration(times[times.len() / 2]),
+            format_duration(times[times.len() - 1])
+        );
+    };
+    for phase in Phase::ALL {
+        summary(phase.name(), reports.iter().map(|report| report.phases[phase as usize]).collect());
+    }
+    summary("total", reports.iter().map(Report::total).collect());
+    info!("Peak memory: {}", peak_memory().map_or_else(|| "unavailable".to_string(), format_bytes));
+}
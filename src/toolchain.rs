@@ -0,0 +1,181 @@
+use crate::compiler::{compiler_index, executable_name};
+
+/// Vendor embedded compilers whose flags need translating for clang-based tools
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Toolchain {
+    Armcc,    // ARM Compiler 5
+    Armclang, // ARM Compiler 6, already clang-based
+    Iar,      // IAR iccarm
+    Ti430,    // TI MSP430 cl430
+    Ti2000,   // TI C2000 cl2000
+}
+
+/// IAR options whose value is given as the next argument
+const IAR_SEPARATE_VALUE: &[&str] = &[
+    "--cpu", "--fpu", "--endian", "--cpu_mode", "--dlib_config", "--diag_suppress", "--diag_error",
+    "--diag_warning", "--diag_remark", "--preinclude", "--system_include_dir", "--dependencies", "-l", "-f",
+];
+
+impl Toolchain {
+    fn detect(compiler: &str) -> Option<Self> {
+        match compiler {
+            "armcc" => Some(Toolchain::Armcc),
+            "armclang" => Some(Toolchain::Armclang),
+            "iccarm" => Some(Toolchain::Iar),
+            "cl430" => Some(Toolchain::Ti430),
+            "cl2000" => Some(Toolchain::Ti2000),
+            _ => None,
+        }
+    }
+
+    /// Flags describing the target, which vendor compilers imply
+    fn implicit_flags(self) -> &'static [&'static str] {
+        match self {
+            Toolchain::Armcc | Toolchain::Iar => &["--target=arm-none-eabi"],
+            Toolchain::Armclang => &[],
+            Toolchain::Ti430 => &["--target=msp430", "-D__MSP430__"],
+            Toolchain::Ti2000 => &["-D__TMS320C28XX__"],
+        }
+    }
+
+    fn takes_separate_value(self, option: &str) -> bool {
+        match self {
+            Toolchain::Armcc => matches!(option, "--via" | "-J"),
+            Toolchain::Iar => IAR_SEPARATE_VALUE.contains(&option),
+            _ => false,
+        }
+    }
+
+    /// Clang equivalent of an option, with its value if it has one
+    fn map(self, arg: &str, option: &str, value: Option<&str>) -> Vec<String> {
+        let value = value.unwrap_or_default();
+        let flags: &[&str] = match (self, option) {
+            (Toolchain::Armclang, "--target") if value == "arm-arm-none-eabi" => &["--target=arm-none-eabi"],
+            (Toolchain::Armclang, _) => return vec![arg.to_string()],
+
+            (Toolchain::Armcc | Toolchain::Iar, "--cpu") => return vec![format!("-mcpu={}", arm_cpu(value))],
+            (Toolchain::Armcc, "--thumb") | (Toolchain::Iar, "--cpu_mode") if value != "arm" => &["-mthumb"],
+            (Toolchain::Armcc, "--arm") | (Toolchain::Iar, "--cpu_mode") => &["-marm"],
+            (Toolchain::Armcc, "--li") => &["-mlittle-endian"],
+            (Toolchain::Armcc, "--bi") => &["-mbig-endian"],
+            (Toolchain::Iar, "--endian") if value == "big" => &["-mbig-endian"],
+            (Toolchain::Iar, "--endian") => &["-mlittle-endian"],
+            (Toolchain::Armcc, "--split_sections") => &["-ffunction-sections"],
+            (Toolchain::Armcc, "--cpp") | (Toolchain::Iar, "--c++" | "--ec++" | "--eec++") => &["-xc++"],
+            (Toolchain::Armcc, "--cpp11") => &["-xc++", "-std=c++11"],
+            (Toolchain::Armcc, "-J") | (Toolchain::Iar, "--system_include_dir") => {
+                return vec!["-isystem".to_string(), value.to_string()]
+            }
+            (Toolchain::Armcc | Toolchain::Ti430 | Toolchain::Ti2000, "--c90") => &["-std=c90"],
+            (Toolchain::Armcc | Toolchain::Ti430 | Toolchain::Ti2000, "--c99") => &["-std=c99"],
+            (Toolchain::Ti430 | Toolchain::Ti2000, "--c11") => &["-std=c11"],
+            (Toolchain::Armcc, "--signed_chars") | (Toolchain::Iar, "--char_is_signed") => &["-fsigned-char"],
+            (Toolchain::Armcc, "--unsigned_chars") | (Toolchain::Iar, "--char_is_unsigned") => &["-funsigned-char"],
+            (Toolchain::Iar, "--debug") | (Toolchain::Ti430 | Toolchain::Ti2000, "--symdebug:dwarf") => &["-g"],
+            (_, "--preinclude") => return vec!["-include".to_string(), value.to_string()],
+            (Toolchain::Ti430 | Toolchain::Ti2000, "--include_path") => return vec![format!("-I{}", value)],
+            (Toolchain::Ti430 | Toolchain::Ti2000, "--define") => return vec![format!("-D{}", value)],
+            (Toolchain::Ti430 | Toolchain::Ti2000, "--undefine") => return vec![format!("-U{}", value)],
+            (Toolchain::Ti430 | Toolchain::Ti2000, "--compile_only") => &["-c"],
+            (Toolchain::Ti430 | Toolchain::Ti2000, "--output_file") => return vec!["-o".to_string(), value.to_string()],
+            // Options clang shares with these compilers, sources, and values of kept options
+            _ if !arg.starts_with('-') || is_common_flag(arg) => return vec![arg.to_string()],
+            // Anything else is vendor-specific (diagnostics, optimization goals, runtime libraries)
+            _ => &[],
+        };
+        flags.iter().map(|flag| flag.to_string()).collect()
+    }
+}
+
+/// Flags understood alike by the vendor compilers and clang
+fn is_common_flag(arg: &str) -> bool {
+    let optimization = arg.strip_prefix("-O").is_some_and(|level| matches!(level, "" | "0" | "1" | "2" | "3" | "s" | "z"));
+    optimization
+        || ["-I", "-D", "-U"].iter().any(|prefix| arg.starts_with(prefix))
+        || matches!(arg, "-c" | "-o" | "-g" | "-E" | "-S")
+}
+
+/// Clang's name for an ARM CPU, e.g. `Cortex-M4.fp.sp` -> `cortex-m4`
+fn arm_cpu(cpu: &str) -> String {
+    cpu.split('.').next().unwrap_or(cpu).to_ascii_lowercase()
+}
+
+/// Translate a command of a vendor compiler into clang's, returning the original compiler
+pub fn translate(args: &mut Vec<String>) -> Option<String> {
+    let index = compiler_index(args)?;
+    let toolchain = Toolchain::detect(executable_name(&args[index]))?;
+
+    let mut translated: Vec<String> = args[..index].to_vec();
+    translated.push("clang".to_string());
+    translated.extend(toolchain.implicit_flags().iter().map(|flag| flag.to_string()));
+    let mut rest = args[index + 1..].iter();
+    while let Some(arg) = rest.next() {
+        let (option, value) = match arg.split_once('=') {
+            Some((option, value)) if option.starts_with('-') => (option, Some(value)),
+            _ => (arg.as_str(), None),
+        };
+        let value = match value {
+            None if toolchain.takes_separate_value(option) => rest.next().map(String::as_str),
+            value => value,
+        };
+        translated.extend(toolchain.map(arg, option, value));
+    }
+
+    let original = std::mem::replace(args, translated);
+    Some(original[index].clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn translated(command: &str) -> Option<(String, String)> {
+        let mut args: Vec<String> = command.split(' ').map(String::from).collect();
+        let original = translate(&mut args)?;
+        Some((original, args.join(" ")))
+    }
+
+    #[test]
+    fn translates_vendor_commands() {
+        let cases = [
+            (
+                "armcc --cpu=Cortex-M4.fp.sp --thumb --c99 -O2 -Otime --apcs=interwork -J /opt/arm/include -Iinc -c main.c -o main.o",
+                "armcc",
+                "clang --target=arm-none-eabi -mcpu=cortex-m4 -mthumb -std=c99 -O2 -isystem /opt/arm/include -Iinc -c main.c -o main.o",
+            ),
+            (
+                "armcc --via opts.via --preinclude=rte.h --diag_suppress=66 --split_sections -c util.c",
+                "armcc",
+                "clang --target=arm-none-eabi -include rte.h -ffunction-sections -c util.c",
+            ),
+            (
+                "iccarm --cpu Cortex-M3 --endian little --dlib_config DLib_Config_Normal.h --diag_suppress Pe177 --preinclude cfg.h -Ohz -DNDEBUG main.c -o main.o",
+                "iccarm",
+                "clang --target=arm-none-eabi -mcpu=cortex-m3 -mlittle-endian -include cfg.h -DNDEBUG main.c -o main.o",
+            ),
+            (
+                "iccarm --cpu_mode thumb --system_include_dir /opt/iar/inc --char_is_signed --debug -c x.c",
+                "iccarm",
+                "clang --target=arm-none-eabi -mthumb -isystem /opt/iar/inc -fsigned-char -g -c x.c",
+            ),
+            (
+                "cl430 -vmspx --silicon_version=mspx --include_path=inc --define=F_CPU=8000000 --c99 --symdebug:dwarf --compile_only main.c --output_file=main.obj",
+                "cl430",
+                "clang --target=msp430 -D__MSP430__ -Iinc -DF_CPU=8000000 -std=c99 -g -c main.c -o main.obj",
+            ),
+            (
+                "ccache armclang --target=arm-arm-none-eabi -mcpu=cortex-m0 -c a.c",
+                "armclang",
+                "ccache clang --target=arm-none-eabi -mcpu=cortex-m0 -c a.c",
+            ),
+        ];
+        for (command, compiler, expected) in cases {
+            assert_eq!(translated(command), Some((compiler.to_string(), expected.to_string())), "{}", command);
+        }
+    }
+
+    #[test]
+    fn leaves_other_compilers_alone() {
+        assert_eq!(translated("gcc --cpu=x -c a.c"), None);
+    }
+}
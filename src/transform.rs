@@ -0,0 +1,216 @@
+use crate::builtins::BuiltinIncludes;
+use crate::compiler::{self, CompilerMap};
+use crate::generated::{GeneratedSources, SourceMap};
+use crate::inject::{ArgEdits, Injection};
+use crate::nix::NixProfiles;
+use crate::paths::{self, PathMap, PathStyle};
+use crate::pch::{self, PchMode};
+use crate::pipe::PipeThrough;
+use crate::plugin::WasmPlugin;
+use crate::prefix_map::{self, PrefixMapMode};
+use crate::script::Script;
+use crate::shell::{join_args, split_args};
+use crate::sysroot::SysrootRemap;
+use crate::toolchain;
+use crate::unity;
+use crate::CompileCommand;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// A rewrite supplied by a library user
+pub type CustomTransform = Arc<dyn Fn(CompileCommand) -> CompileCommand + Send + Sync>;
+
+/// Per-entry rewrites applied while ingesting compile commands
+#[derive(Default)]
+pub struct Transforms {
+    pub source_map: SourceMap, // Gives entries of files copied or generated into a build tree to their source-tree origin
+    pub generated: Option<GeneratedSources>, // Drops or remaps entries of generated sources, before any rewrite
+    pub path_style: Option<PathStyle>,
+    pub nix: Option<NixProfiles>, // Store paths rewritten to the profiles exposing them, before the prefix rewrites
+    pub path_map: PathMap, // Prefix rewrites applied before the path style translation
+    pub sysroot: Option<SysrootRemap>,
+    pub strip_flags: &'static [&'static str], // Flags removed from commands, matched by prefix
+    pub prefix_maps: Option<PrefixMapMode>, // Handling of -ffile-prefix-map and the like
+    pub expand_unity: bool,
+    pub injections: Vec<Injection>, // Options appended to entries of matching compilers
+    pub translate_toolchains: bool, // Rewrite commands of vendor embedded compilers for clang
+    pub compiler_map: CompilerMap,
+    pub resolve_compilers: bool, // Replace compilers with the absolute path they resolve to
+    pub arg_edits: ArgEdits,
+    pub absolute_includes: bool, // Resolve relative include directories against the entry's directory
+    pub pch: Option<PchMode>,
+    pub builtin_includes: Option<BuiltinIncludes>, // Compilers' default include directories, appended as -isystem
+    pub rebase_directory: Option<PathBuf>, // Common working directory of all entries, relative paths adjusted to it
+    pub custom: Vec<CustomTransform>, // Applied in order after the built-in rewrites
+    pub script: Option<Script>, // Applied after the custom rewrites, and may drop entries
+    pub plugins: Vec<WasmPlugin>, // Applied after the script, in order, and may drop entries
+    pub pipe: Option<PipeThrough>, // Filter program each database's entries are finally streamed through
+}
+
+impl Transforms {
+    /// Apply all configured rewrites to an entry, returning `None` if it was dropped; `nix` is the
+    /// mapping of the Nix profiles, resolved once for the batch the entry is in
+    fn apply(&self, mut command: CompileCommand, nix: &PathMap) -> Option<CompileCommand> {
+        if !self.source_map.is_empty() {
+            self.source_map.apply(&mut command);
+        }
+        if let Some(generated) = &self.generated {
+            command = generated.apply(command)?;
+        }
+        if !self.strip_flags.is_empty() {
+            command.command = strip_flags(&command.command, self.strip_flags);
+        }
+        if let Some(mode) = self.prefix_maps {
+            command = prefix_map::apply(command, mode);
+        }
+        if let Some(sysroot) = &self.sysroot {
+            command.command = sysroot.apply(&command.command);
+        }
+        if self.edits_args() {
+            let mut args = split_args(&command.command);
+            let mut changed = false;
+            if self.translate_toolchains {
+                if let Some(original) = toolchain::translate(&mut args) {
+                    command.original_compiler.get_or_insert(original);
+                    changed = true;
+                }
+            }
+            if let Some(original) = self.compiler_map.apply(&mut args) {
+                command.original_compiler.get_or_insert(original);
+                changed = true;
+            }
+            if self.resolve_compilers {
+                if let Some(original) = compiler::resolve_in(&mut args, &command.directory) {
+                    command.original_compiler.get_or_insert(original);
+                    changed = true;
+                }
+            }
+            changed |= self.arg_edits.apply(&mut args);
+            for injection in &self.injections {
+                changed |= injection.apply(&mut args);
+            }
+            if let Some(builtin_includes) = &self.builtin_includes {
+                changed |= builtin_includes.apply(&mut args, &command.file, &command.directory);
+            }
+            if let Some(mode) = self.pch {
+                changed |= pch::apply(&mut args, mode, &command.directory);
+            }
+            if self.absolute_includes {
+                changed |= paths::absolutize_includes(&mut args, &command.directory);
+            }
+            if changed {
+                command.command = join_args(&args);
+            }
+        }
+        map_paths(nix, &mut command);
+        map_paths(&self.path_map, &mut command);
+        if let Some(style) = self.path_style {
+            command.directory = paths::translate(&command.directory, style);
+            command.command = paths::translate(&command.command, style);
+            command.file = paths::translate(&command.file, style);
+            command.output = command.output.map(|output| paths::translate(&output, style));
+        }
+        if let Some(root) = &self.rebase_directory {
+            let directory = PathBuf::from(&command.directory);
+            let mut args = split_args(&command.command);
+            if paths::rebase_args(&mut args, &command.directory, root) {
+                command.command = join_args(&args);
+            }
+            command.file = paths::rebase_path(&command.file, &directory, root).unwrap_or(command.file);
+            command.output = command
+                .output
+                .map(|output| paths::rebase_path(&output, &directory, root).unwrap_or(output));
+            command.directory = root.display().to_string();
+        }
+        for transform in &self.custom {
+            command = transform(command);
+        }
+        if let Some(script) = &self.script {
+            match script.transform(&command) {
+                Ok(Some(transformed)) => command = transformed,
+                Ok(None) => return None,
+                Err(e) => warn!(
+                    "Warning: Script {} failed on {}: {}. Keeping the entry.",
+                    script.path(),
+                    command.file,
+                    e
+                ),
+            }
+        }
+        for plugin in &self.plugins {
+            match plugin.transform(&command) {
+                Ok(Some(transformed)) => command = transformed,
+                Ok(None) => return None,
+                Err(e) => warn!(
+                    "Warning: Plugin {} failed on {}: {}. Keeping the entry.",
+                    plugin.path(),
+                    command.file,
+                    e
+                ),
+            }
+        }
+        Some(command)
+    }
+
+    /// Whether any rewrite works on the command's individual arguments
+    fn edits_args(&self) -> bool {
+        self.translate_toolchains
+            || !self.injections.is_empty()
+            || !self.arg_edits.is_empty()
+            || !self.compiler_map.is_empty()
+            || self.resolve_compilers
+            || self.absolute_includes
+            || self.pch.is_some()
+            || self.builtin_includes.is_some()
+    }
+
+    /// Apply all configured rewrites to a database's entries, then stream them through the filter program
+    pub fn apply_all(&self, commands: Vec<CompileCommand>) -> Vec<CompileCommand> {
+        let nix = self.nix.as_ref().map(NixProfiles::map).unwrap_or_default();
+        let commands: Vec<CompileCommand> = commands.into_iter().filter_map(|command| self.apply(command, &nix)).collect();
+        let Some(pipe) = &self.pipe else {
+            return commands;
+        };
+        match pipe.run(&commands) {
+            Ok(filtered) => filtered,
+            Err(e) => {
+                warn!("Warning: Filter '{}' failed: {:#}. Keeping the entries unfiltered.", pipe.command(), e);
+                commands
+            }
+        }
+    }
+
+    /// Derive per-source entries from a unity build entry, if expansion is enabled
+    pub fn expand(&self, command: &CompileCommand) -> Option<Vec<CompileCommand>> {
+        if self.expand_unity {
+            unity::expand(command)
+        } else {
+            None
+        }
+    }
+}
+
+/// Rewrite the paths of an entry with a prefix mapping
+fn map_paths(map: &PathMap, command: &mut CompileCommand) {
+    if map.is_empty() {
+        return;
+    }
+    command.directory = map.apply(&command.directory);
+    command.command = map.apply(&command.command);
+    command.file = map.apply(&command.file);
+    command.output = command.output.as_deref().map(|output| map.apply(output));
+}
+
+/// Remove arguments starting with any of the given prefixes, leaving the command untouched if none match
+fn strip_flags(command: &str, prefixes: &[&str]) -> String {
+    let args = split_args(command);
+    let kept: Vec<&String> = args
+        .iter()
+        .filter(|arg| !prefixes.iter().any(|prefix| arg.starts_with(prefix)))
+        .collect();
+    if kept.len() == args.len() {
+        command.to_string()
+    } else {
+        join_args(&kept)
+    }
+}
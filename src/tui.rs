@@ -0,0 +1,135 @@
+use crate::log::{self, Level};
+use crate::CombinedState;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Duration;
+
+/// Number of recent events and errors kept for display
+const MAX_LINES: usize = 200;
+
+/// Live status view of the watcher, owning the terminal while it runs
+pub struct Dashboard {
+    terminal: DefaultTerminal,
+    sources: Vec<String>,
+    events: VecDeque<String>,
+    errors: VecDeque<String>,
+}
+
+impl Dashboard {
+    /// Take over the terminal, routing console messages into the dashboard
+    pub fn start(sources: Vec<String>) -> Self {
+        log::start_capture();
+        Dashboard {
+            terminal: ratatui::init(),
+            sources,
+            events: VecDeque::new(),
+            errors: VecDeque::new(),
+        }
+    }
+
+    /// Redraw with the latest state, returning `false` once the user asked to quit
+    pub fn update(&mut self, state: &CombinedState) -> std::io::Result<bool> {
+        for (level, message) in log::drain() {
+            let lines = if level == Level::Info { &mut self.events } else { &mut self.errors };
+            lines.push_back(message);
+            if lines.len() > MAX_LINES {
+                lines.pop_front();
+            }
+        }
+
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        let entries: Vec<_> = state.merged_entries().collect();
+        for entry in &entries {
+            *counts.entry(entry.source.as_str()).or_default() += 1;
+        }
+        let total = entries.len();
+        let sources = &self.sources;
+        let events = &self.events;
+        let errors = &self.errors;
+        self.terminal.draw(|frame| {
+            draw(frame, state, total, sources, &counts, events, errors);
+        })?;
+
+        while event::poll(Duration::ZERO)? {
+            if let Event::Key(key) = event::read()? {
+                let ctrl_c = key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                if key.kind == KeyEventKind::Press && (ctrl_c || matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)) {
+                    return Ok(false);
+                }
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        ratatui::restore();
+        for (level, message) in log::stop_capture() {
+            log::emit(level, message);
+        }
+    }
+}
+
+fn draw(
+    frame: &mut Frame,
+    state: &CombinedState,
+    total: usize,
+    sources: &[String],
+    counts: &BTreeMap<&str, usize>,
+    events: &VecDeque<String>,
+    errors: &VecDeque<String>,
+) {
+    let [header, body] = Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(frame.area());
+    let [left, right] = Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(body);
+    let [watched, inputs] = Layout::vertical([Constraint::Length(sources.len() as u16 + 2), Constraint::Min(0)]).areas(left);
+    let [recent, conflicts, failures] = Layout::vertical([
+        Constraint::Percentage(50),
+        Constraint::Percentage(25),
+        Constraint::Percentage(25),
+    ])
+    .areas(right);
+
+    let mut title = format!(" {} — {} entries", state.output_file, total);
+    if let Some(staleness) = state.staleness.as_ref().filter(|staleness| staleness.count() > 0) {
+        title.push_str(&format!(" — {} stale", staleness.count()));
+    }
+    if let Some(config) = &state.active_config {
+        title.push_str(&format!(" — config: {}", config));
+    }
+    title.push_str(" — press q to quit");
+    frame.render_widget(Paragraph::new(title).bold().reversed(), header);
+
+    frame.render_widget(
+        List::new(sources.iter().map(|source| ListItem::new(source.as_str())))
+            .block(Block::bordered().title(" Watched ")),
+        watched,
+    );
+    frame.render_widget(
+        List::new(counts.iter().map(|(source, count)| ListItem::new(format!("{:>6}  {}", count, source))))
+            .block(Block::bordered().title(" Entries per input ")),
+        inputs,
+    );
+    render_tail(frame, recent, " Recent events ", events.iter(), Style::default());
+    render_tail(frame, conflicts, " Recent conflicts ", state.conflicts.iter(), Style::default().fg(Color::Yellow));
+    render_tail(frame, failures, " Errors ", errors.iter(), Style::default().fg(Color::Red));
+}
+
+/// Render the last lines that fit in `area`
+fn render_tail<'a>(
+    frame: &mut Frame,
+    area: Rect,
+    title: &str,
+    lines: impl DoubleEndedIterator<Item = &'a String> + ExactSizeIterator,
+    style: Style,
+) {
+    let visible = area.height.saturating_sub(2) as usize;
+    let skip = lines.len().saturating_sub(visible);
+    let lines: Vec<Line> = lines.skip(skip).map(|line| Line::styled(line.as_str(), style)).collect();
+    frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(title)), area);
+}
@@ -0,0 +1,76 @@
+use crate::paths::normalize;
+use crate::shell::{join_args, split_args};
+use crate::CompileCommand;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Source extensions that unity files include as translation units
+const SOURCE_EXTENSIONS: &[&str] = &["c", "cc", "cpp", "cxx", "c++", "m", "mm"];
+
+/// Whether a file name looks like a generated unity (jumbo) source from CMake or Meson
+fn is_unity_file(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    let in_cmake_unity_dir = path
+        .parent()
+        .and_then(Path::file_name)
+        .is_some_and(|dir| dir == "Unity");
+    (in_cmake_unity_dir && name.starts_with("unity_")) || name.contains("-unity")
+}
+
+/// Expand a unity entry into one entry per included source, derived from its command.
+/// Returns `None` for entries that aren't unity builds.
+pub fn expand(command: &CompileCommand) -> Option<Vec<CompileCommand>> {
+    let directory = Path::new(&command.directory);
+    let unity_path = normalize(&directory.join(&command.file));
+    if !is_unity_file(&unity_path) {
+        return None;
+    }
+    let content = fs::read_to_string(&unity_path).ok()?;
+    let members = included_sources(&content, unity_path.parent()?);
+    if members.is_empty() {
+        return None;
+    }
+
+    let args = split_args(&command.command);
+    let position = args
+        .iter()
+        .position(|arg| normalize(&directory.join(arg)) == unity_path)?;
+    Some(
+        members
+            .into_iter()
+            .map(|member| {
+                let member = member.display().to_string();
+                let mut member_args = args.clone();
+                member_args[position] = member.clone();
+                CompileCommand {
+                    directory: command.directory.clone(),
+                    command: join_args(&member_args),
+                    file: member,
+                    output: None,
+                    extra: command.extra.clone(),
+                    original_compiler: command.original_compiler.clone(),
+                }
+            })
+            .collect(),
+    )
+}
+
+/// Collect the sources pulled in by `#include` lines of a unity file
+fn included_sources(content: &str, base: &Path) -> Vec<PathBuf> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let rest = line.trim().strip_prefix('#')?.trim_start().strip_prefix("include")?.trim();
+            let target = rest
+                .strip_prefix('"')
+                .and_then(|r| r.split_once('"'))
+                .or_else(|| rest.strip_prefix('<').and_then(|r| r.split_once('>')))?
+                .0;
+            let path = normalize(&base.join(target));
+            let extension = path.extension()?.to_str()?;
+            SOURCE_EXTENSIONS.contains(&extension).then_some(path)
+        })
+        .collect()
+}
@@ -0,0 +1,77 @@
+use crate::settings;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// Options whose value may follow as a separate argument, joined to it for comparison
+const SEPARATE_VALUES: &[&str] = &["-D", "-U", "-I", "-isystem", "-iquote", "-idirafter"];
+
+/// Include directory options, each a search list of its own
+const INCLUDE_OPTIONS: &[&str] = &["-isystem", "-iquote", "-idirafter", "-I"];
+
+/// Options passing arguments through to another tool, which look like warnings but aren't
+const PASS_THROUGH: &[&str] = &["-Wl,", "-Wa,", "-Wp,"];
+
+/// Flags besides macros whose order an entry's comparison ignores
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UnorderedFlags {
+    /// Include directories (-I, -isystem, -iquote, -idirafter), whose order only matters where several hold a header of the same name
+    Includes,
+    /// Warning options (-W..., -w), whose order only matters where one overrides another
+    Warnings,
+}
+
+/// Arguments in a canonical order for telling whether an entry changed: those whose order
+/// doesn't matter sorted after the others, by kind. Macros defined or undefined once are always
+/// among them, as generators that collect them in hash sets emit them in any order; the flags of
+/// --unordered-flags are too.
+pub fn canonical(args: Vec<String>) -> Vec<String> {
+    let mut joined = Vec::with_capacity(args.len());
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match SEPARATE_VALUES.contains(&arg.as_str()) {
+            true => joined.push(args.next().map_or_else(|| arg.clone(), |value| format!("{}{}", arg, value))),
+            false => joined.push(arg),
+        }
+    }
+    let mut macros: HashMap<&str, usize> = HashMap::new();
+    for name in joined.iter().filter_map(|arg| macro_name(arg)) {
+        *macros.entry(name).or_default() += 1;
+    }
+    let settings = settings::current();
+    let policy = &settings.unordered_flags;
+    let mut ordered = Vec::with_capacity(joined.len());
+    let mut unordered: BTreeMap<&str, Vec<&String>> = BTreeMap::new();
+    for (index, arg) in joined.iter().enumerate() {
+        let kind = if index == 0 {
+            None // The compiler
+        } else if macro_name(arg).is_some_and(|name| macros[name] == 1) {
+            Some("-D")
+        } else if policy.contains(&UnorderedFlags::Includes) {
+            INCLUDE_OPTIONS.iter().copied().find(|option| arg.starts_with(option))
+        } else {
+            None
+        };
+        let kind = kind.or_else(|| (policy.contains(&UnorderedFlags::Warnings) && is_warning(arg)).then_some("-W"));
+        match kind {
+            Some(kind) => unordered.entry(kind).or_default().push(arg),
+            None => ordered.push(arg.clone()),
+        }
+    }
+    for (_, mut args) in unordered {
+        args.sort();
+        ordered.extend(args.into_iter().cloned());
+    }
+    ordered
+}
+
+/// The macro a -D or -U argument defines or undefines
+fn macro_name(arg: &str) -> Option<&str> {
+    let definition = arg.strip_prefix("-D").or_else(|| arg.strip_prefix("-U"))?;
+    Some(definition.split_once('=').map_or(definition, |(name, _)| name))
+}
+
+fn is_warning(arg: &str) -> bool {
+    (arg.starts_with("-W") && !PASS_THROUGH.iter().any(|option| arg.starts_with(option))) || arg == "-w"
+}
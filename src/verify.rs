@@ -0,0 +1,55 @@
+use crate::{entry_hash, CompileCommand};
+use std::collections::BTreeSet;
+
+/// How many of the drifted files are named when reporting
+const REPORTED_FILES: usize = 5;
+
+/// Entries by file and the hash of what they compile, as the output has them
+pub type Snapshot = BTreeSet<(String, u64)>;
+
+/// Snapshot of the given entries
+pub fn snapshot<'a>(commands: impl IntoIterator<Item = &'a CompileCommand>) -> Snapshot {
+    commands.into_iter().map(|command| (command.file.clone(), entry_hash(command))).collect()
+}
+
+/// Differences of the incrementally maintained entries from those of a merge from scratch
+pub struct Drift {
+    stale: Vec<String>,   // Files whose entry the fresh merge doesn't have, e.g. after a missed deletion
+    missing: Vec<String>, // Files whose entry only the fresh merge has, e.g. after a missed modification
+}
+
+impl Drift {
+    pub fn between(incremental: &Snapshot, fresh: &Snapshot) -> Self {
+        let files = |entries: std::collections::btree_set::Difference<'_, (String, u64)>| {
+            let mut files: Vec<String> = entries.map(|(file, _)| file.clone()).collect();
+            files.dedup();
+            files
+        };
+        Drift {
+            stale: files(incremental.difference(fresh)),
+            missing: files(fresh.difference(incremental)),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stale.is_empty() && self.missing.is_empty()
+    }
+
+    /// Log the drift found, or that there was none
+    pub fn report(&self, entries: usize) {
+        if self.is_empty() {
+            info!("Verified: the {} merged entries match a merge from scratch", entries);
+            return;
+        }
+        let changed: BTreeSet<&String> = self.stale.iter().filter(|file| self.missing.contains(file)).collect();
+        let mut files: Vec<&String> = self.stale.iter().chain(&self.missing).collect::<BTreeSet<_>>().into_iter().collect();
+        files.truncate(REPORTED_FILES);
+        warn!(
+            "Warning: Merged entries drifted from a merge from scratch: {} outdated, {} left over, {} missing (e.g. {}). Using the fresh merge.",
+            changed.len(),
+            self.stale.len() - changed.len(),
+            self.missing.len() - changed.len(),
+            files.iter().map(|file| file.as_str()).collect::<Vec<_>>().join(", ")
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Settings of folders, next to the folder
+const FOLDER_SETTINGS: &str = ".vscode/settings.json";
+
+/// What a VS Code workspace says to merge: its folders, the build directories its settings point
+/// at outside them, and the databases they name
+#[derive(Default, PartialEq, Eq)]
+pub struct Workspace {
+    pub directories: Vec<PathBuf>,
+    pub files: Vec<PathBuf>,
+}
+
+/// Read a `.code-workspace` file, a folder's `.vscode/settings.json`, or a folder holding one
+pub fn read(path: &Path) -> Result<Workspace> {
+    let mut workspace = Workspace::default();
+    if path.is_dir() || path.ends_with(FOLDER_SETTINGS) {
+        let folder = if path.is_dir() { path.to_path_buf() } else { folder_of_settings(path) };
+        workspace.add_folder(&folder, None)?;
+        return Ok(workspace);
+    }
+    let root = parse(path)?;
+    let base = path.parent().unwrap_or(Path::new("."));
+    let folders: Vec<PathBuf> = root
+        .get("folders")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|folder| match (folder.get("path"), folder.get("uri")) {
+            (Some(Value::String(folder)), _) => Some(base.join(folder)),
+            (_, Some(Value::String(uri))) => uri.strip_prefix("file://").map(PathBuf::from), // Remote folders are skipped
+            _ => None,
+        })
+        .map(|folder| crate::paths::normalize(&std::path::absolute(&folder).unwrap_or(folder)))
+        .collect();
+    for folder in &folders {
+        workspace.directories.push(folder.clone());
+    }
+    for folder in &folders {
+        workspace.add_folder(folder, root.get("settings"))?;
+    }
+    Ok(workspace)
+}
+
+impl Workspace {
+    /// Add a folder's pointers to build directories and databases, from its own settings and
+    /// those of the workspace, which its own override
+    fn add_folder(&mut self, folder: &Path, workspace_settings: Option<&Value>) -> Result<()> {
+        if !self.directories.iter().any(|dir| dir == folder) {
+            self.directories.push(folder.to_path_buf());
+        }
+        let settings_path = folder.join(FOLDER_SETTINGS);
+        let own = if settings_path.is_file() { Some(parse(&settings_path)?) } else { None };
+        let setting = |key: &str| own.as_ref().and_then(|own| own.get(key)).or_else(|| workspace_settings?.get(key)).cloned();
+        if let Some(dir) = setting("cmake.buildDirectory").as_ref().and_then(Value::as_str).and_then(|dir| expand(dir, folder)) {
+            let inside = self.directories.iter().any(|watched| dir.starts_with(watched));
+            if !inside {
+                self.directories.push(dir);
+            }
+        }
+        let databases = match setting("C_Cpp.default.compileCommands") {
+            Some(Value::String(database)) => vec![database],
+            Some(Value::Array(databases)) => databases.into_iter().filter_map(|database| database.as_str().map(str::to_string)).collect(),
+            _ => Vec::new(),
+        };
+        for database in databases.iter().filter_map(|database| expand(database, folder)) {
+            if !self.files.contains(&database) {
+                self.files.push(database);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The folder a `.vscode/settings.json` belongs to
+fn folder_of_settings(path: &Path) -> PathBuf {
+    let folder = path.parent().and_then(Path::parent).unwrap_or(Path::new("."));
+    let folder = if folder.as_os_str().is_empty() { Path::new(".") } else { folder };
+    crate::paths::normalize(&std::path::absolute(folder).unwrap_or_else(|_| folder.to_path_buf()))
+}
+
+/// Resolve a setting's path against its folder, expanding the variables VS Code would; a path
+/// with variables only an extension knows (e.g. CMake Tools' `${buildType}`) stands for the
+/// directory before the first of them
+fn expand(setting: &str, folder: &Path) -> Option<PathBuf> {
+    let mut expanded = String::new();
+    let mut rest = setting;
+    while let Some(start) = rest.find("${") {
+        expanded.push_str(&rest[..start]);
+        let end = rest[start..].find('}')? + start;
+        let variable = &rest[start + 2..end];
+        let value = match variable {
+            "workspaceFolder" | "workspaceRoot" => Some(folder.display().to_string()),
+            "workspaceFolderBasename" => Some(folder.file_name()?.to_string_lossy().into_owned()),
+            "userHome" => std::env::var("HOME").or_else(|_| std::env::var("USERPROFILE")).ok(),
+            _ => variable.strip_prefix("env:").map(|name| std::env::var(name).unwrap_or_default()),
+        };
+        match value {
+            Some(value) => expanded.push_str(&value),
+            None => {
+                // Up to the last separator before the unknown variable
+                let cut = expanded.rfind(['/', '\\']).unwrap_or(0);
+                expanded.truncate(cut);
+                rest = "";
+                break;
+            }
+        }
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+    if expanded.is_empty() {
+        return None;
+    }
+    let path = folder.join(expanded);
+    Some(crate::paths::normalize(&path))
+}
+
+/// Parse a JSON file with the comments and trailing commas VS Code allows
+fn parse(path: &Path) -> Result<Value> {
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&strip_jsonc(&text)).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Turn JSON with comments into JSON, blanking comments and dropping commas before `]` or `}`
+fn strip_jsonc(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            match c {
+                '\\' => out.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                out.push(c);
+            }
+            ('/', Some('/')) => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+                out.push(' ');
+            }
+            (']' | '}', _) => {
+                let trimmed = out.trim_end().len();
+                if out[..trimmed].ends_with(',') {
+                    out.truncate(trimmed - 1);
+                }
+                out.push(c);
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
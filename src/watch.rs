@@ -0,0 +1,889 @@
+use crate::events::{Change, RenameTracker};
+use crate::limits;
+use crate::merger::MergeEvent;
+use crate::porcelain;
+use crate::stale;
+use crate::remote::{self, RemoteSource};
+use crate::replay::{self, Replay};
+use crate::systemd::Systemd;
+use crate::tui::Dashboard;
+use crate::vscode;
+use crate::{archive, cmake, compressed, is_same_file, overrides, platformio, read_database, CombinedState, CompileCommand};
+use anyhow::Result;
+use notify::{Config, Event, EventHandler, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+use serde_json::json;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, oneshot, Notify};
+use tokio::task::JoinSet;
+use tokio::time::{self, Instant, Interval};
+
+/// Interval between dashboard redraws
+const REDRAW_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Sources checked for existence before pruning pauses, keeping the load on the filesystem low
+const PRUNE_BATCH: usize = 500;
+
+/// Pause between batches of existence checks
+const PRUNE_PAUSE: Duration = Duration::from_millis(100);
+
+/// Changes kept for subscribers that fall behind, after which they miss some
+const CHANGES_CAPACITY: usize = 4096;
+
+/// Longest interval between checks for entries past --entry-ttl
+const EXPIRY_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How the watcher runs once the initial merge is written
+pub struct WatchOptions {
+    pub directories: Vec<String>,
+    pub poll_interval: Duration, // Also the interval of the polling fallback
+    pub debounce: Duration,
+    pub rescan_interval: Option<Duration>,
+    pub verify_interval: Option<Duration>, // How often the merged entries are checked against a merge from scratch
+    pub min_write_interval: Duration, // Changes arriving sooner after a write wait for the next one
+    pub prune_interval: Option<Duration>, // How often entries of deleted sources are looked for
+    pub watch_depth: Option<usize>, // Watch directories individually down to this depth, rather than recursively
+    pub replay: Option<Replay>, // Events applied instead of watching
+    pub control_file: Option<PathBuf>, // Written by `use-config`
+    pub code_workspace: Option<(PathBuf, Vec<String>)>, // A --code-workspace file, and the roots it gave
+    #[cfg(feature = "grpc")]
+    pub grpc: Option<std::net::SocketAddr>, // Where the control and query API is served
+    pub events: Option<std::net::SocketAddr>, // Where entry changes are streamed as server-sent events
+    pub tui: bool,
+    pub systemd: Option<Systemd>, // Pinged from the event loop when systemd runs a watchdog
+}
+
+/// State shared by the event loop, the remote poller and the writer
+pub type SharedState = Arc<Mutex<CombinedState>>;
+
+/// Requests to the event loop to change what it watches, answered with the change in the
+/// number of merged entries or why nothing changed
+#[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+pub enum Control {
+    AddRoot(PathBuf, oneshot::Sender<Result<usize, String>>),
+    RemoveRoot(PathBuf, oneshot::Sender<Result<usize, String>>),
+}
+
+/// Start monitoring for compile_commands.json changes, until the dashboard is closed
+pub fn start_watching(state: CombinedState, remotes: Vec<RemoteSource>, mut options: WatchOptions) {
+    let settings = state.settings.clone();
+    let _settings = settings.enter();
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    builder.enable_time().on_thread_start(move || settings.install());
+    #[cfg(feature = "grpc")]
+    builder.enable_io(); // For the gRPC server's sockets
+    let runtime = builder.build().expect("Failed to start async runtime");
+    let state = Arc::new(Mutex::new(state));
+    match options.replay.take() {
+        Some(replay) => runtime.block_on(replay_events(state, replay, &options)),
+        None => runtime.block_on(run(state, remotes, options)),
+    }
+    runtime.shutdown_background(); // Don't wait for a poll or write that is still in flight
+}
+
+async fn run(state: SharedState, remotes: Vec<RemoteSource>, mut options: WatchOptions) {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let Some((mut watcher, _poller)) = register_watches(&state, &options, &tx) else {
+        return;
+    };
+
+    // All writes go through one task, so a burst of requests collapses into a single write
+    let writes = Arc::new(Notify::new());
+    tokio::spawn(write_output(state.clone(), writes.clone(), options.min_write_interval));
+    let sources = options
+        .directories
+        .iter()
+        .cloned()
+        .chain(remotes.iter().map(|remote| remote.name().to_string()))
+        .collect();
+    if let Some(period) = options.prune_interval {
+        tokio::spawn(prune_missing(state.clone(), period, writes.clone()));
+    }
+    let entry_ttl = state.lock().unwrap_or_else(|e| e.into_inner()).entry_ttl;
+    if let Some(ttl) = entry_ttl {
+        tokio::spawn(expire_entries(state.clone(), ttl, writes.clone()));
+    }
+    if !remotes.is_empty() {
+        tokio::spawn(poll_remotes(state.clone(), remotes, options.poll_interval, writes.clone()));
+    }
+
+    if let Some(addr) = options.events {
+        if let Err(e) = crate::sse::serve(addr, subscribe_changes(&state)) {
+            error!("Error: {:#}", e);
+        }
+    }
+    #[cfg_attr(not(feature = "grpc"), allow(unused_mut))]
+    let mut control: Option<mpsc::UnboundedReceiver<Control>> = None;
+    #[cfg(feature = "grpc")]
+    if let Some(addr) = options.grpc {
+        let (requests, received) = mpsc::unbounded_channel();
+        tokio::spawn(crate::grpc::serve(addr, state.clone(), requests, subscribe_changes(&state)));
+        control = Some(received);
+    }
+
+    let mut dashboard = options.tui.then(|| Dashboard::start(sources));
+    let mut redraw = time::interval(REDRAW_INTERVAL);
+    let mut rescan = options
+        .rescan_interval
+        .map(|period| time::interval_at(Instant::now() + period, period));
+    let mut verification = options
+        .verify_interval
+        .map(|period| time::interval_at(Instant::now() + period, period));
+    let mut watchdog = options
+        .systemd
+        .as_ref()
+        .and_then(Systemd::watchdog_interval)
+        .map(time::interval);
+    let mut source_watches = SourceWatches::default();
+    if source_watches.update(&mut watcher, &state, &options) {
+        writes.notify_one();
+    }
+    let mut renames = RenameTracker::default();
+    let mut pending: Vec<Change> = Vec::new();
+    let mut deadline: Option<Instant> = None; // When the pending changes are merged
+
+    loop {
+        tokio::select! {
+            Some(event) = rx.recv() => match event {
+                Ok(event) => {
+                    let changes = renames.changes(event);
+                    if let Some(depth) = options.watch_depth {
+                        watch_new_directories(&mut watcher, &changes, &options.directories, depth);
+                    }
+                    pending.extend(changes);
+                    if !pending.is_empty() {
+                        deadline.get_or_insert_with(|| Instant::now() + options.debounce);
+                    }
+                }
+                Err(e) => error!("Notify error: {:?}", e),
+            },
+            _ = time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                deadline = None;
+                let changes = std::mem::take(&mut pending);
+                let workspace = options.code_workspace.as_ref().map(|(path, _)| path.as_path());
+                if workspace.is_some_and(|workspace| changes.iter().any(|change| change.touches(workspace)))
+                    && reload_workspace(&state, &mut watcher, &mut options).await
+                {
+                    writes.notify_one();
+                }
+                if apply_changes(&state, changes, &options).await | source_watches.update(&mut watcher, &state, &options) {
+                    writes.notify_one();
+                }
+                if restore_output(&state, &mut watcher, &options) {
+                    writes.notify_one();
+                }
+            },
+            _ = tick(&mut rescan) => {
+                if rescan_all(&state, &options.directories).await | source_watches.update(&mut watcher, &state, &options) {
+                    writes.notify_one();
+                }
+            },
+            _ = tick(&mut verification) => {
+                if verify_all(&state, &options.directories).await | source_watches.update(&mut watcher, &state, &options) {
+                    writes.notify_one();
+                }
+            },
+            Some(request) = next_control(&mut control) => {
+                if handle_control(&state, &mut watcher, &mut options, request).await {
+                    writes.notify_one();
+                }
+            },
+            _ = tick(&mut watchdog) => {
+                if let Some(systemd) = &options.systemd {
+                    systemd.ping();
+                }
+            },
+            _ = redraw.tick(), if dashboard.is_some() => {
+                let state = state.lock().unwrap_or_else(|e| e.into_inner());
+                match dashboard.as_mut().map(|dashboard| dashboard.update(&state)) {
+                    Some(Ok(false)) => break,
+                    Some(Err(e)) => {
+                        dashboard = None; // Restore the terminal and keep watching with log output
+                        error!("Error: Dashboard failed: {}", e);
+                    }
+                    _ => {}
+                }
+            },
+        }
+    }
+}
+
+/// Apply recorded events in the batches the debouncing would have made, moving the clock to when
+/// each would have been applied and writing after each; only --entry-ttl of the timers runs
+async fn replay_events(state: SharedState, replay: Replay, options: &WatchOptions) {
+    let events = replay.events.len();
+    let batches = replay::batches(replay.events, options.debounce);
+    let count = batches.len();
+    for (at, changes) in batches {
+        replay.clock.set(replay::REPLAY_START + at);
+        let mut changed = apply_changes(&state, changes, options).await;
+        let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(ttl) = state.entry_ttl {
+            let (removed, databases) = state.expire_entries(ttl);
+            if removed > 0 {
+                info!("Dropped {} entries of {} databases not refreshed within the TTL", removed, databases);
+                changed = true;
+            }
+        }
+        if changed {
+            state.update_output();
+        }
+    }
+    info!("Replayed {} events in {} batches", events, count);
+}
+
+/// The channel of entry changes published by each write, opened by the first subscriber
+fn subscribe_changes(state: &SharedState) -> broadcast::Sender<MergeEvent> {
+    let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+    state.changes.get_or_insert_with(|| broadcast::channel(CHANGES_CAPACITY).0).clone()
+}
+
+/// The polling watcher of an interval, created the first time a directory is polled at it
+fn poller<H: EventHandler>(pollers: &mut Vec<(Duration, PollWatcher)>, interval: Duration, handler: impl FnOnce() -> H) -> Option<&mut PollWatcher> {
+    let index = match pollers.iter().position(|(every, _)| *every == interval) {
+        Some(index) => index,
+        None => match PollWatcher::new(handler(), Config::default().with_poll_interval(interval)) {
+            Ok(poller) => {
+                pollers.push((interval, poller));
+                pollers.len() - 1
+            }
+            Err(e) => {
+                error!("Error: Failed to create polling watcher: {}", e);
+                return None;
+            }
+        },
+    };
+    Some(&mut pollers[index].1)
+}
+
+/// Watch the directories for database changes, returning the watchers that must be kept alive
+fn register_watches(
+    state: &SharedState,
+    options: &WatchOptions,
+    tx: &mpsc::UnboundedSender<notify::Result<Event>>,
+) -> Option<(RecommendedWatcher, Vec<PollWatcher>)> {
+    let handler = |tx: mpsc::UnboundedSender<_>| move |event| {
+        let _ = tx.send(event); // Only fails once the event loop is gone
+    };
+    let mut watcher = match RecommendedWatcher::new(handler(tx.clone()), Config::default()) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            error!("Error: Failed to create watcher: {}", e);
+            return None;
+        }
+    };
+    // By interval: the fallback for roots that native watching can't cover, and roots that poll
+    let mut pollers: Vec<(Duration, PollWatcher)> = Vec::new();
+    let mut limit_reached = false;
+
+    // Watch directories for compile_commands.json files
+    for dir in &options.directories {
+        let root = Path::new(dir);
+        if !root.exists() {
+            warn!("Warning: Directory '{}' does not exist. Skipping.", dir);
+            continue;
+        }
+        if archive::is_archive(root) {
+            continue; // Watched with the other files below
+        }
+        let root_poll = {
+            let state = state.lock().unwrap_or_else(|e| e.into_inner());
+            crate::roots::containing(&state.roots, root).and_then(|root| root.poll_interval())
+        };
+        if let Some(interval) = root_poll {
+            let poller = poller(&mut pollers, interval, || handler(tx.clone()))?;
+            match poller.watch(root, RecursiveMode::Recursive) {
+                Ok(()) => info!("Polling directory every {}s: {}", interval.as_secs(), dir),
+                Err(e) => error!("Error: Failed to poll directory '{}': {}", dir, e),
+            }
+            continue;
+        }
+        if let Some(depth) = options.watch_depth {
+            let watched = watch_to_depth(&mut watcher, root, depth);
+            info!("Watching {} directories up to depth {} under: {}", watched, depth, dir);
+            continue;
+        }
+        if !limit_reached {
+            match watcher.watch(root, RecursiveMode::Recursive) {
+                Ok(()) => {
+                    info!("Watching directory: {}", dir);
+                    continue;
+                }
+                Err(e) if limits::is_watch_limit(&e) => {
+                    limit_reached = true;
+                    let _ = watcher.unwatch(root); // Release the watches registered before failing
+                    let limit = limits::max_user_watches()
+                        .map_or_else(|| "unknown".to_string(), |limit| limit.to_string());
+                    warn!(
+                        "Warning: Watch limit reached for '{}': it needs {} watches, the limit is {} (fs.inotify.max_user_watches). Falling back to targeted watching.",
+                        dir,
+                        limits::count_directories(root),
+                        limit
+                    );
+                }
+                Err(e) => {
+                    error!("Error: Failed to watch directory '{}': {}", dir, e);
+                    continue;
+                }
+            }
+        }
+
+        // Targeted watching: only the directories holding databases, falling back to polling
+        let databases = state.lock().unwrap_or_else(|e| e.into_inner()).discover(root);
+        let targeted = databases.iter().all(|database| {
+            let parent = database.parent().unwrap_or(root);
+            watcher.watch(parent, RecursiveMode::NonRecursive).is_ok()
+        });
+        if targeted {
+            info!("Watching {} database directories under: {}", databases.len(), dir);
+            continue;
+        }
+        let poller = poller(&mut pollers, options.poll_interval, || handler(tx.clone()))?;
+        match poller.watch(root, RecursiveMode::Recursive) {
+            Ok(()) => info!("Polling directory every {}s: {}", options.poll_interval.as_secs(), dir),
+            Err(e) => error!("Error: Failed to poll directory '{}': {}", dir, e),
+        }
+    }
+
+    // Watch the directories of files given by path, which are usually replaced rather than
+    // modified, unless a watched directory covers them
+    let (files, output) = {
+        let state = state.lock().unwrap_or_else(|e| e.into_inner());
+        (state.files.clone(), PathBuf::from(&state.output_file))
+    };
+    let mut file_dirs: Vec<PathBuf> = Vec::new();
+    for file in &files {
+        match std::path::absolute(file).ok().as_deref().and_then(Path::parent) {
+            Some(dir) if is_covered(dir, &options.directories) => info!("Watching file: {}", file.display()),
+            Some(dir) if file_dirs.iter().any(|watched| watched == dir) => info!("Watching file: {}", file.display()),
+            Some(dir) => match watcher.watch(dir, RecursiveMode::NonRecursive) {
+                Ok(()) => {
+                    info!("Watching file: {}", file.display());
+                    file_dirs.push(dir.to_path_buf());
+                }
+                Err(e) => error!("Error: Failed to watch file '{}': {}", file.display(), e),
+            },
+            None => {}
+        }
+    }
+
+    // Watch the output for modifications and deletions by other programs
+    if let Some(output_dir) = std::path::absolute(&output).ok().as_deref().and_then(Path::parent) {
+        if !is_covered(output_dir, &options.directories) && !file_dirs.iter().any(|dir| dir == output_dir) {
+            if let Err(e) = watcher.watch(output_dir, RecursiveMode::NonRecursive) {
+                warn!("Warning: Cannot watch the output for external modifications: {}", e);
+            }
+        }
+    }
+
+    // Watch the --code-workspace file for roots added to or removed from it
+    if let Some(workspace_dir) = options
+        .code_workspace
+        .as_ref()
+        .and_then(|(path, _)| std::path::absolute(path).ok())
+        .and_then(|path| path.parent().map(Path::to_path_buf))
+    {
+        if !is_covered(&workspace_dir, &options.directories) {
+            if let Err(e) = watcher.watch(&workspace_dir, RecursiveMode::NonRecursive) {
+                warn!("Warning: Cannot watch the workspace for changes: {}", e);
+            }
+        }
+    }
+
+    // Watch the directory of the active-configuration control file for `use-config` switches
+    if let Some(control_file) = &options.control_file {
+        let control_dir = match control_file.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        if let Err(e) = watcher.watch(control_dir, RecursiveMode::NonRecursive) {
+            warn!("Warning: Cannot watch for configuration switches: {}", e);
+        }
+    }
+    Some((watcher, pollers.into_iter().map(|(_, poller)| poller).collect()))
+}
+
+/// The directories of entries' sources watched for --watch-sources, and the sources checked
+#[derive(Default)]
+struct SourceWatches {
+    directories: HashSet<PathBuf>,
+    sources: HashSet<PathBuf>, // As `stale::key`s
+}
+
+impl SourceWatches {
+    /// Watch the directories of sources merged since the last call unless the watched directories
+    /// cover them, checking whether those sources already changed after their databases; returns
+    /// whether any became stale
+    fn update(&mut self, watcher: &mut RecommendedWatcher, state: &SharedState, options: &WatchOptions) -> bool {
+        let state = state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.staleness.is_none() {
+            return false;
+        }
+        let mut added = HashSet::new();
+        for (source, _) in state.sources() {
+            let source = stale::key(&source);
+            if self.sources.contains(&source) {
+                continue;
+            }
+            if let Some(dir) = source.parent().filter(|dir| !self.directories.contains(*dir)) {
+                let covered = options.watch_depth.is_none() && is_covered(dir, &options.directories);
+                if !covered && dir.is_dir() {
+                    if let Err(e) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+                        warn!("Warning: Failed to watch sources in '{}': {}", dir.display(), e);
+                    }
+                }
+                self.directories.insert(dir.to_path_buf());
+            }
+            self.sources.insert(source.clone());
+            added.insert(source);
+        }
+        state.sources_changed(&added)
+    }
+}
+
+/// Watch a directory and those below it down to `depth` levels individually, returning how many
+/// were watched
+fn watch_to_depth(watcher: &mut RecommendedWatcher, dir: &Path, depth: usize) -> usize {
+    walkdir::WalkDir::new(dir)
+        .max_depth(depth)
+        .into_iter()
+        .flatten()
+        .filter(|entry| entry.file_type().is_dir())
+        .filter(|entry| match watcher.watch(entry.path(), RecursiveMode::NonRecursive) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("Warning: Failed to watch directory '{}': {}", entry.path().display(), e);
+                false
+            }
+        })
+        .count()
+}
+
+/// Watch directories created or moved in within the depth limit of a watched directory
+fn watch_new_directories(watcher: &mut RecommendedWatcher, changes: &[Change], directories: &[String], depth: usize) {
+    for change in changes {
+        let (Change::Modified(path) | Change::MovedIn(path)) = change else {
+            continue;
+        };
+        if !path.is_dir() {
+            continue;
+        }
+        let Ok(absolute) = std::path::absolute(path) else {
+            continue;
+        };
+        let level = directories
+            .iter()
+            .filter_map(|root| std::path::absolute(root).ok())
+            .filter_map(|root| absolute.strip_prefix(root).ok().map(|relative| relative.components().count()))
+            .min();
+        if let Some(level) = level.filter(|level| *level <= depth) {
+            watch_to_depth(watcher, path, depth - level);
+        }
+    }
+}
+
+/// Whether a changed file is a database given by path, or one found in a watched directory
+fn is_database(state: &CombinedState, path: &Path, directories: &[String]) -> bool {
+    let scanned = compressed::is_database(path, state.database_name(path))
+        && platformio::is_selected(path)
+        && std::path::absolute(path).is_ok_and(|path| path.parent().is_some_and(|dir| is_covered(dir, directories)));
+    scanned || state.files.iter().any(|file| is_same_file(path, file))
+}
+
+/// Whether a directory lies under one of the recursively watched directories
+fn is_covered(dir: &Path, directories: &[String]) -> bool {
+    directories
+        .iter()
+        .filter(|watched| !archive::is_archive(Path::new(watched)))
+        .any(|watched| std::path::absolute(watched).is_ok_and(|watched| dir.starts_with(watched)))
+}
+
+/// Wait for the next control request, forever if there is no control channel
+async fn next_control(control: &mut Option<mpsc::UnboundedReceiver<Control>>) -> Option<Control> {
+    match control {
+        Some(control) => control.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Add or remove a watched root, returning whether the output needs rewriting
+async fn handle_control(
+    state: &SharedState,
+    watcher: &mut RecommendedWatcher,
+    options: &mut WatchOptions,
+    request: Control,
+) -> bool {
+    match request {
+        Control::AddRoot(dir, reply) => {
+            let (added, changed) = add_root(state, watcher, options, &dir).await;
+            let _ = reply.send(added);
+            changed
+        }
+        Control::RemoveRoot(dir, reply) => {
+            let removed = remove_root(state, watcher, options, &dir);
+            let changed = removed.as_ref().is_ok_and(|&removed| removed > 0);
+            let _ = reply.send(removed);
+            changed
+        }
+    }
+}
+
+/// Watch and merge another root, returning the number of entries it added, and whether the
+/// output needs rewriting
+async fn add_root(
+    state: &SharedState,
+    watcher: &mut RecommendedWatcher,
+    options: &mut WatchOptions,
+    dir: &Path,
+) -> (Result<usize, String>, bool) {
+    let merged = |state: &SharedState| state.lock().unwrap_or_else(|e| e.into_inner()).merged_entries().count();
+    let name = dir.display().to_string();
+    if options.directories.iter().any(|watched| std::path::absolute(watched).is_ok_and(|watched| watched == dir)) {
+        return (Err(format!("{} is already watched", name)), false);
+    }
+    if let Err(e) = watcher.watch(dir, RecursiveMode::Recursive) {
+        return (Err(format!("Failed to watch {}: {}", name, e)), false);
+    }
+    info!("Watching directory: {}", name);
+    options.directories.push(name);
+    let before = merged(state);
+    let root = dir.to_path_buf();
+    let databases = with_state(state, move |state| state.discover(&root)).await;
+    let changed = ingest(state, databases).await;
+    (Ok(merged(state).saturating_sub(before)), changed)
+}
+
+/// Stop watching a root and drop its entries, returning how many were dropped
+fn remove_root(
+    state: &SharedState,
+    watcher: &mut RecommendedWatcher,
+    options: &mut WatchOptions,
+    dir: &Path,
+) -> Result<usize, String> {
+    let Some(index) = options.directories.iter().position(|watched| {
+        std::path::absolute(watched).is_ok_and(|watched| watched == dir)
+    }) else {
+        return Err(format!("{} is not watched", dir.display()));
+    };
+    options.directories.remove(index);
+    let _ = watcher.unwatch(dir);
+    let removed = state.lock().unwrap_or_else(|e| e.into_inner()).remove_entries_under(dir);
+    info!("Stopped watching directory: {} ({} entries removed)", dir.display(), removed);
+    Ok(removed)
+}
+
+/// Read the --code-workspace file again after it changed, watching the roots it added and no
+/// longer those it dropped; returns whether the output needs rewriting
+async fn reload_workspace(state: &SharedState, watcher: &mut RecommendedWatcher, options: &mut WatchOptions) -> bool {
+    let Some((path, roots)) = options.code_workspace.clone() else {
+        return false;
+    };
+    let workspace = match vscode::read(&path) {
+        Ok(workspace) => workspace,
+        Err(e) => {
+            warn!("Warning: {:#}. Keeping the workspace's roots as they are.", e);
+            return false;
+        }
+    };
+    info!("Workspace changed: {}", path.display());
+    let current: Vec<String> = workspace.directories.iter().map(|dir| dir.display().to_string()).collect();
+    let mut changed = false;
+    for dir in roots.iter().filter(|dir| !current.contains(dir)) {
+        changed |= remove_root(state, watcher, options, Path::new(dir)).is_ok_and(|removed| removed > 0);
+    }
+    for dir in current.iter().filter(|dir| !roots.contains(dir)) {
+        let (added, root_changed) = add_root(state, watcher, options, Path::new(dir)).await;
+        if let Err(e) = added {
+            warn!("Warning: {}", e);
+        }
+        changed |= root_changed;
+    }
+    options.code_workspace = Some((path, current));
+    changed
+}
+
+/// Wait for the next tick of an optional interval, forever if there is none
+async fn tick(interval: &mut Option<Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Apply a batch of debounced changes, returning whether the output needs rewriting
+async fn apply_changes(state: &SharedState, changes: Vec<Change>, options: &WatchOptions) -> bool {
+    let control_file = options.control_file.clone();
+    let directories = options.directories.clone();
+    let (mut changed, mut databases) = with_state(state, move |state| {
+        let control_file = control_file.as_deref();
+        let mut changed = false;
+        let mut databases: Vec<PathBuf> = Vec::new();
+        let mut edited = HashSet::new(); // Files that may be sources of entries, with --watch-sources
+        for change in changes {
+            if let Some(interpolation) = &state.interpolation {
+                interpolation.forget(change.path());
+            }
+            match change {
+                Change::MovedOut(path) => {
+                    let removed = state.remove_entries_under(&path);
+                    if removed > 0 {
+                        info!("Moved away: {} ({} entries removed)", path.display(), removed);
+                        porcelain::event("pruned", json!({ "reason": "moved-away", "path": path, "entries": removed }));
+                        changed = true;
+                    }
+                }
+                Change::Deleted(path) if !path.exists() => {
+                    let removed = state.remove_entries_under(&path);
+                    if removed > 0 {
+                        info!("Deleted: {} ({} entries removed)", path.display(), removed);
+                        porcelain::event("pruned", json!({ "reason": "deleted", "path": path, "entries": removed }));
+                        changed = true;
+                    }
+                }
+                Change::Deleted(_) => {} // Written again since, which its own event reports
+                Change::MovedIn(path) if path.is_dir() => {
+                    info!("Directory moved in: {}", path.display());
+                    databases.extend(state.discover(&path));
+                }
+                Change::Modified(path) | Change::MovedIn(path) => {
+                    if control_file.is_some_and(|control| is_same_file(&path, control)) {
+                        let name = fs::read_to_string(&path).unwrap_or_default();
+                        changed |= state.set_active_config(name.trim());
+                    } else if is_same_file(&path, Path::new(&state.output_file)) {
+                        changed |= state.output_modified_externally(); // Reconciled by the next write
+                    } else if path.file_name().is_some_and(|name| name == overrides::OVERRIDE_FILE) {
+                        databases.extend(state.reload_overrides(&path, &directories));
+                    } else if path.file_name().is_some_and(|name| name == cmake::CACHE_FILE) {
+                        let (removed, database) = state.cmake_reconfigured(&path);
+                        changed |= removed;
+                        databases.extend(database);
+                    } else if !databases.contains(&path) && is_database(state, &path, &directories) {
+                        info!("Change detected in: {}", path.display());
+                        databases.push(path);
+                    } else if state.staleness.is_some() {
+                        edited.insert(stale::key(&path));
+                    }
+                }
+            }
+        }
+        changed |= state.sources_changed(&edited);
+        (changed, databases)
+    })
+    .await;
+    let mut seen = std::collections::HashSet::new();
+    databases.retain(|path| seen.insert(path.clone()));
+    if databases.len() > 1 {
+        info!("Merging {} changed databases in one write", databases.len());
+    }
+    changed |= ingest(state, databases).await;
+    changed
+}
+
+/// Recreate the directory of the output if something deleted it (e.g. `git clean`), watching
+/// that again if it was watched on its own, returning whether the output must be written again
+fn restore_output(state: &SharedState, watcher: &mut RecommendedWatcher, options: &WatchOptions) -> bool {
+    let output = PathBuf::from(&state.lock().unwrap_or_else(|e| e.into_inner()).output_file);
+    if output.exists() {
+        return false;
+    }
+    info!("Output {} was deleted, writing it again", output.display());
+    let output_dir = std::path::absolute(&output).ok().and_then(|output| output.parent().map(Path::to_path_buf));
+    if let Some(output_dir) = output_dir.filter(|dir| !dir.exists()) {
+        if let Err(e) = fs::create_dir_all(&output_dir) {
+            error!("Error: Failed to create {}: {}", output_dir.display(), e);
+            return false;
+        }
+        if !is_covered(&output_dir, &options.directories) {
+            if let Err(e) = watcher.watch(&output_dir, RecursiveMode::NonRecursive) {
+                warn!("Warning: Cannot watch the output for external modifications: {}", e);
+            }
+        }
+    }
+    true
+}
+
+/// Re-read every database under the watched directories
+async fn rescan_all(state: &SharedState, directories: &[String]) -> bool {
+    let databases = all_databases(state, directories).await;
+    info!("Rescanning {} databases", databases.len());
+    ingest(state, databases).await
+}
+
+/// Merge every database from scratch, off the state, and heal the merged entries if they drifted
+/// from that, e.g. through missed events; returns whether they did
+async fn verify_all(state: &SharedState, directories: &[String]) -> bool {
+    let databases = all_databases(state, directories).await;
+    info!("Verifying the merged entries against {} databases", databases.len());
+    let parsed = parse_all(state, databases).await;
+    let directories = directories.to_vec();
+    with_state(state, move |state| {
+        for (path, result, elapsed) in &parsed {
+            state.record_parse(path, *elapsed, result);
+        }
+        let parsed = parsed.into_iter().map(|(path, result, _)| (path, result)).collect();
+        state.verify(parsed, &directories)
+    })
+    .await
+}
+
+/// The databases under the watched directories, and those watched directly
+async fn all_databases(state: &SharedState, directories: &[String]) -> Vec<PathBuf> {
+    let directories = directories.to_vec();
+    with_state(state, move |state| {
+        for dir in &directories {
+            state.overrides.discover(Path::new(dir));
+        }
+        directories
+            .iter()
+            .flat_map(|dir| state.discover(Path::new(dir)))
+            .chain(state.files.iter().cloned())
+            .collect()
+    })
+    .await
+}
+
+/// Parse databases concurrently, then add their entries in the order they were given, returning
+/// whether any database's entries changed
+async fn ingest(state: &SharedState, databases: Vec<PathBuf>) -> bool {
+    if !databases.is_empty() {
+        porcelain::event("merge-started", json!({ "databases": databases }));
+    }
+    let parsed = parse_all(state, databases).await;
+    with_state(state, move |state| {
+        let mut changed = false;
+        for (path, result, elapsed) in parsed {
+            state.record_parse(&path, elapsed, &result);
+            changed |= state.add_entries_from_file(&path, result);
+        }
+        changed
+    })
+    .await
+}
+
+/// Work on the state on a blocking thread, as merging runs transforms (subprocesses of
+/// --pipe-through, plugins, compiler probes) that would stall the runtime's other tasks
+async fn with_state<R: Send + 'static>(state: &SharedState, work: impl FnOnce(&mut CombinedState) -> R + Send + 'static) -> R {
+    let state = state.clone();
+    let working = tokio::task::spawn_blocking(move || work(&mut state.lock().unwrap_or_else(|e| e.into_inner())));
+    match working.await {
+        Ok(result) => result,
+        Err(e) => std::panic::resume_unwind(e.into_panic()), // Blocking tasks are only cancelled by a shutdown
+    }
+}
+
+/// Parse databases concurrently, without holding the state, returning them in the order given
+async fn parse_all(state: &SharedState, databases: Vec<PathBuf>) -> Vec<(PathBuf, Result<Vec<CompileCommand>>, Duration)> {
+    let input_file = state.lock().unwrap_or_else(|e| e.into_inner()).input_file.clone();
+    let mut parsing = JoinSet::new();
+    for (index, path) in databases.into_iter().enumerate() {
+        let input_file = input_file.clone();
+        parsing.spawn_blocking(move || {
+            let started = std::time::Instant::now();
+            let parsed = read_database(&path, &input_file);
+            (index, path, parsed, started.elapsed())
+        });
+    }
+    let mut parsed: Vec<_> = parsing.join_all().await;
+    parsed.sort_by_key(|(index, _, _, _)| *index); // Keep same-priority overrides deterministic
+    parsed.into_iter().map(|(_, path, result, elapsed)| (path, result, elapsed)).collect()
+}
+
+/// Poll remote sources on a timer, starting right away, without holding the state while
+/// waiting on the network
+async fn poll_remotes(state: SharedState, mut remotes: Vec<RemoteSource>, period: Duration, writes: Arc<Notify>) {
+    let mut interval = time::interval(period);
+    loop {
+        interval.tick().await;
+        let polling = tokio::task::spawn_blocking(move || {
+            let polled = remote::poll_all(&mut remotes);
+            (remotes, polled)
+        });
+        let Ok((returned, polled)) = polling.await else {
+            error!("Error: Remote polling stopped unexpectedly");
+            return;
+        };
+        remotes = returned;
+        if state.lock().unwrap_or_else(|e| e.into_inner()).add_remote_updates(polled) {
+            writes.notify_one();
+        }
+    }
+}
+
+/// Periodically drop entries whose source file was deleted, checking the sources without
+/// holding the state
+async fn prune_missing(state: SharedState, period: Duration, writes: Arc<Notify>) {
+    let mut interval = time::interval_at(Instant::now() + period, period);
+    loop {
+        interval.tick().await;
+        let sources = state.lock().unwrap_or_else(|e| e.into_inner()).sources();
+        let checking = tokio::task::spawn_blocking(move || {
+            let mut missing = HashSet::new();
+            for (checked, (source, directory)) in sources.into_iter().enumerate() {
+                if checked > 0 && checked % PRUNE_BATCH == 0 {
+                    std::thread::sleep(PRUNE_PAUSE);
+                }
+                // Entries whose directory is missing too describe another machine's tree (remote
+                // sources, translated paths) rather than deleted files
+                if !source.exists() && directory.is_dir() {
+                    missing.insert(source);
+                }
+            }
+            missing
+        });
+        let Ok(missing) = checking.await else {
+            error!("Error: Pruning stopped unexpectedly");
+            return;
+        };
+        if missing.is_empty() {
+            continue;
+        }
+        let removed = state.lock().unwrap_or_else(|e| e.into_inner()).remove_sources(&missing);
+        if removed > 0 {
+            info!("Pruned {} entries of deleted source files", removed);
+            porcelain::event("pruned", json!({ "reason": "deleted-sources", "entries": removed }));
+            writes.notify_one();
+        }
+    }
+}
+
+/// Periodically drop entries of databases that were not refreshed within the TTL
+async fn expire_entries(state: SharedState, ttl: Duration, writes: Arc<Notify>) {
+    let period = ttl.clamp(Duration::from_secs(1), EXPIRY_INTERVAL);
+    let mut interval = time::interval_at(Instant::now() + period, period);
+    loop {
+        interval.tick().await;
+        let (removed, databases) = state.lock().unwrap_or_else(|e| e.into_inner()).expire_entries(ttl);
+        if removed > 0 {
+            info!("Dropped {} entries of {} databases not refreshed within the TTL", removed, databases);
+            porcelain::event("pruned", json!({ "reason": "expired", "entries": removed, "databases": databases }));
+            writes.notify_one();
+        }
+    }
+}
+
+/// Write the output whenever asked to, one write at a time and at most once per `min_interval`
+async fn write_output(state: SharedState, writes: Arc<Notify>, min_interval: Duration) {
+    let mut last_write: Option<Instant> = None;
+    loop {
+        writes.notified().await;
+        if let Some(last_write) = last_write {
+            // Requests made while waiting collapse into this write; one arriving after it flushes them
+            time::sleep_until(last_write + min_interval).await;
+            let _ = time::timeout(Duration::ZERO, writes.notified()).await; // Consume a request made while waiting
+        }
+        let state = state.clone();
+        let writing = tokio::task::spawn_blocking(move || {
+            state.lock().unwrap_or_else(|e| e.into_inner()).update_output();
+        });
+        if writing.await.is_err() {
+            error!("Error: Output writer stopped unexpectedly");
+            return;
+        }
+        last_write = Some(Instant::now());
+    }
+}
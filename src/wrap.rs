@@ -0,0 +1,75 @@
+use crate::compiler::source_and_output;
+use crate::shell::join_args;
+use crate::{write_atomically, CompileCommand};
+use anyhow::{bail, Result};
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Environment variable naming the spool directory, for builds that can't pass --spool
+pub const SPOOL_VAR: &str = "CCM_SPOOL";
+
+/// Name of the fragment databases, found by the watcher like any other database
+const FRAGMENT_FILE: &str = "compile_commands.json";
+
+/// Run a compiler invocation, recording it as a fragment in the spool directory; returns the
+/// compiler's exit code. Recording never fails the build, it only warns.
+pub fn run(spool: Option<&Path>, args: &[String]) -> i32 {
+    let Some(compiler) = args.first() else {
+        error!("Error: No compiler to run");
+        return 2;
+    };
+    match spool.map(Path::to_path_buf).or_else(|| std::env::var_os(SPOOL_VAR).map(PathBuf::from)) {
+        Some(spool) => {
+            let recorded = std::env::current_dir().map_err(Into::into).and_then(|dir| record(&spool, args, &dir));
+            if let Err(e) = recorded {
+                warn!("Warning: Failed to record the invocation in {}: {}", spool.display(), e);
+            }
+        }
+        None => warn!("Warning: Not recording the invocation, set --spool or {}", SPOOL_VAR),
+    }
+    match Command::new(compiler).args(&args[1..]).status() {
+        Ok(status) => status.code().unwrap_or(1),
+        Err(e) => {
+            error!("Error: Failed to run {}: {}", compiler, e);
+            127
+        }
+    }
+}
+
+/// Write the entry of an invocation that compiles a source to its fragment, replacing the one of
+/// an earlier compilation of the same source and object; returns whether it compiles a source
+pub fn record(spool: &Path, args: &[String], directory: &Path) -> Result<bool> {
+    if !args.iter().any(|arg| arg == "-c" || arg == "/c") {
+        return Ok(false); // Linking, preprocessing or probing the compiler
+    }
+    let (Some(file), output) = source_and_output(args) else {
+        return Ok(false);
+    };
+    let directory = directory.display().to_string();
+    let mut hasher = DefaultHasher::new();
+    (&directory, &file, &output).hash(&mut hasher);
+    let fragment = spool.join(format!("{:016x}", hasher.finish()));
+    let command = CompileCommand {
+        directory,
+        command: join_args(args),
+        file,
+        output,
+        extra: Default::default(),
+        original_compiler: None,
+    };
+    fs::create_dir_all(&fragment)?;
+    write_atomically(&fragment.join(FRAGMENT_FILE), &serde_json::to_string_pretty(&[command])?)?;
+    Ok(true)
+}
+
+/// Create the spool directory so a watcher can follow it before the first compilation
+pub fn prepare(spool: &Path) -> Result<()> {
+    if spool.exists() && !spool.is_dir() {
+        bail!("Spool '{}' is not a directory", spool.display());
+    }
+    fs::create_dir_all(spool)?;
+    Ok(())
+}